@@ -0,0 +1,45 @@
+/// What a clip does once it runs past its last frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlaybackMode {
+    Loop,
+    /// Stops on the last frame.
+    Once,
+    /// Plays forwards then backwards, forever.
+    PingPong
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AnimationFrame {
+    pub sprite_id: i32,
+    /// Seconds
+    pub duration: f32
+}
+
+/// Named marker on a frame of a clip, e.g. "footstep".
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationEvent {
+    pub clip: String,
+    pub frame: usize,
+    pub name: String
+}
+
+/// A named sequence of atlas frames.
+#[derive(Clone, Debug)]
+pub struct AnimationClip {
+    pub name: String,
+    pub frames: Vec<AnimationFrame>,
+    pub mode: PlaybackMode,
+    /// Atlas the frames' sprite ids refer to.
+    pub atlas: usize
+}
+
+impl AnimationClip {
+    pub fn new(name: &str, frames: Vec<AnimationFrame>, mode: PlaybackMode) -> AnimationClip {
+        AnimationClip { name: name.to_owned(), frames, mode, atlas: 0 }
+    }
+
+    /// Total duration of one pass through the frames, in seconds.
+    pub fn get_length(&self) -> f32 {
+        self.frames.iter().map(|f| f.duration).sum()
+    }
+}
@@ -0,0 +1,67 @@
+use std::{cell::Cell, rc::Rc, time::Instant};
+
+use super::graphics::Graphics;
+
+/// Time source for the game loop.
+pub trait Clock {
+    /// Seconds elapsed since some fixed starting point.
+    fn get_time(&self) -> f32;
+}
+
+/// Reads the GLFW timer. Only valid once `Graphics::init_gl` has succeeded.
+pub struct GlfwClock;
+
+impl Clock for GlfwClock {
+    fn get_time(&self) -> f32 {
+        Graphics::get_glfw_time()
+    }
+}
+
+/// Wall-clock time that doesn't depend on GLFW.
+pub struct SystemClock {
+    start: Instant
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn get_time(&self) -> f32 {
+        self.start.elapsed().as_secs_f32()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time, so keep one around after handing it to the engine.
+#[derive(Clone, Default)]
+pub struct ManualClock {
+    time: Rc<Cell<f32>>
+}
+
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    pub fn advance(&self, delta_time: f32) {
+        self.time.set(self.time.get() + delta_time);
+    }
+
+    pub fn set_time(&self, time: f32) {
+        self.time.set(time);
+    }
+}
+
+impl Clock for ManualClock {
+    fn get_time(&self) -> f32 {
+        self.time.get()
+    }
+}
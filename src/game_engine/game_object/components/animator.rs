@@ -0,0 +1,437 @@
+use std::{rc::Rc, cell::RefCell};
+
+use crate::game_engine::{Engine, game_object::GameObject};
+use crate::game_engine::animation::{AnimationClip, AnimationEvent, PlaybackMode};
+use crate::game_engine::err::EngineError;
+use crate::game_engine::graphics::Graphics;
+
+use super::{Component, SpriteComponent};
+
+type EventCallback = Box<dyn FnMut(&AnimationEvent, &mut Engine, &Rc<RefCell<GameObject>>)>;
+
+/// Plays animation clips on the owner's `SpriteComponent` by changing its sprite id.
+/// Clips from every loaded atlas are picked up on awake and whenever another atlas is loaded, alongside any added with
+/// `add_clip`. Clips added with `add_clip` win over atlas clips with the same name.
+pub struct Animator {
+    clips: Vec<AnimationClip>,
+    /// How many of the engine's atlases the clips have been copied from
+    atlases_seen: usize,
+    current: Option<String>,
+    frame: usize,
+    frame_time: f32,
+    /// 1 or -1, only changes for ping-pong clips
+    direction: i32,
+    playing: bool,
+    finished: bool,
+    started: bool,
+    /// Playback rate. 1.0 is normal speed.
+    pub speed: f32,
+    events: Vec<AnimationEvent>,
+    fired: Vec<AnimationEvent>,
+    callbacks: Vec<EventCallback>
+}
+
+impl Animator {
+    pub fn new() -> Animator {
+        Animator {
+            clips: Vec::new(),
+            atlases_seen: 0,
+            current: None,
+            frame: 0,
+            frame_time: 0.0,
+            direction: 1,
+            playing: false,
+            finished: false,
+            started: false,
+            speed: 1.0,
+            events: Vec::new(),
+            fired: Vec::new(),
+            callbacks: Vec::new()
+        }
+    }
+
+    /// An animator that already knows the clips of every atlas loaded into `gfx`, so `play` can pick one before the
+    /// animator is added to an object.
+    pub fn with_atlas_clips(gfx: &Graphics) -> Animator {
+        let mut animator = Animator::new();
+        animator.pull_atlas_clips(gfx);
+
+        animator
+    }
+
+    /// Copies clips from atlases added since the last call.
+    fn pull_atlas_clips(&mut self, gfx: &Graphics) {
+        let atlases = gfx.get_atlases();
+
+        for atlas in &atlases[self.atlases_seen.min(atlases.len())..] {
+            for clip in atlas.get_clips() {
+                if self.get_clip(&clip.name).is_none() {
+                    self.clips.push(clip.clone());
+                }
+            }
+        }
+
+        self.atlases_seen = atlases.len();
+    }
+
+    /// Adds a clip, replacing any with the same name.
+    pub fn add_clip(&mut self, clip: AnimationClip) {
+        self.clips.retain(|c| c.name != clip.name);
+        self.clips.push(clip);
+    }
+
+    pub fn get_clip(&self, name: &str) -> Option<&AnimationClip> {
+        self.clips.iter().find(|c| c.name == name)
+    }
+
+    /// Switches to `clip`. Does nothing if it is already playing, so this can be called every frame from a state machine.
+    /// Fails and keeps the current clip if the animator doesn't know `clip`.
+    pub fn play(&mut self, clip: &str) -> Result<(), EngineError> {
+        if self.playing && self.current.as_deref() == Some(clip) {
+            return Ok(());
+        }
+
+        self.restart(clip)
+    }
+
+    /// Plays `clip` from its first frame, even if it was already playing.
+    pub fn restart(&mut self, clip: &str) -> Result<(), EngineError> {
+        if self.get_clip(clip).is_none() {
+            return Err(format!("Animation clip \"{}\" not found!", clip).into());
+        }
+
+        self.current = Some(clip.to_owned());
+        self.frame = 0;
+        self.frame_time = 0.0;
+        self.direction = 1;
+        self.playing = true;
+        self.finished = false;
+        self.started = true;
+
+        Ok(())
+    }
+
+    /// Freezes on the current frame.
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn resume(&mut self) {
+        if self.current.is_some() && !self.finished {
+            self.playing = true;
+        }
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    /// Whether a `PlaybackMode::Once` clip has reached its last frame.
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    pub fn get_current_clip(&self) -> Option<&str> {
+        self.current.as_deref()
+    }
+
+    pub fn get_frame(&self) -> usize {
+        self.frame
+    }
+
+    /// Fires an event named `name` whenever `frame` of `clip` is reached.
+    pub fn add_event(&mut self, clip: &str, frame: usize, name: &str) {
+        self.events.push(AnimationEvent { clip: clip.to_owned(), frame, name: name.to_owned() });
+    }
+
+    /// Calls `callback` for every event fired, right after the frame is reached.
+    pub fn on_event<F: FnMut(&AnimationEvent, &mut Engine, &Rc<RefCell<GameObject>>) + 'static>(&mut self, callback: F) {
+        self.callbacks.push(Box::new(callback));
+    }
+
+    /// Events fired during the last update.
+    pub fn get_fired_events(&self) -> &[AnimationEvent] {
+        &self.fired
+    }
+
+    /// Moves `frame_time` forward by `delta`, returning every frame that was entered on the way.
+    fn advance(&mut self, clip: &AnimationClip, delta: f32) -> Vec<usize> {
+        let mut entered = Vec::new();
+
+        if !self.playing || clip.frames.is_empty() || clip.get_length() <= 0.0 {
+            return entered;
+        }
+
+        let count = clip.frames.len();
+        self.frame = self.frame.min(count - 1);
+        self.frame_time += delta * self.speed;
+
+        while self.frame_time >= clip.frames[self.frame].duration {
+            self.frame_time -= clip.frames[self.frame].duration;
+
+            match clip.mode {
+                PlaybackMode::Loop => self.frame = (self.frame + 1) % count,
+                PlaybackMode::Once => {
+                    if self.frame + 1 >= count {
+                        self.frame_time = 0.0;
+                        self.playing = false;
+                        self.finished = true;
+                        break;
+                    }
+
+                    self.frame += 1;
+                },
+                PlaybackMode::PingPong => {
+                    if count == 1 {
+                        continue;
+                    }
+
+                    let next = self.frame as i32 + self.direction;
+                    if next < 0 || next >= count as i32 {
+                        self.direction = -self.direction;
+                    }
+
+                    self.frame = (self.frame as i32 + self.direction) as usize;
+                }
+            }
+
+            entered.push(self.frame);
+        }
+
+        entered
+    }
+}
+
+impl Default for Animator {
+    fn default() -> Self {
+        Animator::new()
+    }
+}
+
+impl Component for Animator {
+    fn awake(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {
+        self.pull_atlas_clips(_engine.get_gfx());
+    }
+
+    fn update(&mut self, _info: super::TickInfo, _owner: Rc<RefCell<GameObject>>) {
+        self.fired.clear();
+
+        if _info.engine.get_gfx().get_atlases().len() != self.atlases_seen {
+            self.pull_atlas_clips(_info.engine.get_gfx());
+        }
+
+        // play only accepts known clips, and clips are never taken away
+        let clip = match self.current.as_ref().and_then(|name| self.get_clip(name)) {
+            Some(clip) => clip.clone(),
+            None => return
+        };
+        let name = clip.name.clone();
+
+        let mut entered = Vec::new();
+        if self.started {
+            entered.push(self.frame);
+            self.started = false;
+        }
+
+        entered.append(&mut self.advance(&clip, _info.delta_time));
+
+        for frame in entered {
+            let events = self.events.iter().filter(|e| e.clip == name && e.frame == frame);
+            self.fired.extend(events.cloned());
+        }
+
+        for event in &self.fired {
+            for callback in self.callbacks.iter_mut() {
+                callback(event, _info.engine, &_owner);
+            }
+        }
+
+        if let Some(frame) = clip.frames.get(self.frame) {
+            // The sprite pushes this to Graphics in render, after every update, so it shows up this frame whatever the component order
+            if let Some(sprite) = _owner.borrow().get_component::<SpriteComponent>() {
+                let mut sprite = sprite.borrow_mut();
+
+                sprite.sprite.sprite_id = frame.sprite_id;
+                sprite.sprite.atlas = clip.atlas;
+            }
+        }
+    }
+
+    fn fixed_update(&mut self, _info: super::TickInfo, _owner: Rc<RefCell<GameObject>>) {}
+
+    fn render(&mut self, _info: super::TickInfo, _owner: Rc<RefCell<GameObject>>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use image::RgbaImage;
+
+    use super::*;
+    use crate::game_engine::animation::AnimationFrame;
+    use crate::game_engine::graphics::atlas::{SpriteAtlas, SpriteFrame};
+    use crate::game_engine::Vector2;
+
+    /// Frames 0.25 seconds long, with sprite ids 1 to `frames`.
+    fn clip(name: &str, frames: i32, mode: PlaybackMode) -> AnimationClip {
+        AnimationClip::new(name, (1..=frames).map(|sprite_id| AnimationFrame { sprite_id, duration: 0.25 }).collect(), mode)
+    }
+
+    fn playing(clip: &AnimationClip) -> Animator {
+        let mut animator = Animator::new();
+        animator.add_clip(clip.clone());
+        animator.play(&clip.name).unwrap();
+
+        animator
+    }
+
+    #[test]
+    fn loop_wraps_around() {
+        let walk = clip("walk", 3, PlaybackMode::Loop);
+        let mut animator = playing(&walk);
+
+        assert_eq!(animator.advance(&walk, 0.8), [1, 2, 0]);
+        assert_eq!(animator.get_frame(), 0);
+        assert!(animator.is_playing());
+        assert_eq!(animator.advance(&walk, 0.2), [1]);
+    }
+
+    #[test]
+    fn once_stops_on_the_last_frame() {
+        let attack = clip("attack", 3, PlaybackMode::Once);
+        let mut animator = playing(&attack);
+
+        assert_eq!(animator.advance(&attack, 10.0), [1, 2]);
+        assert_eq!(animator.get_frame(), 2);
+        assert!(animator.is_finished());
+        assert!(!animator.is_playing());
+
+        // Finished clips stay put, and resume doesn't restart them
+        animator.resume();
+        assert!(animator.advance(&attack, 1.0).is_empty());
+
+        // Playing the same clip again starts it over, since it isn't playing anymore
+        animator.play("attack").unwrap();
+        assert_eq!(animator.get_frame(), 0);
+        assert!(!animator.is_finished());
+    }
+
+    #[test]
+    fn ping_pong_bounces_off_both_ends() {
+        let idle = clip("idle", 3, PlaybackMode::PingPong);
+        let mut animator = playing(&idle);
+
+        assert_eq!(animator.advance(&idle, 1.5), [1, 2, 1, 0, 1, 2]);
+
+        let still = clip("still", 1, PlaybackMode::PingPong);
+        let mut animator = playing(&still);
+        assert!(animator.advance(&still, 1.0).is_empty());
+    }
+
+    #[test]
+    fn speed_and_pause() {
+        let walk = clip("walk", 4, PlaybackMode::Loop);
+        let mut animator = playing(&walk);
+
+        animator.speed = 2.0;
+        assert_eq!(animator.advance(&walk, 0.25), [1, 2]);
+
+        animator.speed = 0.5;
+        assert_eq!(animator.advance(&walk, 0.25), []);
+        assert_eq!(animator.advance(&walk, 0.25), [3]);
+
+        animator.pause();
+        assert!(animator.advance(&walk, 1.0).is_empty());
+        animator.resume();
+        assert_eq!(animator.advance(&walk, 0.5), [0]);
+    }
+
+    #[test]
+    fn unknown_clips_are_rejected_by_play() {
+        let walk = clip("walk", 2, PlaybackMode::Loop);
+        let mut animator = playing(&walk);
+        animator.advance(&walk, 0.25);
+
+        assert!(animator.play("wlak").is_err());
+        assert!(animator.restart("wlak").is_err());
+        assert_eq!(animator.get_current_clip(), Some("walk"));
+        assert_eq!(animator.get_frame(), 1);
+
+        // Already playing, so nothing changes
+        animator.play("walk").unwrap();
+        assert_eq!(animator.get_frame(), 1);
+        animator.restart("walk").unwrap();
+        assert_eq!(animator.get_frame(), 0);
+    }
+
+    #[test]
+    fn events_fire_on_their_frames() {
+        let mut engine = Engine::init_headless();
+        let obj = GameObject::create_empty("obj".to_owned(), Some(engine.get_root_object()));
+
+        let mut animator = Animator::new();
+        animator.add_clip(clip("walk", 4, PlaybackMode::Loop));
+        animator.add_event("walk", 0, "start");
+        animator.add_event("walk", 2, "step");
+        animator.add_event("run", 2, "not this clip");
+        animator.play("walk").unwrap();
+
+        let steps = Rc::new(Cell::new(0));
+        let counter = steps.clone();
+        animator.on_event(move |event, _, _| {
+            if event.name == "step" {
+                counter.set(counter.get() + 1);
+            }
+        });
+
+        obj.borrow_mut().add_component(SpriteComponent::new(0));
+        obj.borrow_mut().add_component(animator);
+        let animator = obj.borrow().get_component::<Animator>().unwrap();
+        let sprite = obj.borrow().get_component::<SpriteComponent>().unwrap();
+
+        // The first frame is entered as soon as the clip starts
+        engine.step(0.0);
+        let names: Vec<String> = animator.borrow().get_fired_events().iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, ["start"]);
+        assert_eq!(sprite.borrow().sprite.sprite_id, 1);
+
+        engine.step(0.5);
+        let names: Vec<String> = animator.borrow().get_fired_events().iter().map(|e| e.name.clone()).collect();
+        assert_eq!(names, ["step"]);
+        assert_eq!(sprite.borrow().sprite.sprite_id, 3);
+
+        // Twice round the clip in one frame fires both times
+        engine.step(2.0);
+        assert_eq!(animator.borrow().get_fired_events().len(), 4);
+        assert_eq!(steps.get(), 3);
+    }
+
+    #[test]
+    fn clips_from_atlases_loaded_later_are_picked_up() {
+        let mut engine = Engine::init_headless();
+        let obj = GameObject::create_empty("obj".to_owned(), Some(engine.get_root_object()));
+        obj.borrow_mut().add_component(SpriteComponent::new(0));
+        obj.borrow_mut().add_component(Animator::new());
+        engine.step(0.0);
+
+        let animator = obj.borrow().get_component::<Animator>().unwrap();
+        assert!(animator.borrow_mut().play("blink").is_err());
+
+        let frames = (0..2).map(|i| SpriteFrame { name: format!("eye_{}", i), rect: (i * 2, 0, 2, 2), pivot: Vector2::new(0.5, 0.5) }).collect();
+        let mut atlas = SpriteAtlas::new(RgbaImage::new(4, 2), frames).unwrap();
+        atlas.add_clip(clip("blink", 2, PlaybackMode::Loop));
+        let atlas = engine.get_gfx_mut().add_atlas(atlas);
+
+        engine.step(0.0);
+        animator.borrow_mut().play("blink").unwrap();
+        engine.step(0.25);
+
+        let sprite = obj.borrow().get_component::<SpriteComponent>().unwrap();
+        assert_eq!(sprite.borrow().sprite.atlas, atlas);
+        assert_eq!(sprite.borrow().sprite.sprite_id, 2);
+
+        // And by an animator made before it was added to anything
+        assert!(Animator::with_atlas_clips(engine.get_gfx()).play("blink").is_ok());
+    }
+}
@@ -0,0 +1,401 @@
+use std::{rc::{Rc, Weak}, cell::RefCell};
+
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+use crate::game_engine::{game_object::GameObject, Vector2};
+
+use super::Component;
+
+/// Moves the `Graphics` camera to keep a target object in view. Can be attached to any object, it only reads the target.
+pub struct CameraFollow {
+    target: Option<Weak<RefCell<GameObject>>>,
+    /// Added to the target's position.
+    pub offset: Vector2,
+    /// Seconds it takes to cover most of the distance to the target. 0 snaps instantly.
+    pub smoothing: f32,
+    /// Size of a rectangle around the camera center the target can move in without the camera following.
+    pub dead_zone: Vector2,
+    /// How far ahead of the target to look on each axis while it is moving.
+    pub look_ahead: Vector2,
+    /// Same as `smoothing`, for easing the look-ahead in and out.
+    pub look_ahead_smoothing: f32,
+    /// World space rectangle (min, max) the camera's view is kept inside. Centered on any axis the view is larger than.
+    pub bounds: Option<(Vector2, Vector2)>,
+    /// Follow the target's interpolated position. Only useful if the target is moved in `fixed_update`.
+    pub interpolate: bool,
+    /// Trauma lost per second.
+    pub trauma_decay: f32,
+    /// Camera offset at full trauma.
+    pub max_shake: Vector2,
+    /// Camera roll at full trauma, in radians.
+    pub max_shake_angle: f32,
+    trauma: f32,
+    /// Seeded, so the same trauma shakes the same way in every run and replays stay in sync
+    rng: StdRng,
+    /// Smoothed camera center, without shake
+    position: Option<Vector2>,
+    look: Vector2,
+    last_target_pos: Option<Vector2>,
+    applied_angle: f32
+}
+
+impl CameraFollow {
+    pub fn new(target: &Rc<RefCell<GameObject>>) -> CameraFollow {
+        CameraFollow {
+            target: Some(Rc::downgrade(target)),
+            offset: Vector2::ZERO,
+            smoothing: 0.2,
+            dead_zone: Vector2::ZERO,
+            look_ahead: Vector2::ZERO,
+            look_ahead_smoothing: 0.5,
+            bounds: None,
+            interpolate: false,
+            trauma_decay: 1.0,
+            max_shake: Vector2::new(0.1, 0.1),
+            max_shake_angle: 0.0,
+            trauma: 0.0,
+            rng: StdRng::seed_from_u64(0),
+            position: None,
+            look: Vector2::ZERO,
+            last_target_pos: None,
+            applied_angle: 0.0
+        }
+    }
+
+    /// Follows `target` from now on, or stops following if `None`.
+    pub fn set_target(&mut self, target: Option<&Rc<RefCell<GameObject>>>) {
+        self.target = target.map(Rc::downgrade);
+        self.last_target_pos = None;
+    }
+
+    /// `None` once the target has been destroyed.
+    pub fn get_target(&self) -> Option<Rc<RefCell<GameObject>>> {
+        self.target.as_ref().and_then(Weak::upgrade).filter(|t| !t.borrow().is_destroyed())
+    }
+
+    /// Jumps straight to the target on the next update, e.g. after it was teleported.
+    pub fn snap(&mut self) {
+        self.position = None;
+        self.look = Vector2::ZERO;
+        self.last_target_pos = None;
+    }
+
+    /// Adds screen shake. Trauma goes from 0 to 1 and the shake grows with its square, so small hits barely move the camera.
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn get_trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    /// Restarts the shake's random sequence from `seed`. Every `CameraFollow` starts from seed 0.
+    pub fn set_shake_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Fraction of the remaining distance to cover this frame, independent of frame rate.
+    fn smooth_factor(smoothing: f32, delta_time: f32) -> f32 {
+        if smoothing <= 0.0 {
+            return 1.0;
+        }
+
+        1.0 - (-delta_time * 4.0 / smoothing).exp()
+    }
+
+    /// Moves `pos` as little as possible for `focus` to be inside the dead-zone around it.
+    fn apply_dead_zone(pos: f32, focus: f32, dead_zone: f32) -> f32 {
+        let half = dead_zone / 2.0;
+
+        if focus > pos + half {
+            focus - half
+        } else if focus < pos - half {
+            focus + half
+        } else {
+            pos
+        }
+    }
+
+    fn clamp_axis(pos: f32, min: f32, max: f32, view: f32) -> f32 {
+        let half = view / 2.0;
+
+        if max - min <= view {
+            (min + max) / 2.0
+        } else {
+            pos.clamp(min + half, max - half)
+        }
+    }
+}
+
+impl Component for CameraFollow {
+    // Late, so the target has already moved this frame
+    fn late_update(&mut self, info: super::TickInfo, _owner: Rc<RefCell<GameObject>>) {
+        let delta = info.delta_time;
+        let engine = info.engine;
+        let alpha = engine.get_interpolation_alpha();
+
+        if let Some(target) = self.get_target() {
+            let target = target.borrow();
+            let target_pos = if self.interpolate {
+                target.get_interpolated_world_pos(alpha)
+            } else {
+                target.get_world_pos()
+            };
+            let target_pos = Vector2::new(target_pos.x, target_pos.y);
+
+            // Look ahead in whichever direction the target moved this frame, easing back to center once it stops
+            let moved = self.last_target_pos.map(|last| target_pos - last).unwrap_or(Vector2::ZERO);
+            let direction = |d: f32| if d.abs() > 1e-5 { d.signum() } else { 0.0 };
+            let look_goal = Vector2::new(direction(moved.x) * self.look_ahead.x, direction(moved.y) * self.look_ahead.y);
+
+            self.look = self.look.lerp(look_goal, CameraFollow::smooth_factor(self.look_ahead_smoothing, delta));
+            self.last_target_pos = Some(target_pos);
+
+            let focus = target_pos + self.offset + self.look;
+
+            self.position = Some(match self.position {
+                Some(pos) => {
+                    let goal = Vector2::new(
+                        CameraFollow::apply_dead_zone(pos.x, focus.x, self.dead_zone.x),
+                        CameraFollow::apply_dead_zone(pos.y, focus.y, self.dead_zone.y)
+                    );
+
+                    pos.lerp(goal, CameraFollow::smooth_factor(self.smoothing, delta))
+                },
+                None => focus
+            });
+        }
+
+        self.trauma = f32::max(0.0, self.trauma - self.trauma_decay * delta);
+
+        let view_size = engine.get_gfx().get_view_size();
+        let cam = engine.get_gfx_mut().get_camera_mut();
+        let mut pos = self.position.unwrap_or(Vector2::new(cam.pos.x, cam.pos.y));
+
+        if let Some((min, max)) = self.bounds {
+            pos = Vector2::new(CameraFollow::clamp_axis(pos.x, min.x, max.x, view_size.x), CameraFollow::clamp_axis(pos.y, min.y, max.y, view_size.y));
+
+            // Keep the clamped position, otherwise the camera lags behind when the target comes back from past the edge
+            if self.position.is_some() {
+                self.position = Some(pos);
+            }
+        }
+
+        // Shake is added on top of the follow position so it never feeds back into the smoothing
+        let shake = self.trauma * self.trauma;
+        let rng = &mut self.rng;
+        let (mut shake_offset, mut shake_angle) = (Vector2::ZERO, 0.0);
+
+        if shake > 0.0 {
+            shake_offset = Vector2::new(rng.gen_range(-1.0..=1.0) * self.max_shake.x, rng.gen_range(-1.0..=1.0) * self.max_shake.y) * shake;
+            shake_angle = rng.gen_range(-1.0..=1.0) * self.max_shake_angle * shake;
+        }
+
+        cam.pos.x = pos.x + shake_offset.x;
+        cam.pos.y = pos.y + shake_offset.y;
+        cam.rot.z += shake_angle - self.applied_angle;
+        self.applied_angle = shake_angle;
+    }
+
+    fn fixed_update(&mut self, _info: super::TickInfo, _owner: Rc<RefCell<GameObject>>) {}
+
+    fn render(&mut self, _info: super::TickInfo, _owner: Rc<RefCell<GameObject>>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_engine::{Engine, Vector3};
+
+    /// Camera position and roll over a few frames of shake, following an object that stays put.
+    fn shake(seed: Option<u64>) -> Vec<(f32, f32, f32)> {
+        let mut engine = Engine::init_headless();
+        let target = GameObject::create_empty("target".to_owned(), Some(engine.get_root_object()));
+
+        let mut follow = CameraFollow::new(&target);
+        follow.max_shake_angle = 0.1;
+        follow.add_trauma(1.0);
+        if let Some(seed) = seed {
+            follow.set_shake_seed(seed);
+        }
+        target.borrow_mut().add_component(follow);
+
+        (0..5).map(|_| {
+            engine.step_fixed();
+            let cam = engine.get_gfx().get_camera();
+
+            (cam.pos.x, cam.pos.y, cam.rot.z)
+        }).collect()
+    }
+
+    /// Engine with a 4x2 view and a `CameraFollow` on the root, following a target at the origin.
+    fn follow_engine(configure: impl FnOnce(&mut CameraFollow)) -> (Engine, Rc<RefCell<GameObject>>) {
+        let mut engine = Engine::init_headless();
+        engine.get_gfx_mut().get_camera_mut().size = Vector2::new(4.0, 2.0);
+
+        let target = GameObject::create_empty("target".to_owned(), Some(engine.get_root_object()));
+
+        let mut follow = CameraFollow::new(&target);
+        configure(&mut follow);
+        engine.get_root_object().borrow_mut().add_component(follow);
+
+        (engine, target)
+    }
+
+    fn move_to(target: &Rc<RefCell<GameObject>>, x: f32, y: f32) {
+        target.borrow_mut().set_pos(Vector3::new(x, y, 0.0));
+    }
+
+    fn cam_pos(engine: &Engine) -> (f32, f32) {
+        let cam = engine.get_gfx().get_camera();
+
+        (cam.pos.x, cam.pos.y)
+    }
+
+    fn assert_cam(engine: &Engine, x: f32, y: f32) {
+        let (cx, cy) = cam_pos(engine);
+
+        assert!((cx - x).abs() < 1e-4 && (cy - y).abs() < 1e-4, "camera at ({}, {}), expected ({}, {})", cx, cy, x, y);
+    }
+
+    #[test]
+    fn smoothing_eases_towards_the_target() {
+        let (mut engine, target) = follow_engine(|f| f.smoothing = 0.2);
+
+        // The first frame jumps straight to the target
+        move_to(&target, 1.0, 2.0);
+        engine.step_fixed();
+        assert_cam(&engine, 1.0, 2.0);
+
+        move_to(&target, 2.0, 2.0);
+        engine.step_fixed();
+        let (x, _) = cam_pos(&engine);
+        assert!(x > 1.0 && x < 2.0, "{}", x);
+
+        for _ in 0..120 {
+            engine.step_fixed();
+        }
+        assert_cam(&engine, 2.0, 2.0);
+
+        // Doesn't depend on the frame rate
+        let (mut halves, halves_target) = follow_engine(|f| f.smoothing = 0.2);
+        halves.step(0.0);
+        move_to(&halves_target, 1.0, 0.0);
+        halves.step(1.0 / 120.0);
+        halves.step(1.0 / 120.0);
+
+        let (mut whole, whole_target) = follow_engine(|f| f.smoothing = 0.2);
+        whole.step(0.0);
+        move_to(&whole_target, 1.0, 0.0);
+        whole.step(1.0 / 60.0);
+
+        assert_cam(&halves, cam_pos(&whole).0, 0.0);
+
+        // No smoothing snaps
+        let (mut engine, target) = follow_engine(|f| f.smoothing = 0.0);
+        engine.step_fixed();
+        move_to(&target, 3.0, -1.0);
+        engine.step_fixed();
+        assert_cam(&engine, 3.0, -1.0);
+    }
+
+    #[test]
+    fn dead_zone_only_follows_past_its_edge() {
+        let (mut engine, target) = follow_engine(|f| {
+            f.smoothing = 0.0;
+            f.dead_zone = Vector2::new(2.0, 1.0);
+        });
+        engine.step_fixed();
+
+        move_to(&target, 0.8, 0.4);
+        engine.step_fixed();
+        assert_cam(&engine, 0.0, 0.0);
+
+        // Pulls the camera along by the edge of the dead-zone
+        move_to(&target, 1.5, -1.0);
+        engine.step_fixed();
+        assert_cam(&engine, 0.5, -0.5);
+
+        move_to(&target, 0.0, -0.5);
+        engine.step_fixed();
+        assert_cam(&engine, 0.5, -0.5);
+    }
+
+    #[test]
+    fn look_ahead_follows_the_direction_of_movement() {
+        let (mut engine, target) = follow_engine(|f| {
+            f.smoothing = 0.0;
+            f.look_ahead = Vector2::new(1.0, 0.5);
+            f.look_ahead_smoothing = 0.0;
+        });
+        engine.step_fixed();
+        assert_cam(&engine, 0.0, 0.0);
+
+        move_to(&target, 0.1, 0.0);
+        engine.step_fixed();
+        assert_cam(&engine, 1.1, 0.0);
+
+        // Back to center once it stops
+        engine.step_fixed();
+        assert_cam(&engine, 0.1, 0.0);
+
+        move_to(&target, 0.0, -0.1);
+        engine.step_fixed();
+        assert_cam(&engine, -1.0, -0.6);
+
+        // Eases in with smoothing
+        let (mut engine, target) = follow_engine(|f| {
+            f.smoothing = 0.0;
+            f.look_ahead = Vector2::new(1.0, 0.0);
+        });
+        engine.step_fixed();
+        move_to(&target, 0.1, 0.0);
+        engine.step_fixed();
+        let (x, _) = cam_pos(&engine);
+        assert!(x > 0.1 && x < 1.1, "{}", x);
+    }
+
+    #[test]
+    fn bounds_keep_the_view_inside() {
+        let (mut engine, target) = follow_engine(|f| {
+            f.smoothing = 0.0;
+            f.bounds = Some((Vector2::new(-10.0, -10.0), Vector2::new(10.0, 10.0)));
+        });
+
+        // The 4x2 view stops with its edges on the bounds
+        move_to(&target, 9.0, -9.5);
+        engine.step_fixed();
+        assert_cam(&engine, 8.0, -9.0);
+
+        move_to(&target, 0.0, 3.0);
+        engine.step_fixed();
+        assert_cam(&engine, 0.0, 3.0);
+    }
+
+    #[test]
+    fn bounds_smaller_than_the_view_are_centered() {
+        let (mut engine, target) = follow_engine(|f| {
+            f.smoothing = 0.0;
+            f.bounds = Some((Vector2::new(0.0, 0.0), Vector2::new(3.0, 10.0)));
+        });
+
+        // 3 wide is narrower than the 4 wide view, so x stays centered while y still follows
+        move_to(&target, 100.0, 5.0);
+        engine.step_fixed();
+        assert_cam(&engine, 1.5, 5.0);
+
+        move_to(&target, -100.0, 20.0);
+        engine.step_fixed();
+        assert_cam(&engine, 1.5, 9.0);
+    }
+
+    #[test]
+    fn shake_is_deterministic() {
+        let first = shake(None);
+
+        assert!(first.iter().any(|(x, y, _)| *x != 0.0 || *y != 0.0), "camera never shook");
+        assert_eq!(first, shake(None));
+        assert_eq!(shake(Some(7)), shake(Some(7)));
+        assert_ne!(first, shake(Some(7)));
+    }
+}
@@ -5,6 +5,8 @@ use std::{hash::Hash, rc::Rc, cell::RefCell};
 
 pub struct Collider {
     owner: Option<Rc<RefCell<GameObject>>>,
+    /// Between `on_enable` and `on_disable`
+    enabled: bool,
     pub hitbox: Polygon
 }
 
@@ -24,17 +26,49 @@ impl Hash for Collider {
 }
 
 impl Component for Collider {
-    fn init(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {
+    fn awake(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {
         self.owner = Some(Rc::clone(&_owner));
     }
+
+    fn on_enable(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {
+        self.enabled = true;
+    }
+
+    fn on_disable(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {
+        self.enabled = false;
+    }
+
+    fn on_destroy(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {
+        // The owner holds on to its components, so this would keep both alive
+        self.owner = None;
+    }
 }
 
 impl Collider {
     pub fn new(hitbox: Polygon) -> Collider {
-        Collider { owner: None, hitbox }
+        Collider { owner: None, enabled: false, hitbox }
     }
 
+    /// Panics before the collider's `awake`, which the engine calls before the next tick after it is added.
     pub fn get_owner(&self) -> Rc<RefCell<GameObject>> {
-        self.owner.as_ref().unwrap().clone()
+        self.owner.as_ref().expect("Collider has no owner until the engine calls its awake!").clone()
+    }
+
+    /// False until `awake` and after `on_destroy`.
+    pub fn has_owner(&self) -> bool {
+        self.owner.is_some()
+    }
+
+    /// Whether other objects collide with it: it has an owner, and it is enabled on an active object.
+    pub fn is_active(&self) -> bool {
+        self.enabled && self.owner.is_some()
+    }
+
+    /// The hitbox moved, rotated and scaled by the owner's world transform.
+    pub fn get_world_hitbox(&self) -> Polygon {
+        match &self.owner {
+            Some(owner) => self.hitbox.transformed(&owner.borrow().get_world_transform()),
+            None => self.hitbox.clone()
+        }
     }
 }
\ No newline at end of file
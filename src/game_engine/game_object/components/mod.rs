@@ -2,6 +2,8 @@ mod test_component;
 mod wasdy;
 mod sprite;
 mod collider;
+mod animator;
+mod camera_follow;
 
 use std::{rc::Rc, cell::{RefCell, RefMut, Ref}, marker::PhantomData};
 
@@ -10,6 +12,8 @@ pub use test_component::TestComponent;
 pub use wasdy::WASDy;
 pub use sprite::SpriteComponent;
 pub use collider::Collider;
+pub use animator::Animator;
+pub use camera_follow::CameraFollow;
 
 use crate::game_engine::Engine;
 
@@ -17,14 +21,30 @@ use super::GameObject;
 
 pub struct TickInfo<'a> {
     pub(in crate::game_engine) delta_time: f32,
+    pub(in crate::game_engine) unscaled_delta_time: f32,
     pub(in crate::game_engine) engine: &'a mut Engine
 }
 
+/// Hooks the engine calls on components, in this order:
+/// - `awake` once, before the next tick after the component is added, even if it is disabled.
+/// - `on_enable` after `awake` and whenever the component becomes enabled with its object active. `on_disable` when that stops.
+/// - `start` once, after the first `on_enable`.
+/// - Every fixed tick `fixed_update`, every frame `update` and then `late_update` once everything has updated. Skipped while disabled.
+/// - `render` every frame right before drawing, even while paused. Skipped while disabled.
+/// - `on_destroy` at the end of the frame the component or its object was removed in, after `on_disable`.
+///
+/// Enabling and disabling takes effect at the start of the next tick.
 pub trait Component: Downcast {
-    fn init(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {}
+    fn awake(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {}
+    fn start(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {}
     fn update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) {}
+    /// For anything that should see where everything ended up this frame, like cameras.
+    fn late_update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) {}
     fn fixed_update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) {}
     fn render(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) {}
+    fn on_enable(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {}
+    fn on_disable(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {}
+    fn on_destroy(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) {}
 }
 
 impl_downcast!(Component);
@@ -70,6 +90,10 @@ impl<C: Component> CompRc<C> {
         self.rc
     }
 
+    pub(in crate::game_engine) fn get_rc(&self) -> &Rc<RefCell<dyn Component>> {
+        &self.rc
+    }
+
     pub fn ptr_eq(&self, other: &CompRc<C>) -> bool {
         Rc::ptr_eq(&self.rc, &other.rc)
     }
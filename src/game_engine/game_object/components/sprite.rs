@@ -1,39 +1,97 @@
 use std::{marker::PhantomData, rc::Rc, cell::RefCell};
 
-use crate::game_engine::{Sprite, game_object::GameObject};
+use crate::game_engine::{Sprite, game_object::GameObject, graphics::SpriteHandle};
 
 use super::Component;
 
 pub struct SpriteComponent {
+    /// Position is taken from the owner. Size, rotation, flipping, tint and opacity are relative to the owner's transform.
     pub sprite: Sprite,
-    pub index: usize,
+    /// Atlas frame to look up when the component wakes up. Overrides `sprite.sprite_id` and `sprite.atlas`.
+    frame: Option<String>,
+    /// Name of a sorting layer registered with `Graphics`. Overrides `sprite.layer` when set. Has to exist by the time the
+    /// component wakes up.
+    pub sorting_layer: Option<String>,
+    handle: Option<SpriteHandle>,
+    /// Blend between fixed-tick positions, on by default. Turn it off for objects that are only moved in `update`, so they
+    /// don't trail a tick behind.
+    pub interpolate: bool,
     _pd: PhantomData<()>
 }
 
 impl SpriteComponent {
-    pub fn new(sprite_number: usize, sprite_id: i32) -> SpriteComponent {
-        SpriteComponent { sprite: Sprite { sprite_id, x: 0.0, y: 0.0, w: 1.0, h: 1.0 }, index: sprite_number, _pd: PhantomData }
+    pub fn new(sprite_id: i32) -> SpriteComponent {
+        SpriteComponent { sprite: Sprite { sprite_id, ..Sprite::default() }, frame: None, sorting_layer: None, handle: None, interpolate: true, _pd: PhantomData }
+    }
+
+    /// Refers to an atlas frame by name, e.g. `"player_idle_0"`.
+    pub fn from_frame(frame: &str) -> SpriteComponent {
+        SpriteComponent { frame: Some(frame.to_owned()), ..SpriteComponent::new(0) }
     }
 }
 
 impl Component for SpriteComponent {
-    fn init(&mut self, _engine: &mut crate::game_engine::Engine, _owner: Rc<RefCell<GameObject>>) {
-        let gfx = _engine.get_gfx_mut();
+    fn awake(&mut self, _engine: &mut crate::game_engine::Engine, _owner: Rc<RefCell<GameObject>>) {
+        if let Some(frame) = &self.frame {
+            let (atlas, sprite_id) = _engine.get_gfx().find_frame(frame).unwrap_or_else(|| panic!("Sprite frame \"{}\" is not in any atlas!", frame));
+
+            self.sprite.atlas = atlas;
+            self.sprite.sprite_id = sprite_id;
+        }
 
-        gfx.update_sprite(self.sprite, self.index);
+        // Checked once here rather than every frame in render
+        if let Some(name) = &self.sorting_layer {
+            if _engine.get_gfx().get_sorting_layer(name).is_none() {
+                panic!("Sorting layer \"{}\" does not exist!", name);
+            }
+        }
     }
 
-    fn update(&mut self, _info: super::TickInfo, _owner: Rc<RefCell<GameObject>>) {
-        let engine = _info.engine;
-        let gfx = engine.get_gfx_mut();
-        let parent = _owner.borrow();
+    fn on_enable(&mut self, _engine: &mut crate::game_engine::Engine, _owner: Rc<RefCell<GameObject>>) {
+        let gfx = _engine.get_gfx_mut();
+        let handle = gfx.alloc_sprite();
 
-        let real_sprite = Sprite { sprite_id: self.sprite.sprite_id, x: parent.pos.x, y: parent.pos.y, w: self.sprite.w, h: self.sprite.h };
+        gfx.update_sprite(self.sprite, &handle);
+        self.handle = Some(handle);
+    }
 
-        gfx.update_sprite(real_sprite, self.index);
+    fn on_disable(&mut self, _engine: &mut crate::game_engine::Engine, _owner: Rc<RefCell<GameObject>>) {
+        // Gives the slot back, so disabled sprites aren't drawn
+        self.handle = None;
     }
 
     fn fixed_update(&mut self, _info: super::TickInfo, _owner: Rc<RefCell<GameObject>>) {}
 
-    fn render(&mut self, _info: super::TickInfo, _owner: Rc<RefCell<GameObject>>) {}
+    // In render rather than update, so it sees where the owner ended up after every update and late_update this frame
+    fn render(&mut self, info: super::TickInfo, owner: Rc<RefCell<GameObject>>) {
+        let engine = info.engine;
+        let alpha = engine.get_interpolation_alpha();
+        let gfx = engine.get_gfx_mut();
+        let parent = owner.borrow();
+
+        let pos = if self.interpolate {
+            parent.get_interpolated_world_pos(alpha)
+        } else {
+            parent.get_world_pos()
+        };
+        let scale = parent.get_world_scale();
+        let rot = parent.get_world_rot();
+
+        let layer = match &self.sorting_layer {
+            // Looked up every frame, so changing the layer's value moves its sprites
+            Some(name) => gfx.get_sorting_layer(name).map(|l| l.value).unwrap_or(self.sprite.layer),
+            None => self.sprite.layer
+        };
+
+        let real_sprite = Sprite { x: pos.x, y: pos.y, w: self.sprite.w * scale.x, h: self.sprite.h * scale.y, rot: self.sprite.rot + rot.z, layer, ..self.sprite };
+
+        if let Some(handle) = &self.handle {
+            gfx.update_sprite(real_sprite, handle);
+        }
+    }
+
+    fn on_destroy(&mut self, _engine: &mut crate::game_engine::Engine, _owner: Rc<RefCell<GameObject>>) {
+        // Frees the slot even if something else still holds on to the component
+        self.handle = None;
+    }
 }
\ No newline at end of file
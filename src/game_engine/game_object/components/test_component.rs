@@ -1,6 +1,6 @@
 use std::{rc::Rc, cell::RefCell};
 
-use crate::game_engine::{game_object::GameObject, self, Vector3};
+use crate::game_engine::game_object::GameObject;
 
 use super::{Component, TickInfo};
 
@@ -15,7 +15,7 @@ pub struct TestComponent {
 impl Component for TestComponent {
     fn update(&mut self, _tick_info: TickInfo, _owner: Rc<RefCell<GameObject>>) {
         self.count += 1;
-        let current_tick = game_engine::Graphics::get_glfw_time();
+        let current_tick = _tick_info.engine.get_time();
 
         let delta = current_tick - self.last_update;
 
@@ -30,7 +30,7 @@ impl Component for TestComponent {
 
     fn fixed_update(&mut self, _tick_info: TickInfo, _owner: Rc<RefCell<GameObject>>) {
         self.fixed_count += 1;
-        let current_tick = game_engine::Graphics::get_glfw_time();
+        let current_tick = _tick_info.engine.get_time();
 
         let delta = current_tick - self.last_fixed_update;
 
@@ -1,19 +1,47 @@
-use std::{rc::Rc, cell::RefCell, collections::VecDeque};
+use std::{rc::Rc, cell::{RefCell, Cell}, collections::VecDeque};
 pub mod components;
 
 use components::Component;
 
 use self::components::{CompRc, Collider};
 
-use super::{Vector3, Engine, Vector2};
+use super::{Vector3, Engine, matrix::Mat4x4};
+
+/// A component and how far through its lifecycle it is.
+#[derive(Clone)]
+pub(in crate::game_engine) struct ComponentEntry {
+    pub component: Rc<RefCell<dyn Component>>,
+    /// Set with `GameObject::set_component_enabled`. The component only runs while its object is active as well.
+    pub enabled: bool,
+    pub awake: bool,
+    pub started: bool,
+    /// Whether `on_enable` was the last of `on_enable` and `on_disable` to be called
+    pub reported_enabled: bool
+}
 
 pub struct GameObject {
     name: String,
     pos: Vector3,
+    prev_pos: Vector3,
     rot: Vector3,
     scale: Vector3,
+    world_transform: Cell<Mat4x4>,
+    transform_dirty: Cell<bool>,
     grounded: bool,
-    components: Vec<Rc<RefCell<dyn Component>>>,
+    /// Queued by `destroy`, carried out by the engine at the end of the frame
+    pending_destroy: bool,
+    destroyed: bool,
+    active: bool,
+    components: Vec<ComponentEntry>,
+    /// Whether the engine has called `init` on the object itself
+    initialized: bool,
+    /// Only used on the top of a tree. Set when something in it may need a lifecycle hook, see `mark_lifecycle_dirty`.
+    lifecycle_dirty: Cell<bool>,
+    /// Only used on the top of a tree. Set when something in it was queued by `destroy` or `remove_component`, see
+    /// `mark_destroy_pending`.
+    destroy_pending: Cell<bool>,
+    /// Queued by `remove_component`
+    removed_components: Vec<Rc<RefCell<dyn Component>>>,
     children: Vec<Rc<RefCell<GameObject>>>,
     parent: Option<Rc<RefCell<GameObject>>>
 }
@@ -35,10 +63,20 @@ impl GameObject {
         let new_obj = Rc::new(RefCell::new(GameObject {
             name,
             pos: Vector3::ZERO,
+            prev_pos: Vector3::ZERO,
             rot: Vector3::ZERO,
             scale: Vector3::ONE,
+            world_transform: Cell::new(Mat4x4::ident()),
+            transform_dirty: Cell::new(true),
             grounded: false,
+            pending_destroy: false,
+            destroyed: false,
+            active: true,
             components: Vec::new(),
+            initialized: false,
+            lifecycle_dirty: Cell::new(true),
+            destroy_pending: Cell::new(false),
+            removed_components: Vec::new(),
             children: Vec::new(),
             parent: None
         }));
@@ -48,16 +86,135 @@ impl GameObject {
         new_obj
     }
 
+    /// Position relative to the parent.
     pub fn get_pos(&self) -> Vector3 {
         self.pos
     }
 
     pub fn set_pos(&mut self, pos: Vector3) {
         self.pos = pos;
+        self.mark_transform_dirty();
+    }
+
+    /// Rotation in radians relative to the parent. Only z matters for sprites and colliders.
+    pub fn get_rot(&self) -> Vector3 {
+        self.rot
+    }
+
+    pub fn set_rot(&mut self, rot: Vector3) {
+        self.rot = rot;
+        self.mark_transform_dirty();
+    }
+
+    /// Scale relative to the parent.
+    pub fn get_scale(&self) -> Vector3 {
+        self.scale
+    }
+
+    pub fn set_scale(&mut self, scale: Vector3) {
+        self.scale = scale;
+        self.mark_transform_dirty();
+    }
+
+    /// Position at the start of the most recent fixed tick, relative to the parent.
+    pub fn get_prev_pos(&self) -> Vector3 {
+        self.prev_pos
+    }
+
+    /// Blends between the previous and current fixed-tick positions. `alpha` is usually `Engine::get_interpolation_alpha`.
+    pub fn get_interpolated_pos(&self, alpha: f32) -> Vector3 {
+        self.prev_pos.lerp(self.pos, alpha)
+    }
+
+    /// Same as `get_interpolated_pos`, but in world space. Parents are interpolated too, so children moved by their parent
+    /// in `fixed_update` blend the same way.
+    pub fn get_interpolated_world_pos(&self, alpha: f32) -> Vector3 {
+        self.get_interpolated_world_transform(alpha).transform_point(Vector3::ZERO)
+    }
+
+    fn get_interpolated_world_transform(&self, alpha: f32) -> Mat4x4 {
+        let parent_transform = match &self.parent {
+            Some(p) => p.borrow().get_interpolated_world_transform(alpha),
+            None => Mat4x4::ident()
+        };
+
+        parent_transform * Mat4x4::translation(self.get_interpolated_pos(alpha)) * Mat4x4::rotation(self.rot) * Mat4x4::scale(self.scale)
+    }
+
+    /// Moves the object without interpolating from its old position.
+    pub fn teleport(&mut self, pos: Vector3) {
+        self.prev_pos = pos;
+        self.set_pos(pos);
+    }
+
+    /// Local translation * rotation * scale.
+    pub fn get_local_transform(&self) -> Mat4x4 {
+        Mat4x4::translation(self.pos) * Mat4x4::rotation(self.rot) * Mat4x4::scale(self.scale)
+    }
+
+    /// Transform from this object's local space to world space. Cached until this object or one of its parents changes.
+    pub fn get_world_transform(&self) -> Mat4x4 {
+        if self.transform_dirty.get() {
+            self.world_transform.set(self.get_parent_transform() * self.get_local_transform());
+            self.transform_dirty.set(false);
+        }
+
+        self.world_transform.get()
+    }
+
+    pub fn get_world_pos(&self) -> Vector3 {
+        self.get_world_transform().transform_point(Vector3::ZERO)
+    }
+
+    /// Moves the object so that it ends up at `pos` in world space.
+    pub fn set_world_pos(&mut self, pos: Vector3) {
+        let local = match self.get_parent_transform().inverse() {
+            Some(inv) => inv.transform_point(pos),
+            None => pos
+        };
+
+        self.set_pos(local);
+    }
+
+    /// Sum of the rotations up the parent chain.
+    pub fn get_world_rot(&self) -> Vector3 {
+        match &self.parent {
+            Some(p) => p.borrow().get_world_rot() + self.rot,
+            None => self.rot
+        }
+    }
+
+    /// Product of the scales up the parent chain.
+    pub fn get_world_scale(&self) -> Vector3 {
+        let parent_scale = match &self.parent {
+            Some(p) => p.borrow().get_world_scale(),
+            None => Vector3::ONE
+        };
+
+        Vector3 { x: parent_scale.x * self.scale.x, y: parent_scale.y * self.scale.y, z: parent_scale.z * self.scale.z }
+    }
+
+    fn get_parent_transform(&self) -> Mat4x4 {
+        match &self.parent {
+            Some(p) => p.borrow().get_world_transform(),
+            None => Mat4x4::ident()
+        }
+    }
+
+    fn mark_transform_dirty(&self) {
+        if self.transform_dirty.replace(true) {
+            // Children were already invalidated the last time this was dirtied
+            return;
+        }
+
+        for c in &self.children {
+            c.borrow().mark_transform_dirty();
+        }
     }
 
     pub(in crate::game_engine) fn init(&mut self, engine: &mut Engine) {
-        // Idk if this is gonna be needed.
+        // Wherever the object was placed before its first tick is where it starts, not somewhere between there and the origin
+        self.prev_pos = self.pos;
     }
 
     pub(in crate::game_engine) fn update(&mut self, delta_time: f32, engine: &mut Engine) {
@@ -65,7 +222,7 @@ impl GameObject {
     }
 
     pub(in crate::game_engine) fn fixed_update(&mut self, delta_time: f32, engine: &mut Engine) {
-        // Idk if this is gonna be needed.
+        self.prev_pos = self.pos;
     }
 
     pub(in crate::game_engine) fn render(&mut self, delta_time: f32, engine: &mut Engine) {
@@ -76,8 +233,174 @@ impl GameObject {
         self.grounded
     }
 
+    /// Adds an enabled component. The engine calls its `awake` and `start` before its first update, even if it is added in
+    /// the middle of a frame.
     pub fn add_component<C: Component>(&mut self, component: C) {
-        self.components.push(Rc::new(RefCell::new(component)));
+        self.components.push(ComponentEntry { component: Rc::new(RefCell::new(component)), enabled: true, awake: false, started: false, reported_enabled: false });
+        self.mark_lifecycle_dirty();
+    }
+
+    /// Disabled components don't tick. They get `on_disable` before the next tick, and `on_enable` once enabled again.
+    /// Does nothing if `component` isn't one of this object's.
+    pub fn set_component_enabled<C: Component>(&mut self, component: &CompRc<C>, enabled: bool) {
+        if let Some(entry) = self.components.iter_mut().find(|e| Rc::ptr_eq(&e.component, component.get_rc())) {
+            entry.enabled = enabled;
+            self.mark_lifecycle_dirty();
+        }
+    }
+
+    /// Whether `component` is enabled, ignoring whether the object is active. False if it isn't one of this object's.
+    pub fn is_component_enabled<C: Component>(&self, component: &CompRc<C>) -> bool {
+        self.components.iter().any(|e| Rc::ptr_eq(&e.component, component.get_rc()) && e.enabled)
+    }
+
+    /// Inactive objects and everything under them don't tick, and their components get `on_disable` before the next tick.
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+        self.mark_lifecycle_dirty();
+    }
+
+    /// The object's own flag. It can still be inactive because of a parent, see `is_active_in_hierarchy`.
+    pub fn is_active_self(&self) -> bool {
+        self.active
+    }
+
+    pub fn is_active_in_hierarchy(&self) -> bool {
+        self.active && self.parent.as_ref().map(|p| p.borrow().is_active_in_hierarchy()).unwrap_or(true)
+    }
+
+    /// Queues the first component of type `C` for removal at the end of the frame, after its `on_destroy` is called.
+    /// Returns false if there is no such component, or all of them are already queued.
+    pub fn remove_component<C: Component>(&mut self) -> bool {
+        let found = self.components.iter().map(|e| &e.component).find(|c| {
+            // Same trick as get_component, the contents aren't used
+            let r: Option<&C> = unsafe {(*c.as_ptr()).downcast_ref()};
+
+            r.is_some() && !self.removed_components.iter().any(|r| Rc::ptr_eq(r, c))
+        });
+
+        match found {
+            Some(c) => {
+                self.removed_components.push(c.clone());
+                self.mark_destroy_pending();
+                true
+            },
+            None => false
+        }
+    }
+
+    /// Queues this object and all of its children to be destroyed at the end of the frame. They still update until then.
+    /// Every component gets `on_disable` if it is enabled and `on_destroy`, then the objects are taken out of the tree and
+    /// their components dropped.
+    /// The root object can't be destroyed.
+    pub fn destroy(&mut self) {
+        self.pending_destroy = true;
+        self.mark_destroy_pending();
+    }
+
+    /// True once the object has been destroyed. Anything still holding on to it should let go.
+    pub fn is_destroyed(&self) -> bool {
+        self.destroyed
+    }
+
+    pub(in crate::game_engine) fn is_pending_destroy(&self) -> bool {
+        self.pending_destroy && !self.destroyed
+    }
+
+    /// Tells the engine to look for pending lifecycle hooks before the next tick, by flagging the top of the tree.
+    fn mark_lifecycle_dirty(&self) {
+        match &self.parent {
+            Some(parent) => parent.borrow().mark_lifecycle_dirty(),
+            None => self.lifecycle_dirty.set(true)
+        }
+    }
+
+    /// Whether anything in the tree under this object was flagged by `mark_lifecycle_dirty` since the last call.
+    pub(in crate::game_engine) fn take_lifecycle_dirty(&self) -> bool {
+        self.lifecycle_dirty.replace(false)
+    }
+
+    /// Tells the engine there is something to destroy at the end of the frame, by flagging the top of the tree.
+    fn mark_destroy_pending(&self) {
+        match &self.parent {
+            Some(parent) => parent.borrow().mark_destroy_pending(),
+            None => self.destroy_pending.set(true)
+        }
+    }
+
+    /// Whether anything in the tree under this object was flagged by `mark_destroy_pending` since the last call.
+    pub(in crate::game_engine) fn take_destroy_pending(&self) -> bool {
+        self.destroy_pending.replace(false)
+    }
+
+    /// Whether the object itself or any of its components still need waking up.
+    pub(in crate::game_engine) fn needs_awake(&self) -> bool {
+        !self.initialized || self.components.iter().any(|e| !e.awake)
+    }
+
+    /// Components that need `awake`, in the order they were added, marked as awake. Also marks the object as initialized.
+    pub(in crate::game_engine) fn take_pending_awake(&mut self) -> Vec<Rc<RefCell<dyn Component>>> {
+        self.initialized = true;
+
+        self.components.iter_mut().filter(|e| !e.awake).map(|e| {
+            e.awake = true;
+            e.component.clone()
+        }).collect()
+    }
+
+    /// Components that should get `on_enable` (true) or `on_disable` (false), given whether the object is active in the
+    /// hierarchy. They are marked as told.
+    pub(in crate::game_engine) fn take_enable_changes(&mut self, active_in_hierarchy: bool) -> Vec<(Rc<RefCell<dyn Component>>, bool)> {
+        self.components.iter_mut().filter(|e| e.awake && e.reported_enabled != (e.enabled && active_in_hierarchy)).map(|e| {
+            e.reported_enabled = !e.reported_enabled;
+            (e.component.clone(), e.reported_enabled)
+        }).collect()
+    }
+
+    /// Enabled components that need `start`, marked as started.
+    pub(in crate::game_engine) fn take_pending_start(&mut self) -> Vec<Rc<RefCell<dyn Component>>> {
+        self.components.iter_mut().filter(|e| e.reported_enabled && !e.started).map(|e| {
+            e.started = true;
+            e.component.clone()
+        }).collect()
+    }
+
+    /// Components that are started and still enabled.
+    pub(in crate::game_engine) fn get_tickable_components(&self) -> Vec<Rc<RefCell<dyn Component>>> {
+        self.components.iter().filter(|e| e.started && e.reported_enabled && e.enabled).map(|e| e.component.clone()).collect()
+    }
+
+    pub(in crate::game_engine) fn get_component_entries(&self) -> Vec<ComponentEntry> {
+        self.components.clone()
+    }
+
+    pub(in crate::game_engine) fn take_removed_components(&mut self) -> Vec<ComponentEntry> {
+        let removed = std::mem::take(&mut self.removed_components);
+        let (gone, kept) = self.components.drain(..).partition(|e| removed.iter().any(|r| Rc::ptr_eq(r, &e.component)));
+        self.components = kept;
+
+        gone
+    }
+
+    /// Cuts the object out of the tree and drops its components, which also frees their sprite slots.
+    pub(in crate::game_engine) fn tear_down(slf: &Rc<RefCell<GameObject>>) {
+        if let Some(parent) = slf.borrow().get_parent() {
+            parent.borrow_mut().remove_child(slf);
+        }
+
+        let (components, children) = {
+            let mut obj = slf.borrow_mut();
+
+            obj.destroyed = true;
+            obj.parent = None;
+            obj.removed_components.clear();
+
+            (std::mem::take(&mut obj.components), std::mem::take(&mut obj.children))
+        };
+
+        // Dropped outside the borrow in case a component's drop looks at the object
+        drop(components);
+        drop(children);
     }
 
     pub fn get_parent(&self) -> Option<Rc<RefCell<GameObject>>> {
@@ -91,9 +414,8 @@ impl GameObject {
         {
             let old_parent = &slf.borrow().parent;
 
-            match old_parent {
-                Some(p) => p.borrow_mut().remove_child(&*slf.borrow().as_ref()),
-                None => ()
+            if let Some(p) = old_parent {
+                p.borrow_mut().remove_child(&slf);
             }
         }
 
@@ -104,6 +426,12 @@ impl GameObject {
             },
             None => slf.borrow_mut().parent = None
         }
+
+        slf.borrow().mark_transform_dirty();
+        // Wakes up new objects, and reparenting can change whether the object is active in the hierarchy
+        slf.borrow().mark_lifecycle_dirty();
+        // Anything queued while the object was outside this tree was flagged on its old top
+        slf.borrow().mark_destroy_pending();
     }
 
     pub fn get_children(&self) -> Vec<Rc<RefCell<GameObject>>> {
@@ -117,6 +445,7 @@ impl GameObject {
         v
     }
 
+    /// Every descendant, breadth first. The list is a copy, so objects can be added and removed while going through it.
     pub fn get_all_children(&self) -> Vec<Rc<RefCell<GameObject>>> {
         let mut v = Vec::new();
 
@@ -127,7 +456,7 @@ impl GameObject {
             v.push(c.clone());
         }
 
-        while q.len() > 0 {
+        while !q.is_empty() {
             let current = q.pop_front().unwrap();
             v.reserve(current.borrow().children.len());
 
@@ -144,24 +473,20 @@ impl GameObject {
         self.children.push(child.clone());
     }
 
-    pub fn remove_child(&mut self, child: *const GameObject) {
-        let mut idx = -1;
-        for (i, c) in self.children.iter().enumerate() {
-            if c.borrow().as_ref() as *const GameObject == child {
-                idx = i as i32;
-                break;
-            }
-        }
-
-        if idx == -1 {
-            panic!("Child does not exist! This shouldn't happen!");
+    /// Takes `child` out of this object's children. Returns false if it isn't one of them. The child keeps pointing at
+    /// this object as its parent, use `set_parent` to move objects around instead.
+    pub fn remove_child(&mut self, child: &Rc<RefCell<GameObject>>) -> bool {
+        match self.children.iter().position(|c| Rc::ptr_eq(c, child)) {
+            Some(i) => {
+                self.children.remove(i);
+                true
+            },
+            None => false
         }
-
-        self.children.remove(idx as usize);
     }
 
     pub fn get_component<C: Component>(&self) -> Option<CompRc<C>> {
-        for c in &self.components {
+        for c in self.components.iter().map(|e| &e.component) {
             let t = c.as_ptr();
             // This should be fine since the contents of r are not being used and any borrow issues will still panic at the downcast_rc function call
             let r: Option<&C> = unsafe {(*t).downcast_ref()};
@@ -177,7 +502,7 @@ impl GameObject {
     pub fn get_components<C: Component>(&self) -> Vec<CompRc<C>> {
         let mut vec = Vec::new();
 
-        for c in &self.components {
+        for c in self.components.iter().map(|e| &e.component) {
             let t = c.as_ptr();
             // This should be fine since the contents of r are not being used and any borrow issues will still panic at the downcast_rc function call
             let r: Option<&C> = unsafe {(*t).downcast_ref()}; 
@@ -202,38 +527,108 @@ impl GameObject {
     }
 
     pub fn get_all_components(&self) -> Vec<Rc<RefCell<dyn Component>>> {
-        self.components.clone()
+        self.components.iter().map(|e| e.component.clone()).collect()
     }
 
+    /// Moves the object by `offset` in world space, then pushes it out of any colliders it ended up overlapping.
     pub fn move_and_collide(obj: &Rc<RefCell<GameObject>>, offset: Vector3, engine: &mut Engine) {
         obj.borrow_mut().grounded = false;
-        obj.borrow_mut().pos += offset;
-
-        let c = obj.borrow().get_component();
+        {
+            let mut o = obj.borrow_mut();
+            let pos = o.get_world_pos() + offset;
+            o.set_world_pos(pos);
+        }
 
-        if c.is_some() {
-            let c = c.unwrap();
+        let c: Option<CompRc<Collider>> = obj.borrow().get_component();
 
+        if let Some(c) = c.filter(|c| c.borrow().is_active()) {
             let all_colliders = engine.get_root_object().borrow().get_components_in_children::<Collider>();
             for collider in all_colliders {
-                if !collider.ptr_eq(&c) {
-                    let o_owner = collider.borrow().get_owner();
-                    let s = &mut c.borrow_mut().hitbox;
-                    let o = &mut collider.borrow_mut().hitbox;
-
-                    s.pos = Vector2 { x: obj.borrow().pos.x, y: obj.borrow().pos.y };
-                    o.pos = Vector2 { x: o_owner.borrow().pos.x, y: o_owner.borrow().pos.y };
-
-                    let push = s.collide(o);
-
-                    if push.is_some() {
-                        obj.borrow_mut().pos += Vector3 { x: push.unwrap().x, y: push.unwrap().y, z: 0.0 };
-                        if push.unwrap().y > 0.0 {
-                            obj.borrow_mut().grounded = true;
+                // Skips disabled colliders, and ones added this frame that don't know where they are yet
+                if !collider.ptr_eq(&c) && collider.borrow().is_active() {
+                    let s = c.borrow().get_world_hitbox();
+                    let o = collider.borrow().get_world_hitbox();
+
+                    if let Some(push) = s.collide(&o) {
+                        let mut o = obj.borrow_mut();
+                        let pos = o.get_world_pos() + Vector3 { x: push.x, y: push.y, z: 0.0 };
+                        o.set_world_pos(pos);
+
+                        if push.y > 0.0 {
+                            o.grounded = true;
                         }
                     }
                 }
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_engine::{Polygon, Vector2};
+
+    fn close(a: Vector3, b: Vector3) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    #[test]
+    fn moving_a_parent_moves_cached_children() {
+        let parent = GameObject::create_empty("parent".to_owned(), None);
+        let child = GameObject::create_empty("child".to_owned(), Some(parent.clone()));
+        let grandchild = GameObject::create_empty("grandchild".to_owned(), Some(child.clone()));
+
+        parent.borrow_mut().set_pos(Vector3::new(1.0, 0.0, 0.0));
+        child.borrow_mut().set_pos(Vector3::new(0.5, 0.0, 0.0));
+        grandchild.borrow_mut().set_pos(Vector3::new(0.0, 0.25, 0.0));
+
+        // Fills the caches all the way up
+        assert!(close(grandchild.borrow().get_world_pos(), Vector3::new(1.5, 0.25, 0.0)));
+        assert!(!grandchild.borrow().transform_dirty.get());
+
+        parent.borrow_mut().set_pos(Vector3::new(2.0, 1.0, 0.0));
+        assert!(close(child.borrow().get_world_pos(), Vector3::new(2.5, 1.0, 0.0)));
+        assert!(close(grandchild.borrow().get_world_pos(), Vector3::new(2.5, 1.25, 0.0)));
+
+        // Rotating and scaling the parent carries the children around it
+        parent.borrow_mut().set_rot(Vector3::new(0.0, 0.0, std::f32::consts::FRAC_PI_2));
+        parent.borrow_mut().set_scale(Vector3::new(2.0, 2.0, 1.0));
+        assert!(close(grandchild.borrow().get_world_pos(), Vector3::new(1.5, 2.0, 0.0)), "{}", grandchild.borrow().get_world_pos());
+
+        // Reparenting invalidates as well
+        GameObject::set_parent(grandchild.clone(), Some(parent.clone()));
+        assert!(close(grandchild.borrow().get_world_pos(), Vector3::new(1.5, 1.0, 0.0)), "{}", grandchild.borrow().get_world_pos());
+    }
+
+    #[test]
+    fn set_world_pos_goes_through_the_parent() {
+        let parent = GameObject::create_empty("parent".to_owned(), None);
+        let child = GameObject::create_empty("child".to_owned(), Some(parent.clone()));
+        parent.borrow_mut().set_pos(Vector3::new(1.0, 2.0, 0.0));
+        parent.borrow_mut().set_scale(Vector3::new(2.0, 2.0, 1.0));
+
+        child.borrow_mut().set_world_pos(Vector3::new(3.0, 3.0, 0.0));
+
+        assert!(close(child.borrow().get_pos(), Vector3::new(1.0, 0.5, 0.0)));
+        assert!(close(child.borrow().get_world_pos(), Vector3::new(3.0, 3.0, 0.0)));
+    }
+
+    #[test]
+    fn collider_hitbox_follows_the_parent() {
+        let mut engine = Engine::init_headless();
+        let parent = GameObject::create_empty("parent".to_owned(), Some(engine.get_root_object()));
+        let child = GameObject::create_empty("child".to_owned(), Some(parent.clone()));
+        child.borrow_mut().set_pos(Vector3::new(0.5, 0.0, 0.0));
+        child.borrow_mut().add_component(Collider::new(Polygon::new(Vector2::ZERO, vec![(-0.25, 0.25).into(), (0.25, 0.25).into(), (0.25, -0.25).into(), (-0.25, -0.25).into()])));
+
+        // Wakes the collider up so it knows its owner
+        engine.step(0.0);
+        let collider = child.borrow().get_component::<Collider>().unwrap();
+        assert!((collider.borrow().get_world_hitbox().get_points()[0] - Vector2::new(0.25, 0.25)).magnitude() < 1e-5);
+
+        parent.borrow_mut().set_pos(Vector3::new(0.0, 1.0, 0.0));
+        let points = collider.borrow().get_world_hitbox().get_points();
+        assert!((points[0] - Vector2::new(0.25, 1.25)).magnitude() < 1e-5);
+        assert!((points[2] - Vector2::new(0.75, 0.75)).magnitude() < 1e-5);
+    }
+}
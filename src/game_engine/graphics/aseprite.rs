@@ -0,0 +1,368 @@
+use std::path::Path;
+
+use image::RgbaImage;
+
+use super::atlas::{SpriteAtlas, SpriteFrame, open_image};
+use crate::game_engine::Vector2;
+use crate::game_engine::animation::{AnimationClip, AnimationFrame, PlaybackMode};
+use crate::game_engine::err::EngineError;
+use crate::game_engine::json::JsonValue;
+
+/// A frame as it appears in the export, before it is turned into a `SpriteFrame`.
+struct ExportedFrame {
+    name: String,
+    rect: (u32, u32, u32, u32),
+    /// Where the trimmed rect sits inside the untrimmed frame
+    source_offset: (u32, u32),
+    source_size: (u32, u32),
+    /// Seconds
+    duration: f32
+}
+
+fn error(message: &str) -> EngineError {
+    format!("Aseprite JSON: {}", message).into()
+}
+
+fn get_u32(v: &JsonValue, key: &str) -> Option<u32> {
+    v.get(key).and_then(JsonValue::as_f64).map(|n| n as u32)
+}
+
+fn get_rect(v: &JsonValue) -> Option<(u32, u32, u32, u32)> {
+    Some((get_u32(v, "x")?, get_u32(v, "y")?, get_u32(v, "w")?, get_u32(v, "h")?))
+}
+
+fn parse_frame(name: &str, v: &JsonValue) -> Result<ExportedFrame, EngineError> {
+    if v.get("rotated").and_then(JsonValue::as_bool) == Some(true) {
+        return Err(error(&format!("frame \"{}\" is rotated, which isn't supported", name)));
+    }
+
+    let rect = v.get("frame").and_then(get_rect);
+    if rect.is_none() {
+        return Err(error(&format!("frame \"{}\" has no valid \"frame\" rect", name)));
+    }
+
+    let rect = rect.unwrap();
+    let source_offset = v.get("spriteSourceSize").and_then(|s| Some((get_u32(s, "x")?, get_u32(s, "y")?))).unwrap_or((0, 0));
+    let source_size = v.get("sourceSize").and_then(|s| Some((get_u32(s, "w")?, get_u32(s, "h")?))).unwrap_or((rect.2, rect.3));
+    let duration = v.get("duration").and_then(JsonValue::as_f64).unwrap_or(100.0) as f32 / 1000.0;
+
+    Ok(ExportedFrame { name: name.to_owned(), rect, source_offset, source_size, duration })
+}
+
+/// Slice that sets the pivot of every frame not covered by a more specific one.
+const PIVOT_SLICE: &str = "pivot";
+
+/// Pivot in untrimmed frame pixels from the key of `slice` at or before `frame`.
+fn key_pivot(slice: &JsonValue, frame: u32) -> Option<(f32, f32)> {
+    let keys = slice.get("keys").and_then(JsonValue::as_array).unwrap_or(&[]);
+
+    // Slice keys apply from their frame until the next key
+    let key = keys.iter()
+        .filter(|k| get_u32(k, "frame").is_some_and(|f| f <= frame))
+        .max_by_key(|k| get_u32(k, "frame"))?;
+
+    let bounds = key.get("bounds")?;
+    let pivot = key.get("pivot")?;
+
+    let bx = get_u32(bounds, "x").unwrap_or(0) as f32;
+    let by = get_u32(bounds, "y").unwrap_or(0) as f32;
+
+    Some((bx + get_u32(pivot, "x").unwrap_or(0) as f32, by + get_u32(pivot, "y").unwrap_or(0) as f32))
+}
+
+/// Pivot for `frame` from the slice named after the first of `tags` that has one, or else from `PIVOT_SLICE`. Slices
+/// with any other name are left alone.
+fn slice_pivot(slices: &[JsonValue], tags: &[&str], frame: u32) -> Option<(f32, f32)> {
+    tags.iter().chain(std::iter::once(&PIVOT_SLICE)).find_map(|name| {
+        let slice = slices.iter().find(|s| s.get("name").and_then(JsonValue::as_str) == Some(*name))?;
+
+        key_pivot(slice, frame)
+    })
+}
+
+/// Names of the tags that cover `frame`, in file order.
+fn frame_tags(tags: &[JsonValue], frame: u32) -> Vec<&str> {
+    tags.iter()
+        .filter(|t| get_u32(t, "from").is_some_and(|f| f <= frame) && get_u32(t, "to").is_some_and(|t| frame <= t))
+        .filter_map(|t| t.get("name").and_then(JsonValue::as_str))
+        .collect()
+}
+
+fn parse_tag(tag: &JsonValue, frames: &[ExportedFrame]) -> Result<AnimationClip, EngineError> {
+    let name = tag.get("name").and_then(JsonValue::as_str);
+    let from = get_u32(tag, "from");
+    let to = get_u32(tag, "to");
+
+    if name.is_none() || from.is_none() || to.is_none() {
+        return Err(error("frame tags need a name, from and to"));
+    }
+
+    let (name, from, to) = (name.unwrap(), from.unwrap() as usize, to.unwrap() as usize);
+    if from > to || to >= frames.len() {
+        return Err(error(&format!("tag \"{}\" covers frames {}-{}, but there are only {} frames", name, from, to, frames.len())));
+    }
+
+    let mut clip_frames: Vec<AnimationFrame> = (from..=to).map(|i| AnimationFrame { sprite_id: i as i32 + 1, duration: frames[i].duration }).collect();
+
+    let direction = tag.get("direction").and_then(JsonValue::as_str).unwrap_or("forward");
+    let mut mode = match direction {
+        "forward" | "reverse" => PlaybackMode::Loop,
+        "pingpong" | "pingpong_reverse" => PlaybackMode::PingPong,
+        _ => return Err(error(&format!("tag \"{}\" has unknown direction \"{}\"", name, direction)))
+    };
+
+    if direction.ends_with("reverse") {
+        clip_frames.reverse();
+    }
+
+    // Newer versions of Aseprite export the repeat count as a string. Anything but a single pass keeps looping.
+    if tag.get("repeat").and_then(JsonValue::as_str) == Some("1") {
+        mode = PlaybackMode::Once;
+    }
+
+    Ok(AnimationClip::new(name, clip_frames, mode))
+}
+
+impl SpriteAtlas {
+    /// Loads a sheet exported with Aseprite's "Export Sprite Sheet". The image is found through `meta.image`, relative to the JSON file.
+    ///
+    /// Frames are named after their `filename`, so export with an item filename like `{tag}_{tagframe}` to get names like
+    /// `"player_idle_0"`. Frame tags become animation clips. Frame pivots come from the pivot of a slice named after the
+    /// frame's tag, or else of a slice named `"pivot"`, and default to the center of the untrimmed frame.
+    pub fn load_aseprite<P: AsRef<Path>>(json_path: P) -> Result<SpriteAtlas, EngineError> {
+        let json_path = json_path.as_ref();
+
+        let json = std::fs::read_to_string(json_path);
+        if json.is_err() {
+            return Err(format!("Failed to read {}: {}", json_path.display(), json.err().unwrap()).into());
+        }
+
+        let json = json.unwrap();
+        let doc = JsonValue::parse(&json);
+        if doc.is_err() {
+            return Err(doc.err().unwrap());
+        }
+
+        let image_name = doc.unwrap().get("meta").and_then(|m| m.get("image")).and_then(JsonValue::as_str).map(|s| s.to_owned());
+        if image_name.is_none() {
+            return Err(error("missing meta.image"));
+        }
+
+        let image_path = json_path.parent().unwrap_or(Path::new("")).join(image_name.unwrap());
+        let image = open_image(image_path);
+        if image.is_err() {
+            return Err(image.err().unwrap());
+        }
+
+        SpriteAtlas::from_aseprite(&json, image.unwrap())
+    }
+
+    /// Builds an atlas from the contents of an Aseprite JSON export and its already loaded image.
+    /// Both the "Hash" and "Array" frame layouts are accepted.
+    pub fn from_aseprite(json: &str, image: RgbaImage) -> Result<SpriteAtlas, EngineError> {
+        let doc = JsonValue::parse(json);
+        if doc.is_err() {
+            return Err(doc.err().unwrap());
+        }
+
+        let doc = doc.unwrap();
+        let mut exported = Vec::new();
+
+        match doc.get("frames") {
+            Some(JsonValue::Object(fields)) => {
+                for (name, v) in fields {
+                    let frame = parse_frame(name, v);
+                    if frame.is_err() {
+                        return Err(frame.err().unwrap());
+                    }
+
+                    exported.push(frame.unwrap());
+                }
+            },
+            Some(JsonValue::Array(items)) => {
+                for v in items {
+                    let name = v.get("filename").and_then(JsonValue::as_str);
+                    if name.is_none() {
+                        return Err(error("frame is missing its filename"));
+                    }
+
+                    let frame = parse_frame(name.unwrap(), v);
+                    if frame.is_err() {
+                        return Err(frame.err().unwrap());
+                    }
+
+                    exported.push(frame.unwrap());
+                }
+            },
+            _ => return Err(error("missing frames"))
+        }
+
+        let meta = doc.get("meta");
+        let slices = meta.and_then(|m| m.get("slices")).and_then(JsonValue::as_array).unwrap_or(&[]);
+        let tags = meta.and_then(|m| m.get("frameTags")).and_then(JsonValue::as_array).unwrap_or(&[]);
+
+        let frames = exported.iter().enumerate().map(|(i, f)| {
+            let (ox, oy) = (f.source_offset.0 as f32, f.source_offset.1 as f32);
+            let (px, py) = slice_pivot(slices, &frame_tags(tags, i as u32), i as u32).unwrap_or((f.source_size.0 as f32 / 2.0, f.source_size.1 as f32 / 2.0));
+
+            // Aseprite pivots are in pixels from the top left of the untrimmed frame, ours are relative to the trimmed rect from the bottom left
+            let pivot = Vector2::new((px - ox) / f.rect.2 as f32, 1.0 - (py - oy) / f.rect.3 as f32);
+
+            SpriteFrame { name: f.name.clone(), rect: f.rect, pivot }
+        }).collect();
+
+        let atlas = SpriteAtlas::new(image, frames);
+        if atlas.is_err() {
+            return Err(atlas.err().unwrap());
+        }
+
+        let mut atlas = atlas.unwrap();
+
+        for tag in tags {
+            let clip = parse_tag(tag, &exported);
+            if clip.is_err() {
+                return Err(clip.err().unwrap());
+            }
+
+            atlas.add_clip(clip.unwrap());
+        }
+
+        Ok(atlas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn atlas(json: &str) -> SpriteAtlas {
+        SpriteAtlas::from_aseprite(json, RgbaImage::new(32, 8)).unwrap()
+    }
+
+    fn pivot(atlas: &SpriteAtlas, name: &str) -> (f32, f32) {
+        let pivot = atlas.get_frame(atlas.get_frame_id(name).unwrap()).unwrap().pivot;
+
+        (pivot.x, pivot.y)
+    }
+
+    /// (sprite id, duration in ms) for every frame of a clip.
+    fn clip_frames(atlas: &SpriteAtlas, name: &str) -> Vec<(i32, u32)> {
+        atlas.get_clip(name).unwrap().frames.iter().map(|f| (f.sprite_id, (f.duration * 1000.0).round() as u32)).collect()
+    }
+
+    /// Four 8x8 frames in a row, lasting 10, 20, 30 and 40 ms.
+    const FOUR_FRAMES: &str = r#"[
+        {"filename": "f0", "frame": {"x": 0, "y": 0, "w": 8, "h": 8}, "duration": 10},
+        {"filename": "f1", "frame": {"x": 8, "y": 0, "w": 8, "h": 8}, "duration": 20},
+        {"filename": "f2", "frame": {"x": 16, "y": 0, "w": 8, "h": 8}, "duration": 30},
+        {"filename": "f3", "frame": {"x": 24, "y": 0, "w": 8, "h": 8}, "duration": 40}
+    ]"#;
+
+    fn with_meta(meta: &str) -> String {
+        format!(r#"{{"frames": {}, "meta": {}}}"#, FOUR_FRAMES, meta)
+    }
+
+    #[test]
+    fn hash_and_array_frames() {
+        let hash = atlas(r#"{"frames": {
+            "b": {"frame": {"x": 8, "y": 0, "w": 8, "h": 8}},
+            "a": {"frame": {"x": 0, "y": 0, "w": 4, "h": 8}, "duration": 250}
+        }}"#);
+        let array = atlas(r#"{"frames": [
+            {"filename": "b", "frame": {"x": 8, "y": 0, "w": 8, "h": 8}},
+            {"filename": "a", "frame": {"x": 0, "y": 0, "w": 4, "h": 8}, "duration": 250}
+        ]}"#);
+
+        for atlas in [hash, array] {
+            // Ids follow file order
+            assert_eq!(atlas.get_frame_id("b"), Some(1));
+            assert_eq!(atlas.get_frame_id("a"), Some(2));
+            assert_eq!(atlas.get_frame(1).unwrap().rect, (8, 0, 8, 8));
+            assert_eq!(atlas.get_frame(2).unwrap().rect, (0, 0, 4, 8));
+            assert_eq!(pivot(&atlas, "a"), (0.5, 0.5));
+        }
+
+        assert!(SpriteAtlas::from_aseprite(r#"{"frames": [{"frame": {"x": 0, "y": 0, "w": 8, "h": 8}}]}"#, RgbaImage::new(8, 8)).is_err());
+        assert!(SpriteAtlas::from_aseprite(r#"{"frames": {"a": {"frame": {"x": 0, "y": 0, "w": 8}}}}"#, RgbaImage::new(8, 8)).is_err());
+        assert!(SpriteAtlas::from_aseprite(r#"{"meta": {}}"#, RgbaImage::new(8, 8)).is_err());
+    }
+
+    #[test]
+    fn tags_become_clips() {
+        let atlas = atlas(&with_meta(r#"{"frameTags": [
+            {"name": "fwd", "from": 0, "to": 2, "direction": "forward"},
+            {"name": "default", "from": 2, "to": 3},
+            {"name": "rev", "from": 1, "to": 3, "direction": "reverse"},
+            {"name": "pp", "from": 0, "to": 3, "direction": "pingpong"},
+            {"name": "pp_rev", "from": 0, "to": 1, "direction": "pingpong_reverse"},
+            {"name": "once", "from": 0, "to": 1, "repeat": "1"},
+            {"name": "twice", "from": 0, "to": 1, "repeat": "2"}
+        ]}"#));
+
+        assert_eq!(clip_frames(&atlas, "fwd"), [(1, 10), (2, 20), (3, 30)]);
+        assert_eq!(atlas.get_clip("fwd").unwrap().mode, PlaybackMode::Loop);
+        assert_eq!(clip_frames(&atlas, "default"), [(3, 30), (4, 40)]);
+        assert_eq!(atlas.get_clip("default").unwrap().mode, PlaybackMode::Loop);
+        assert_eq!(clip_frames(&atlas, "rev"), [(4, 40), (3, 30), (2, 20)]);
+        assert_eq!(atlas.get_clip("rev").unwrap().mode, PlaybackMode::Loop);
+        assert_eq!(clip_frames(&atlas, "pp"), [(1, 10), (2, 20), (3, 30), (4, 40)]);
+        assert_eq!(atlas.get_clip("pp").unwrap().mode, PlaybackMode::PingPong);
+        assert_eq!(clip_frames(&atlas, "pp_rev"), [(2, 20), (1, 10)]);
+        assert_eq!(atlas.get_clip("pp_rev").unwrap().mode, PlaybackMode::PingPong);
+        assert_eq!(atlas.get_clip("once").unwrap().mode, PlaybackMode::Once);
+        assert_eq!(atlas.get_clip("twice").unwrap().mode, PlaybackMode::Loop);
+    }
+
+    #[test]
+    fn bad_tags_are_rejected() {
+        let bad = [
+            r#"{"frameTags": [{"name": "a", "from": 0, "to": 4}]}"#,
+            r#"{"frameTags": [{"name": "a", "from": 2, "to": 1}]}"#,
+            r#"{"frameTags": [{"name": "a", "from": 0, "to": 1, "direction": "sideways"}]}"#,
+            r#"{"frameTags": [{"from": 0, "to": 1}]}"#
+        ];
+
+        for meta in bad {
+            assert!(SpriteAtlas::from_aseprite(&with_meta(meta), RgbaImage::new(32, 8)).is_err(), "{}", meta);
+        }
+    }
+
+    #[test]
+    fn trimmed_frames_keep_the_untrimmed_pivot() {
+        // A 4x4 rect cut out of an 8x8 frame, 1 from the left and 2 from the top. The untrimmed center (4, 4) is 3 pixels
+        // into the rect from the left and 2 from the top.
+        let atlas = atlas(r#"{"frames": {"t": {
+            "frame": {"x": 0, "y": 0, "w": 4, "h": 4},
+            "trimmed": true,
+            "spriteSourceSize": {"x": 1, "y": 2, "w": 4, "h": 4},
+            "sourceSize": {"w": 8, "h": 8}
+        }}}"#);
+
+        assert_eq!(pivot(&atlas, "t"), (0.75, 0.5));
+    }
+
+    #[test]
+    fn slice_pivots() {
+        // "hitbox" is ignored, "run" only applies to the run tag, and "pivot" to everything else with a key per frame range
+        let atlas = atlas(&with_meta(r#"{
+            "frameTags": [{"name": "idle", "from": 0, "to": 0}, {"name": "run", "from": 1, "to": 1}],
+            "slices": [
+                {"name": "hitbox", "keys": [{"frame": 0, "bounds": {"x": 0, "y": 0, "w": 8, "h": 8}, "pivot": {"x": 0, "y": 0}}]},
+                {"name": "pivot", "keys": [
+                    {"frame": 0, "bounds": {"x": 0, "y": 0, "w": 8, "h": 8}, "pivot": {"x": 4, "y": 8}},
+                    {"frame": 2, "bounds": {"x": 0, "y": 0, "w": 8, "h": 8}, "pivot": {"x": 0, "y": 0}}
+                ]},
+                {"name": "run", "keys": [{"frame": 0, "bounds": {"x": 2, "y": 0, "w": 4, "h": 8}, "pivot": {"x": 0, "y": 4}}]},
+                {"name": "idle", "keys": [{"frame": 0, "bounds": {"x": 0, "y": 0, "w": 8, "h": 8}}]}
+            ]
+        }"#));
+
+        // Bottom center from "pivot", since the "idle" slice has no pivot
+        assert_eq!(pivot(&atlas, "f0"), (0.5, 0.0));
+        // Bounds offset plus pivot from "run"
+        assert_eq!(pivot(&atlas, "f1"), (0.25, 0.5));
+        // Untagged, from the second "pivot" key, which also covers the frames after it
+        assert_eq!(pivot(&atlas, "f2"), (0.0, 1.0));
+        assert_eq!(pivot(&atlas, "f3"), (0.0, 1.0));
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+
+use image::RgbaImage;
+
+use super::SpriteInfo;
+use crate::game_engine::animation::AnimationClip;
+use super::shader::decode_texture;
+use crate::game_engine::Vector2;
+use crate::game_engine::err::EngineError;
+
+/// A named region of the atlas image.
+#[derive(Clone, Debug)]
+pub struct SpriteFrame {
+    pub name: String,
+    /// Pixel rectangle as (x, y, w, h), with y measured from the top of the image.
+    pub rect: (u32, u32, u32, u32),
+    /// Point the sprite is positioned and rotated around, from (0, 0) at the bottom left to (1, 1) at the top right.
+    pub pivot: Vector2
+}
+
+/// A sprite sheet image plus the frames cut out of it. Sprite ids index into the frames starting at 1, since 0 means "no sprite".
+pub struct SpriteAtlas {
+    image: RgbaImage,
+    frames: Vec<SpriteFrame>,
+    ids: HashMap<String, i32>,
+    clips: Vec<AnimationClip>
+}
+
+pub(super) fn open_image<P: AsRef<Path>>(path: P) -> Result<RgbaImage, EngineError> {
+    let f = File::open(path.as_ref());
+
+    if f.is_err() {
+        return Err(format!("Failed to open {}: {}", path.as_ref().display(), f.err().unwrap()).into());
+    }
+
+    match decode_texture(f.unwrap()) {
+        Ok(img) => Ok(img),
+        Err(e) => Err(format!("{}: {}", path.as_ref().display(), e.get_error_message()).into())
+    }
+}
+
+fn parse_num<T: std::str::FromStr>(s: &str, line: usize) -> Result<T, EngineError> {
+    match s.parse() {
+        Ok(v) => Ok(v),
+        Err(_) => Err(format!("Atlas line {}: \"{}\" is not a valid number", line, s).into())
+    }
+}
+
+impl SpriteAtlas {
+    pub fn new(image: RgbaImage, frames: Vec<SpriteFrame>) -> Result<SpriteAtlas, EngineError> {
+        let mut ids = HashMap::new();
+
+        for (i, frame) in frames.iter().enumerate() {
+            let (x, y, w, h) = frame.rect;
+
+            if x + w > image.width() || y + h > image.height() {
+                return Err(format!("Sprite frame \"{}\" is outside of the {}x{} atlas image", frame.name, image.width(), image.height()).into());
+            }
+
+            if ids.insert(frame.name.clone(), i as i32 + 1).is_some() {
+                return Err(format!("Duplicate sprite frame \"{}\"", frame.name).into());
+            }
+        }
+
+        Ok(SpriteAtlas { image, frames, ids, clips: Vec::new() })
+    }
+
+    /// Loads an atlas from an image and a metadata file. Each non-empty line of the metadata that doesn't start with `#`
+    /// describes one frame:
+    ///
+    /// `name x y w h [pivot_x pivot_y]`
+    ///
+    /// Rectangles are in pixels from the top left of the image. The pivot defaults to the center (0.5, 0.5).
+    pub fn load<P: AsRef<Path>, Q: AsRef<Path>>(image_path: P, metadata_path: Q) -> Result<SpriteAtlas, EngineError> {
+        let image = open_image(image_path);
+        if image.is_err() {
+            return Err(image.err().unwrap());
+        }
+
+        let metadata = std::fs::read_to_string(metadata_path.as_ref());
+        if metadata.is_err() {
+            return Err(format!("Failed to read {}: {}", metadata_path.as_ref().display(), metadata.err().unwrap()).into());
+        }
+
+        let frames = SpriteAtlas::parse_metadata(&metadata.unwrap());
+        if frames.is_err() {
+            return Err(frames.err().unwrap());
+        }
+
+        SpriteAtlas::new(image.unwrap(), frames.unwrap())
+    }
+
+    pub fn parse_metadata(metadata: &str) -> Result<Vec<SpriteFrame>, EngineError> {
+        let mut frames = Vec::new();
+
+        for (i, line) in metadata.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() != 5 && parts.len() != 7 {
+                return Err(format!("Atlas line {}: expected \"name x y w h [pivot_x pivot_y]\"", line_number).into());
+            }
+
+            let mut rect = [0u32; 4];
+            for j in 0..4 {
+                let v = parse_num(parts[j + 1], line_number);
+                if v.is_err() {
+                    return Err(v.err().unwrap());
+                }
+
+                rect[j] = v.unwrap();
+            }
+
+            let mut pivot = Vector2::new(0.5, 0.5);
+            if parts.len() == 7 {
+                let x = parse_num(parts[5], line_number);
+                let y = parse_num(parts[6], line_number);
+
+                if x.is_err() {
+                    return Err(x.err().unwrap());
+                }
+                if y.is_err() {
+                    return Err(y.err().unwrap());
+                }
+
+                pivot = Vector2::new(x.unwrap(), y.unwrap());
+            }
+
+            frames.push(SpriteFrame { name: parts[0].to_owned(), rect: (rect[0], rect[1], rect[2], rect[3]), pivot });
+        }
+
+        Ok(frames)
+    }
+
+    pub fn get_image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    pub fn get_frames(&self) -> &[SpriteFrame] {
+        &self.frames
+    }
+
+    pub fn add_clip(&mut self, clip: AnimationClip) {
+        self.clips.retain(|c| c.name != clip.name);
+        self.clips.push(clip);
+    }
+
+    /// Points every clip at atlas `id`. Called when the atlas is added to `Graphics`.
+    pub(super) fn set_clip_atlas(&mut self, id: usize) {
+        for clip in self.clips.iter_mut() {
+            clip.atlas = id;
+        }
+    }
+
+    pub fn get_clip(&self, name: &str) -> Option<&AnimationClip> {
+        self.clips.iter().find(|c| c.name == name)
+    }
+
+    pub fn get_clips(&self) -> &[AnimationClip] {
+        &self.clips
+    }
+
+    /// Looks up the sprite id for a named frame.
+    pub fn get_frame_id(&self, name: &str) -> Option<i32> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn get_frame(&self, sprite_id: i32) -> Option<&SpriteFrame> {
+        if sprite_id < 1 {
+            return None;
+        }
+
+        self.frames.get(sprite_id as usize - 1)
+    }
+
+    /// UV rectangles indexed by sprite id. Entry 0 is empty.
+    pub fn get_sprite_infos(&self) -> Vec<SpriteInfo> {
+        let w = self.image.width() as f32;
+        let h = self.image.height() as f32;
+
+        let mut infos = vec![SpriteInfo { u: 0.0, v: 0.0, w: 0.0, h: 0.0 }];
+        infos.extend(self.frames.iter().map(|f| {
+            SpriteInfo { u: f.rect.0 as f32 / w, v: f.rect.1 as f32 / h, w: f.rect.2 as f32 / w, h: f.rect.3 as f32 / h }
+        }));
+
+        infos
+    }
+
+    /// Pivots indexed by sprite id. Entry 0 is the center.
+    pub fn get_pivots(&self) -> Vec<Vector2> {
+        let mut pivots = vec![Vector2::new(0.5, 0.5)];
+        pivots.extend(self.frames.iter().map(|f| f.pivot));
+
+        pivots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn non_images_are_errors() {
+        // This source file is certainly not an image
+        let result = open_image(file!());
+
+        assert!(result.is_err());
+        assert!(result.err().unwrap().get_error_message().contains(file!()));
+    }
+
+    #[test]
+    fn metadata_lines() {
+        let frames = SpriteAtlas::parse_metadata("# comment\n\nidle 0 0 16 16\n  run  16 0 16 8 0.5 0 \n").unwrap();
+
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].name, "idle");
+        assert_eq!(frames[0].rect, (0, 0, 16, 16));
+        assert_eq!((frames[0].pivot.x, frames[0].pivot.y), (0.5, 0.5));
+        assert_eq!(frames[1].name, "run");
+        assert_eq!(frames[1].rect, (16, 0, 16, 8));
+        assert_eq!((frames[1].pivot.x, frames[1].pivot.y), (0.5, 0.0));
+    }
+
+    #[test]
+    fn malformed_metadata_is_rejected() {
+        let bad = [
+            "idle 0 0 16",
+            "idle 0 0 16 16 0.5",
+            "idle 0 0 16 16 0.5 0.5 1",
+            "idle 0 0 -16 16",
+            "idle 0 0 16 sixteen",
+            "idle 0 0 16 16 0.5 top"
+        ];
+
+        for metadata in bad {
+            assert!(SpriteAtlas::parse_metadata(metadata).is_err(), "{}", metadata);
+        }
+
+        // The error points at the offending line
+        let err = SpriteAtlas::parse_metadata("idle 0 0 16 16\n\nrun 0 0 x 16").err().unwrap();
+        assert!(err.get_error_message().starts_with("Atlas line 3"), "{}", err.get_error_message());
+    }
+
+    #[test]
+    fn ids_start_at_one_and_names_are_unique() {
+        let image = RgbaImage::new(32, 16);
+        let frames = SpriteAtlas::parse_metadata("a 0 0 16 16\nb 16 0 16 16").unwrap();
+        let atlas = SpriteAtlas::new(image.clone(), frames).unwrap();
+
+        assert_eq!(atlas.get_frame_id("a"), Some(1));
+        assert_eq!(atlas.get_frame_id("b"), Some(2));
+        assert_eq!(atlas.get_frame(1).unwrap().name, "a");
+        assert!(atlas.get_frame(0).is_none());
+        assert_eq!(atlas.get_sprite_infos().len(), 3);
+
+        let frames = SpriteAtlas::parse_metadata("a 0 0 16 16\na 16 0 16 16").unwrap();
+        assert!(SpriteAtlas::new(image.clone(), frames).is_err());
+
+        let frames = SpriteAtlas::parse_metadata("a 24 0 16 16").unwrap();
+        assert!(SpriteAtlas::new(image, frames).is_err());
+    }
+}
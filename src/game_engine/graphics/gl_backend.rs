@@ -0,0 +1,595 @@
+use glfw::{WindowEvent, Context};
+
+use gl33::global_loader::*;
+use gl33::gl_enumerations::*;
+
+use std::cell::RefCell;
+use std::os::raw::c_void;
+use std::ptr::addr_of_mut;
+use std::sync::mpsc::Receiver;
+
+use core::mem::{size_of, size_of_val};
+
+use libc::strlen;
+
+use super::shader::*;
+use super::{RenderBackend, FrameData, TerrainVertex, SpriteVertex, Graphics, GLFW, VERT_SHADER, FRAG_SHADER, SPRITE_VERT_SHADER, SPRITE_FRAG_SHADER, SpriteInstance, SPRITE_VERTICIES};
+use super::atlas::SpriteAtlas;
+use super::window::{WindowConfig, WindowMode};
+use crate::game_engine::err::EngineError;
+
+/// Renders through OpenGL into a GLFW window.
+pub struct GlBackend {
+    window: glfw::Window,
+    events: Receiver<(f64, glfw::WindowEvent)>,
+    terrain_shader: Shader,
+    sprite_shader: Shader,
+    sprite_vbo: u32,
+    sprite_vao: u32,
+    instance_vbo: u32,
+    instance_capacity: usize,
+    terrain_vbo: u32,
+    terrain_vao: u32,
+    terrain_vertex_count: i32,
+    atlases: Vec<GlAtlas>,
+    mode: WindowMode,
+    /// Position and size to go back to when switching to windowed mode
+    windowed_rect: (i32, i32, u32, u32)
+}
+
+struct GlAtlas {
+    texture: u32,
+    /// Buffer texture the sprite shader looks frames up in, see `create_frame_table`
+    frame_table: u32,
+    frame_table_buffer: u32
+}
+
+fn get_proc_address(window: &mut glfw::Window, fn_name: *const u8) -> *const c_void {
+    // The fact that I need to do ths is bullshit, but I don't see any way around it.
+    let fn_name_slice = std::str::from_utf8(unsafe {std::slice::from_raw_parts(fn_name, strlen(fn_name as *const i8))}).unwrap();
+
+    // I'm pretty sure this function shouldn't atually be mutating anything idk why the library wants a &mut self
+    window.get_proc_address(fn_name_slice)
+}
+
+/// Uploads the UV rect and pivot of every frame in `atlas` to a buffer texture, as two RGBA texels per frame. Unlike a
+/// uniform array this isn't limited to a few hundred frames, and the shader doesn't depend on the atlas size.
+/// Returns the texture and the buffer behind it.
+unsafe fn create_frame_table(atlas: &SpriteAtlas) -> (u32, u32) {
+    let mut texels: Vec<[f32; 4]> = Vec::new();
+    for (info, pivot) in atlas.get_sprite_infos().iter().zip(atlas.get_pivots()) {
+        texels.push([info.u, info.v, info.w, info.h]);
+        texels.push([pivot.x, pivot.y, 0.0, 0.0]);
+    }
+
+    let mut buffer: u32 = 0;
+    glGenBuffers(1, &mut buffer);
+    glBindBuffer(GL_TEXTURE_BUFFER, buffer);
+    glBufferData(
+        GL_TEXTURE_BUFFER,
+        (texels.len() * size_of::<[f32; 4]>()) as isize,
+        texels.as_ptr().cast(),
+        GL_STATIC_DRAW,
+    );
+
+    let mut texture: u32 = 0;
+    glGenTextures(1, &mut texture);
+    glBindTexture(GL_TEXTURE_BUFFER, texture);
+    glTexBuffer(GL_TEXTURE_BUFFER, GL_RGBA32F, buffer);
+
+    glBindTexture(GL_TEXTURE_BUFFER, 0);
+    glBindBuffer(GL_TEXTURE_BUFFER, 0);
+
+    (texture, buffer)
+}
+
+/// Points the per-instance attributes of the bound VAO at `first_instance` in the bound instance buffer.
+/// GL 3.3 has no base instance for draws, so this is how batches after the first are drawn.
+unsafe fn point_instance_attributes(first_instance: usize) {
+    let stride = size_of::<SpriteInstance>() as i32;
+    let base = first_instance * stride as usize;
+
+    // Rect, transform and tint
+    for (location, offset) in [(2, 0), (3, 16), (4, 32)] {
+        glVertexAttribPointer(
+            location,
+            4,
+            GL_FLOAT,
+            0,
+            stride,
+            (base + offset) as *const _,
+        );
+    }
+
+    // Sprite id
+    glVertexAttribIPointer(
+        5,
+        1,
+        GL_INT,
+        stride,
+        (base + 48) as *const _,
+    );
+
+    // Depth
+    glVertexAttribPointer(
+        6,
+        1,
+        GL_FLOAT,
+        0,
+        stride,
+        (base + 52) as *const _,
+    );
+}
+
+/// Position and size covering the whole of `monitor`, and its refresh rate.
+fn monitor_rect(monitor: &glfw::Monitor) -> Option<(i32, i32, u32, u32, u32)> {
+    let (x, y) = monitor.get_pos();
+
+    monitor.get_video_mode().map(|mode| (x, y, mode.width, mode.height, mode.refresh_rate))
+}
+
+impl GlBackend {
+    pub fn create_window(config: &WindowConfig) -> Result<GlBackend, EngineError> {
+        if !Graphics::gl_initialized() {
+            return Err("GL not initialized!".into());
+        }
+
+        let glfw = unsafe {(*addr_of_mut!(GLFW)).assume_init_mut()};
+
+        glfw.window_hint(glfw::WindowHint::Resizable(config.resizable));
+        glfw.window_hint(glfw::WindowHint::Decorated(config.mode != WindowMode::Borderless));
+
+        // Create the window and its OpenGL context
+        let created = glfw.with_connected_monitors(|glfw, monitors| {
+            let monitor = monitors.get(config.monitor);
+            let rect = monitor.and_then(monitor_rect);
+
+            if config.mode != WindowMode::Windowed && rect.is_none() {
+                return Err(format!("Monitor {} is not connected ({} found).", config.monitor, monitors.len()));
+            }
+
+            let op = match config.mode {
+                WindowMode::Windowed => glfw.create_window(config.width, config.height, &config.title, glfw::WindowMode::Windowed),
+                WindowMode::Fullscreen => {
+                    let (_, _, w, h, _) = rect.unwrap();
+                    glfw.create_window(w, h, &config.title, glfw::WindowMode::FullScreen(monitor.unwrap()))
+                },
+                WindowMode::Borderless => {
+                    let (_, _, w, h, _) = rect.unwrap();
+                    glfw.create_window(w, h, &config.title, glfw::WindowMode::Windowed)
+                }
+            };
+
+            if op.is_none() {
+                return Err("Failed to create GLFW window.".to_owned());
+            }
+
+            let (mut window, events) = op.unwrap();
+
+            // Windowed goes in the middle of the chosen monitor, borderless covers it
+            if let Some((x, y, w, h, _)) = rect {
+                match config.mode {
+                    WindowMode::Windowed => window.set_pos(x + (w as i32 - config.width as i32) / 2, y + (h as i32 - config.height as i32) / 2),
+                    WindowMode::Borderless => window.set_pos(x, y),
+                    WindowMode::Fullscreen => {}
+                }
+            }
+
+            Ok((window, events))
+        });
+
+        if created.is_err() {
+            return Err(created.err().unwrap().into());
+        }
+
+        let (mut window, events) = created.unwrap();
+        let (x, y) = window.get_pos();
+        let windowed_rect = (x, y, config.width, config.height);
+
+        // Make the window's context current
+        window.make_current();
+        glfw.set_swap_interval(if config.vsync { glfw::SwapInterval::Sync(1) } else { glfw::SwapInterval::None });
+
+        window.set_key_polling(true);
+        window.set_mouse_button_polling(true);
+        window.set_cursor_pos_polling(true);
+        window.set_scroll_polling(true);
+        // Typed text, after the OS keyboard layout and any IME have had their say
+        window.set_char_polling(true);
+
+        let backend = GlBackend { window,
+            events,
+            terrain_shader: Shader::null_shader(),
+            sprite_shader: Shader::null_shader(),
+            sprite_vbo: 0,
+            sprite_vao: 0,
+            instance_vbo: 0,
+            instance_capacity: 0,
+            terrain_vbo: 0,
+            terrain_vao: 0,
+            terrain_vertex_count: 0,
+            atlases: Vec::new(),
+            mode: config.mode,
+            windowed_rect
+        };
+
+        let backend = RefCell::new(backend);
+
+        // Do gl stuff
+        unsafe {
+            load_global_gl(&|fn_name| get_proc_address(&mut backend.borrow_mut().window, fn_name));
+            let mut backend = backend.into_inner();
+            backend.terrain_shader = Shader::load_shader_program("Terrain Shader", VERT_SHADER, FRAG_SHADER, &[]);
+
+            glClearColor(0.2, 0.3, 0.3, 1.0);
+
+            backend.sprite_shader = Shader::load_shader_program("Sprite Shader", SPRITE_VERT_SHADER, SPRITE_FRAG_SHADER, &[]);
+
+            let mut vao: u32 = 0;
+            glGenVertexArrays(1, &mut vao);
+            if (vao as i32) < 0 {
+                return Err("Error creaing VAO!".into());
+            }
+
+            glBindVertexArray(vao);
+
+            let mut vbo: u32 = 0;
+            glGenBuffers(1, &mut vbo);
+            if (vbo as i32) < 0 {
+                return Err("Error creaing VBO!".into());
+            }
+
+            backend.terrain_vao = vao;
+            backend.terrain_vbo = vbo;
+
+            glBindBuffer(GL_ARRAY_BUFFER, vbo);
+
+            // Enable pos attribute pointer
+            glVertexAttribPointer(
+                0,
+                3,
+                GL_FLOAT,
+                0,
+                24,
+                std::ptr::null(),
+            );
+            glEnableVertexAttribArray(0);
+
+            // Enable color attribute pointer
+            glVertexAttribPointer(
+                1,
+                3,
+                GL_FLOAT,
+                0,
+                24,
+                12 as *const _,
+            );
+            glEnableVertexAttribArray(1);
+
+            // Create Sprite VAO
+            let mut vao: u32 = 0;
+            glGenVertexArrays(1, &mut vao);
+            if (vao as i32) < 0 {
+                return Err("Error creaing VAO!".into());
+            }
+
+            glBindVertexArray(vao);
+
+            let mut vbo: u32 = 0;
+            glGenBuffers(1, &mut vbo);
+            if (vbo as i32) < 0 {
+                return Err("Error creaing VBO!".into());
+            }
+
+            backend.sprite_vao = vao;
+            backend.sprite_vbo = vbo;
+
+            glBindBuffer(GL_ARRAY_BUFFER, vbo);
+
+            // Enable pos attribute pointer
+            glVertexAttribPointer(
+                0,
+                3,
+                GL_FLOAT,
+                0,
+                20,
+                std::ptr::null(),
+            );
+            glEnableVertexAttribArray(0);
+
+            // Enable uv attribute pointer
+            glVertexAttribPointer(
+                1,
+                3,
+                GL_FLOAT,
+                0,
+                20,
+                12 as *const _,
+            );
+            glEnableVertexAttribArray(1);
+
+            // Send verticies to gpu
+            glBufferData(
+                GL_ARRAY_BUFFER,
+                (SPRITE_VERTICIES.len() * size_of::<SpriteVertex>()) as isize,
+                SPRITE_VERTICIES.as_ptr().cast(),
+                GL_STATIC_DRAW,
+            );
+
+            // Per-instance sprite data lives in its own buffer. It starts empty and grows in render.
+            let mut vbo: u32 = 0;
+            glGenBuffers(1, &mut vbo);
+            if (vbo as i32) < 0 {
+                return Err("Error creaing VBO!".into());
+            }
+
+            backend.instance_vbo = vbo;
+
+            glBindBuffer(GL_ARRAY_BUFFER, vbo);
+
+            // Enable rect, transform, tint, sprite id and depth attribute pointers
+            point_instance_attributes(0);
+
+            for location in 2..=6 {
+                glEnableVertexAttribArray(location);
+                glVertexAttribDivisor(location, 1);
+            }
+
+            glBlendFunc(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA);
+            glEnable( GL_BLEND );
+
+            glBindBuffer(GL_ARRAY_BUFFER, 0);
+            glBindVertexArray(0);
+
+            Ok(backend)
+        }
+    }
+}
+
+impl RenderBackend for GlBackend {
+    fn add_atlas(&mut self, atlas: &SpriteAtlas) {
+        unsafe {
+            let texture = create_texture(atlas.get_image());
+            let (frame_table, frame_table_buffer) = create_frame_table(atlas);
+
+            self.atlases.push(GlAtlas { texture, frame_table, frame_table_buffer });
+        }
+    }
+
+    fn buffer_terrain_verticies(&mut self, verticies: &[TerrainVertex]) {
+        unsafe {
+            glBindVertexArray(self.terrain_vao);
+            glBindBuffer(GL_ARRAY_BUFFER, self.terrain_vbo);
+
+            // Send verticies to gpu
+            glBufferData(
+                GL_ARRAY_BUFFER,
+                size_of_val(verticies) as isize,
+                verticies.as_ptr().cast(),
+                GL_STATIC_DRAW,
+            );
+
+            glBindBuffer(GL_ARRAY_BUFFER, 0);
+            glBindVertexArray(0);
+        }
+
+        self.terrain_vertex_count = verticies.len() as i32;
+    }
+
+    fn render(&mut self, frame: &FrameData) {
+        unsafe {
+            // Black bars outside the viewport, then the clear color inside it
+            let (_, fb_height) = self.window.get_framebuffer_size();
+            let vp = frame.viewport;
+            let vp_y = fb_height - (vp.y + vp.height) as i32;
+
+            glDisable(GL_SCISSOR_TEST);
+            glClearColor(0.0, 0.0, 0.0, 1.0);
+            glClear(GL_COLOR_BUFFER_BIT | GL_DEPTH_BUFFER_BIT);
+
+            glEnable(GL_SCISSOR_TEST);
+            glScissor(vp.x as i32, vp_y, vp.width as i32, vp.height as i32);
+            glViewport(vp.x as i32, vp_y, vp.width as i32, vp.height as i32);
+            glClearColor(0.2, 0.3, 0.3, 1.0);
+            glClear(GL_COLOR_BUFFER_BIT);
+
+            // Get transformation matrix
+            let view_matrix = frame.camera.get_viewmatrix();
+            let ortho = frame.camera.ortho();
+            let t = view_matrix * ortho;
+
+            glBindVertexArray(self.terrain_vao);
+            glBindBuffer(GL_ARRAY_BUFFER, self.terrain_vbo);
+
+            let program = self.terrain_shader.get_program();
+            glUseProgram(program);
+            let loc: i32 = glGetUniformLocation(program, b"transform\0" as *const u8);
+
+            if loc >= 0 {
+                glUniformMatrix4fv(loc, 1, 0, &t.values[0] as *const f32);
+            }
+
+            glDrawArrays(GL_TRIANGLES, 0, self.terrain_vertex_count);
+
+            glBindVertexArray(self.sprite_vao);
+            glBindBuffer(GL_ARRAY_BUFFER, self.sprite_vbo);
+
+            let program = self.sprite_shader.get_program();
+            glUseProgram(program);
+
+            let loc: i32 = glGetUniformLocation(program, b"view_matrix\0" as *const u8);
+
+            if loc >= 0 {
+                glUniformMatrix4fv(loc, 1, 0, &view_matrix.values[0] as *const f32);
+            }
+
+            let loc: i32 = glGetUniformLocation(program, b"projection_matrix\0" as *const u8);
+
+            if loc >= 0 {
+                glUniformMatrix4fv(loc, 1, 0, &ortho.values[0] as *const f32);
+            }
+
+            glActiveTexture(GL_TEXTURE0);
+
+            let loc: i32 = glGetUniformLocation(program, b"MainTex\0" as *const u8);
+
+            if loc >= 0 {
+                glUniform1i(loc, 0);
+            }
+
+            let loc: i32 = glGetUniformLocation(program, b"frame_table\0" as *const u8);
+
+            if loc >= 0 {
+                glUniform1i(loc, 1);
+            }
+
+            glBindBuffer(GL_ARRAY_BUFFER, self.instance_vbo);
+
+            // Grow the instance buffer if there are more sprites than it can hold
+            if frame.sprites.len() > self.instance_capacity {
+                self.instance_capacity = usize::max(frame.sprites.len(), self.instance_capacity * 2);
+
+                glBufferData(
+                    GL_ARRAY_BUFFER,
+                    (self.instance_capacity * size_of::<SpriteInstance>()) as isize,
+                    std::ptr::null(),
+                    GL_DYNAMIC_DRAW,
+                );
+            }
+
+            glBufferSubData(
+                GL_ARRAY_BUFFER,
+                0,
+                size_of_val(frame.sprites) as isize,
+                frame.sprites.as_ptr().cast(),
+            );
+
+            let cutoff_loc: i32 = glGetUniformLocation(program, b"alpha_cutoff\0" as *const u8);
+
+            if frame.depth_buffer {
+                glEnable(GL_DEPTH_TEST);
+                glDepthFunc(GL_LESS);
+            }
+
+            // One draw call per batch, each with its own texture and frame table
+            for batch in frame.batches {
+                let atlas = match self.atlases.get(batch.atlas) {
+                    Some(atlas) => atlas,
+                    None => continue
+                };
+
+                glActiveTexture(GL_TEXTURE1);
+                glBindTexture(GL_TEXTURE_BUFFER, atlas.frame_table);
+                glActiveTexture(GL_TEXTURE0);
+                glBindTexture(GL_TEXTURE_2D, atlas.texture);
+
+                // Opaque sprites write depth and cut out their transparent pixels. The rest only test against them.
+                glDepthMask(batch.opaque as u8);
+
+                if cutoff_loc >= 0 {
+                    glUniform1f(cutoff_loc, if batch.opaque { 0.5 } else { 0.0 });
+                }
+
+                point_instance_attributes(batch.start);
+                glDrawArraysInstanced(GL_TRIANGLES, 0, 6, batch.count as i32);
+            }
+
+            // The depth mask also applies to glClear
+            glDepthMask(1);
+            glDisable(GL_DEPTH_TEST);
+            glDisable(GL_SCISSOR_TEST);
+        }
+    }
+
+    fn window_alive(&self) -> bool {
+        !self.window.should_close()
+    }
+
+    fn close_window(&mut self) {
+        self.window.set_should_close(true);
+    }
+
+    fn get_window_events(&mut self) -> Vec<(f64, WindowEvent)> {
+        let glfw = unsafe {(*addr_of_mut!(GLFW)).assume_init_mut()};
+
+        glfw.poll_events();
+        glfw::flush_messages(&self.events).collect()
+    }
+
+    fn swap_window_buffers(&mut self) {
+        self.window.swap_buffers();
+    }
+
+    fn get_window_size(&self) -> (u32, u32) {
+        let (w, h) = self.window.get_size();
+
+        (w as u32, h as u32)
+    }
+
+    fn get_framebuffer_size(&self) -> (u32, u32) {
+        let (w, h) = self.window.get_framebuffer_size();
+
+        (w as u32, h as u32)
+    }
+
+    fn set_window_mode(&mut self, mode: WindowMode, monitor: usize) -> Result<(), EngineError> {
+        let glfw = unsafe {(*addr_of_mut!(GLFW)).assume_init_mut()};
+
+        if self.mode == WindowMode::Windowed {
+            let (x, y) = self.window.get_pos();
+            let (w, h) = self.window.get_size();
+
+            self.windowed_rect = (x, y, w as u32, h as u32);
+        }
+
+        let window = &mut self.window;
+        let windowed_rect = self.windowed_rect;
+
+        let result = glfw.with_connected_monitors(|_, monitors| {
+            let chosen = monitors.get(monitor);
+            let rect = chosen.and_then(monitor_rect);
+
+            if mode != WindowMode::Windowed && rect.is_none() {
+                return Err(format!("Monitor {} is not connected ({} found).", monitor, monitors.len()));
+            }
+
+            match mode {
+                WindowMode::Windowed => {
+                    let (x, y, w, h) = windowed_rect;
+
+                    window.set_decorated(true);
+                    window.set_monitor(glfw::WindowMode::Windowed, x, y, w, h, None);
+                },
+                WindowMode::Fullscreen => {
+                    let (_, _, w, h, refresh_rate) = rect.unwrap();
+
+                    window.set_monitor(glfw::WindowMode::FullScreen(chosen.unwrap()), 0, 0, w, h, Some(refresh_rate));
+                },
+                WindowMode::Borderless => {
+                    let (x, y, w, h, _) = rect.unwrap();
+
+                    window.set_decorated(false);
+                    window.set_monitor(glfw::WindowMode::Windowed, x, y, w, h, None);
+                }
+            }
+
+            Ok(())
+        });
+
+        if result.is_err() {
+            return Err(result.err().unwrap().into());
+        }
+
+        self.mode = mode;
+
+        Ok(())
+    }
+
+    fn get_clipboard(&self) -> Option<String> {
+        self.window.get_clipboard_string()
+    }
+
+    fn set_clipboard(&mut self, text: &str) {
+        self.window.set_clipboard_string(text);
+    }
+}
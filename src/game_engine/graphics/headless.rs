@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+use std::time::Instant;
+
+use glfw::WindowEvent;
+
+use super::{RenderBackend, FrameData, TerrainVertex, SpriteBatch};
+use super::atlas::SpriteAtlas;
+use super::window::{Viewport, WindowMode};
+use crate::game_engine::{Sprite, matrix::Mat4x4};
+use crate::game_engine::err::EngineError;
+
+/// Snapshot of everything that was sent to the backend for a single frame.
+#[derive(Clone, Debug)]
+pub struct RecordedFrame {
+    pub viewport: Viewport,
+    pub view_matrix: Mat4x4,
+    pub projection_matrix: Mat4x4,
+    /// Sprites in draw order.
+    pub sprites: Vec<Sprite>,
+    /// One entry per draw call.
+    pub batches: Vec<SpriteBatch>
+}
+
+impl RecordedFrame {
+    /// Returns the sprites that would actually be drawn (sprite id 0 is never drawn), along with their draw index.
+    pub fn visible_sprites(&self) -> Vec<(usize, Sprite)> {
+        self.sprites.iter().copied().enumerate().filter(|(_, s)| s.sprite_id > 0).collect()
+    }
+}
+
+/// Backend that never touches GL or GLFW. Draw state is recorded to memory so it can be inspected by tests and tools.
+pub struct HeadlessBackend {
+    start: Instant,
+    alive: bool,
+    frame_limit: Option<u64>,
+    frames_rendered: u64,
+    terrain: Vec<TerrainVertex>,
+    last_frame: Option<RecordedFrame>,
+    events: VecDeque<WindowEvent>,
+    window_size: (u32, u32),
+    window_mode: WindowMode,
+    /// Stands in for the system clipboard
+    clipboard: Option<String>
+}
+
+impl HeadlessBackend {
+    pub fn new() -> HeadlessBackend {
+        HeadlessBackend { start: Instant::now(), alive: true, frame_limit: None, frames_rendered: 0, terrain: Vec::new(), last_frame: None, events: VecDeque::new(), window_size: (800, 600), window_mode: WindowMode::Windowed, clipboard: None }
+    }
+
+    /// Closes the "window" after `frames` frames have been presented, so `Engine::start_game_loop` returns on its own.
+    pub fn with_frame_limit(frames: u64) -> HeadlessBackend {
+        HeadlessBackend { frame_limit: Some(frames), ..HeadlessBackend::new() }
+    }
+
+    /// Queues an event to be returned by the next call to `get_window_events`.
+    pub fn push_event(&mut self, event: WindowEvent) {
+        self.events.push_back(event);
+    }
+
+    /// Pretends the window is this big. Defaults to 800x600. The framebuffer is always the same size as the window.
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        self.window_size = (width, height);
+    }
+
+    pub fn get_window_mode(&self) -> WindowMode {
+        self.window_mode
+    }
+
+    pub fn get_frames_rendered(&self) -> u64 {
+        self.frames_rendered
+    }
+
+    pub fn get_terrain_verticies(&self) -> &[TerrainVertex] {
+        &self.terrain
+    }
+
+    pub fn get_last_frame(&self) -> Option<&RecordedFrame> {
+        self.last_frame.as_ref()
+    }
+}
+
+impl Default for HeadlessBackend {
+    fn default() -> Self {
+        HeadlessBackend::new()
+    }
+}
+
+impl RenderBackend for HeadlessBackend {
+    fn add_atlas(&mut self, _atlas: &SpriteAtlas) {}
+
+    fn buffer_terrain_verticies(&mut self, verticies: &[TerrainVertex]) {
+        self.terrain = verticies.to_vec();
+    }
+
+    fn render(&mut self, frame: &FrameData) {
+        let sprites = frame.sprites.iter().map(|s| Sprite::from(*s)).collect();
+
+        self.last_frame = Some(RecordedFrame {
+            viewport: frame.viewport,
+            view_matrix: frame.camera.get_viewmatrix(),
+            projection_matrix: frame.camera.ortho(),
+            sprites,
+            batches: frame.batches.to_vec()
+        });
+        self.frames_rendered += 1;
+    }
+
+    fn window_alive(&self) -> bool {
+        self.alive
+    }
+
+    fn close_window(&mut self) {
+        self.alive = false;
+    }
+
+    fn get_window_events(&mut self) -> Vec<(f64, WindowEvent)> {
+        let time = self.start.elapsed().as_secs_f64();
+
+        self.events.drain(..).map(|e| (time, e)).collect()
+    }
+
+    fn swap_window_buffers(&mut self) {
+        if let Some(limit) = self.frame_limit {
+            if self.frames_rendered >= limit {
+                self.alive = false;
+            }
+        }
+    }
+
+    fn get_window_size(&self) -> (u32, u32) {
+        self.window_size
+    }
+
+    fn get_framebuffer_size(&self) -> (u32, u32) {
+        self.window_size
+    }
+
+    fn set_window_mode(&mut self, mode: WindowMode, _monitor: usize) -> Result<(), EngineError> {
+        self.window_mode = mode;
+
+        Ok(())
+    }
+
+    fn get_clipboard(&self) -> Option<String> {
+        self.clipboard.clone()
+    }
+
+    fn set_clipboard(&mut self, text: &str) {
+        self.clipboard = Some(text.to_owned());
+    }
+}
@@ -1,29 +1,36 @@
 extern crate glfw;
 
-use glfw::{FlushedMessages, WindowEvent, Context};
+use glfw::WindowEvent;
 
-use gl33::global_loader::*;
-use gl33::gl_enumerations::*;
+use downcast_rs::{Downcast, impl_downcast};
 
-use std::borrow::BorrowMut;
 use std::cell::RefCell;
-use std::fmt::Display;
-use std::fs::File;
+use std::cmp;
 use std::mem::MaybeUninit;
-use std::ops::Mul;
-use std::os::raw::c_void;
-use std::sync::mpsc::Receiver;
+use std::path::Path;
+use std::rc::Rc;
 
 use std::sync::atomic::{Ordering, AtomicBool};
 
-use core::mem::{size_of};
-
-use libc::strlen;
-
 mod shader;
-use shader::*;
+pub mod atlas;
+mod aseprite;
+pub mod gl_backend;
+pub mod headless;
+pub mod software;
+pub mod snapshot;
+mod sprite_pool;
+pub mod window;
+
+use atlas::SpriteAtlas;
+use gl_backend::GlBackend;
+use headless::HeadlessBackend;
+pub use sprite_pool::SpriteHandle;
+use sprite_pool::SpritePool;
+use window::{ScalingPolicy, Viewport, WindowConfig, WindowMode};
 
 use super::Sprite;
+use super::Color;
 use super::Vector2;
 use super::Vector3;
 use super::err::{EngineError, EngineErrorTrait};
@@ -31,7 +38,7 @@ use super::matrix::Mat4x4;
 
 mod missing_gl_enums;
 
-static mut GLFW: MaybeUninit<glfw::Glfw> = MaybeUninit::uninit();
+pub(in crate::game_engine) static mut GLFW: MaybeUninit<glfw::Glfw> = MaybeUninit::uninit();
 
 static GL_INITIALIZED: AtomicBool = AtomicBool::new(false);
 
@@ -42,8 +49,12 @@ const FRAG_SHADER: &'static str = include_str!("shaders/terrain_shader.frag");
 const SPRITE_VERT_SHADER: &'static str = include_str!("shaders/sprite_shader.vert");
 const SPRITE_FRAG_SHADER: &'static str = include_str!("shaders/sprite_shader.frag");
 
-const INSTANCES: usize = 500;
+/// Multiplies `v` by `m` the way GLSL would after `glUniformMatrix4fv` with transpose off (i.e. `values` is read column-major).
+pub(super) fn transform_gl(m: &Mat4x4, v: [f32; 4]) -> [f32; 4] {
+    std::array::from_fn(|r| m.values[r] * v[0] + m.values[4 + r] * v[1] + m.values[8 + r] * v[2] + m.values[12 + r] * v[3])
+}
 
+#[derive(Clone, Copy)]
 pub struct Camera {
     pub pos: Vector3,
     pub rot: Vector3,
@@ -54,36 +65,7 @@ pub struct Camera {
 
 impl Camera {
     pub fn get_viewmatrix(&self) -> Mat4x4 {
-        // TODO: manually premultiply these matricies
-        let pitch = self.rot.x;
-        let yaw = self.rot.y;
-        let roll = self.rot.z;
-
-        let p_sin = f32::sin(pitch);
-        let p_cos = f32::cos(pitch);
-        
-        let y_sin = f32::sin(yaw);
-        let y_cos = f32::cos(yaw);
-
-        let r_sin = f32::sin(roll);
-        let r_cos = f32::cos(roll);
-
-        let p_matrix = Mat4x4 { values: [1.0,   0.0,    0.0, 0.0,
-                                                 0.0, p_cos, -p_sin, 0.0,
-                                                 0.0, p_sin,  p_cos, 0.0,
-                                                 0.0,   0.0,    0.0, 1.0]};
-        
-        let y_matrix = Mat4x4 { values: [ y_cos, 0.0, y_sin, 0.0,
-                                                    0.0, 1.0,   0.0, 0.0,
-                                                 -y_sin, 0.0, y_cos, 0.0,
-                                                    0.0, 0.0,   0.0, 1.0 ]};
-
-        let r_matrix = Mat4x4 { values: [r_cos, -r_sin, 0.0, 0.0,
-                                                 r_sin,  r_cos, 0.0, 0.0,
-                                                   0.0,    0.0, 1.0, 0.0,
-                                                   0.0,    0.0, 0.0, 1.0 ]};
-        
-        let mut rotation_matrix = p_matrix * y_matrix * r_matrix;
+        let mut rotation_matrix = Mat4x4::rotation(self.rot);
         
         rotation_matrix.set(4, 1, self.pos.x);
         rotation_matrix.set(4, 2, self.pos.y);
@@ -105,8 +87,29 @@ impl Camera {
                            0.0, 0.0, -2.0 / (f - n), -(f + n) / (f - n),
                            0.0, 0.0, 0.0, 1.0 ] }
     }
+
+    /// Converts a position in pixels from the top left of a `screen_size` window to a point in the world on the z = 0 plane.
+    pub fn screen_to_world(&self, screen_pos: Vector2, screen_size: Vector2) -> Vector2 {
+        let ndc = [screen_pos.x / screen_size.x * 2.0 - 1.0, 1.0 - screen_pos.y / screen_size.y * 2.0, 0.0, 1.0];
+
+        // Undo the shader's projection_matrix * view_matrix, matrices read the way GL reads them
+        let view = transform_gl(&self.ortho().inverse().unwrap(), ndc);
+        let world = transform_gl(&self.get_viewmatrix().inverse().unwrap(), [view[0] / view[3], view[1] / view[3], 0.0, 1.0]);
+
+        Vector2::new(world[0] / world[3], world[1] / world[3])
+    }
+
+    /// Converts a point in the world to a position in pixels from the top left of a `screen_size` window.
+    pub fn world_to_screen(&self, world_pos: Vector2, screen_size: Vector2) -> Vector2 {
+        let view = transform_gl(&self.get_viewmatrix(), [world_pos.x, world_pos.y, 0.0, 1.0]);
+        let clip = transform_gl(&self.ortho(), [view[0], view[1], 0.0, 1.0]);
+        let (x, y) = (clip[0] / clip[3], clip[1] / clip[3]);
+
+        Vector2::new((x + 1.0) * 0.5 * screen_size.x, (1.0 - y) * 0.5 * screen_size.y)
+    }
 }
 
+#[derive(Clone, Copy, Debug)]
 pub struct TerrainVertex {
     pub x: f32,
     pub y: f32,
@@ -124,6 +127,9 @@ pub struct SpriteVertex {
     pub v: f32
 }
 
+/// UV rectangle of a sprite frame, uploaded as one texel of the GL backend's frame table.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
 pub struct SpriteInfo {
     pub u: f32,
     pub v: f32,
@@ -131,12 +137,6 @@ pub struct SpriteInfo {
     pub h: f32
 }
 
-const TEST_SPRITE_INFOS: [SpriteInfo; 3] = [
-    SpriteInfo { u: 0.00, v: 0.00, w: 0.00, h: 0.00},
-    SpriteInfo { u: 0.00, v: 0.00, w: 1.00, h: 0.25},
-    SpriteInfo { u: 0.00, v: 0.25, w: 0.25, h: 0.5},
-];
-
 const SPRITE_VERTICIES: [SpriteVertex; 6] = [
     SpriteVertex { x: -0.5, y: -0.5, z: 0.0, u: 0.0, v: 1.0 }, // Bottom left
     SpriteVertex { x:  0.5, y: -0.5, z: 0.0, u: 1.0, v: 1.0 }, // Bottom right
@@ -155,26 +155,155 @@ impl EngineErrorTrait for glfw::InitError {
     }
 }
 
-pub struct Graphics {
-    window: glfw::Window,
-    events: Receiver<(f64, glfw::WindowEvent)>,
-    terrain_shader: Shader,
-    sprite_shader: Shader,
-    sprite_vbo: u32,
-    sprite_vao: u32,
-    terrain_vbo: u32,
-    terrain_vao: u32,
-    camera: Camera,
-    sprites: [f32; INSTANCES * 4],
-    sprite_ids: [i32; INSTANCES]
+/// Per-instance sprite data, laid out exactly as it is uploaded to the instance buffer.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpriteInstance {
+    /// x, y, w, h
+    pub rect: [f32; 4],
+    /// rotation, flip x (0 or 1), flip y (0 or 1), opacity
+    pub transform: [f32; 4],
+    /// RGBA tint
+    pub tint: [f32; 4],
+    pub sprite_id: i32,
+    /// Filled in by `Graphics::render` from the sprite's place in the draw order
+    pub depth: f32,
+    // The rest is not read by the shader, only used to sort and batch draws.
+    pub atlas: u32,
+    pub layer: i32,
+    pub order_in_layer: i32,
+    pub opaque: u32
+}
+
+impl From<Sprite> for SpriteInstance {
+    fn from(sprite: Sprite) -> Self {
+        SpriteInstance {
+            rect: [sprite.x, sprite.y, sprite.w, sprite.h],
+            transform: [sprite.rot, sprite.flip_x as i32 as f32, sprite.flip_y as i32 as f32, sprite.opacity],
+            tint: sprite.tint.to_array(),
+            sprite_id: sprite.sprite_id,
+            depth: 0.0,
+            atlas: sprite.atlas as u32,
+            layer: sprite.layer,
+            order_in_layer: sprite.order_in_layer,
+            opaque: sprite.opaque as u32
+        }
+    }
+}
+
+impl From<SpriteInstance> for Sprite {
+    fn from(instance: SpriteInstance) -> Self {
+        Sprite {
+            sprite_id: instance.sprite_id,
+            x: instance.rect[0],
+            y: instance.rect[1],
+            w: instance.rect[2],
+            h: instance.rect[3],
+            rot: instance.transform[0],
+            flip_x: instance.transform[1] != 0.0,
+            flip_y: instance.transform[2] != 0.0,
+            tint: Color::new(instance.tint[0], instance.tint[1], instance.tint[2], instance.tint[3]),
+            opacity: instance.transform[3],
+            atlas: instance.atlas as usize,
+            layer: instance.layer,
+            order_in_layer: instance.order_in_layer,
+            opaque: instance.opaque != 0
+        }
+    }
+}
+
+/// A run of sprites in `FrameData::sprites` that share an atlas and can be drawn with one call.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpriteBatch {
+    pub atlas: usize,
+    pub start: usize,
+    pub count: usize,
+    /// Drawn with depth writes and alpha cutout. Only set when the depth buffer is enabled.
+    pub opaque: bool
+}
+
+/// A named sorting layer. Sprites are drawn by layer value first, then by order in layer.
+#[derive(Clone, Debug)]
+pub struct SortingLayer {
+    pub name: String,
+    pub value: i32,
+    /// Within the same order in layer, sprites lower on screen draw on top. Meant for top-down games.
+    pub y_sort: bool
+}
+
+/// Everything a backend needs to draw a single frame.
+pub struct FrameData<'a> {
+    /// Camera with its size already adjusted to the viewport by the scaling policy.
+    pub camera: &'a Camera,
+    pub viewport: Viewport,
+    /// Visible sprites in draw order.
+    pub sprites: &'a [SpriteInstance],
+    pub batches: &'a [SpriteBatch],
+    /// Test against and write to the depth buffer for opaque batches
+    pub depth_buffer: bool
+}
+
+/// Painter's order: layer, then order in layer, then y if the layer is y-sorted. Ties are grouped by atlas to save draw calls.
+fn compare_draw_order(a: &SpriteInstance, b: &SpriteInstance, y_sorted_layers: &[i32]) -> cmp::Ordering {
+    let y_order = if a.layer == b.layer && y_sorted_layers.contains(&a.layer) {
+        b.rect[1].partial_cmp(&a.rect[1]).unwrap_or(cmp::Ordering::Equal)
+    } else {
+        cmp::Ordering::Equal
+    };
+
+    a.layer.cmp(&b.layer)
+        .then(a.order_in_layer.cmp(&b.order_in_layer))
+        .then(y_order)
+        .then(a.atlas.cmp(&b.atlas))
+}
+
+/// Splits `sprites` into runs of the same atlas and opacity.
+fn batch_by_atlas(sprites: &[SpriteInstance], batches: &mut Vec<SpriteBatch>, depth_buffer: bool) {
+    batches.clear();
+
+    for (i, sprite) in sprites.iter().enumerate() {
+        let opaque = depth_buffer && sprite.opaque != 0;
+
+        match batches.last_mut() {
+            Some(batch) if batch.atlas == sprite.atlas as usize && batch.opaque == opaque => batch.count += 1,
+            _ => batches.push(SpriteBatch { atlas: sprite.atlas as usize, start: i, count: 1, opaque })
+        }
+    }
+}
+
+/// Where `Graphics` sends its draw state. `GlBackend` draws to a GLFW window, `HeadlessBackend` only records it.
+pub trait RenderBackend: Downcast {
+    /// Uploads another sprite sheet. Atlases are numbered in the order they are added, starting at 0.
+    fn add_atlas(&mut self, atlas: &SpriteAtlas);
+    fn buffer_terrain_verticies(&mut self, verticies: &[TerrainVertex]);
+    fn render(&mut self, frame: &FrameData);
+    fn window_alive(&self) -> bool;
+    fn close_window(&mut self);
+    fn get_window_events(&mut self) -> Vec<(f64, WindowEvent)>;
+    fn swap_window_buffers(&mut self);
+    /// Size of the window in screen coordinates, the same units as cursor positions.
+    fn get_window_size(&self) -> (u32, u32);
+    /// Size of the window in pixels. Differs from the window size on high-DPI displays.
+    fn get_framebuffer_size(&self) -> (u32, u32);
+    /// Switches between windowed, fullscreen and borderless. `monitor` is ignored for windowed mode.
+    fn set_window_mode(&mut self, mode: WindowMode, monitor: usize) -> Result<(), EngineError>;
+    /// Text on the system clipboard. `None` if it is empty or doesn't hold text.
+    fn get_clipboard(&self) -> Option<String>;
+    fn set_clipboard(&mut self, text: &str);
 }
 
-fn get_proc_address(window: &mut glfw::Window, fn_name: *const u8) -> *const c_void {
-    // The fact that I need to do ths is bullshit, but I don't see any way around it.
-    let fn_name_slice = std::str::from_utf8(unsafe {std::slice::from_raw_parts(fn_name, strlen(fn_name as *const i8))}).unwrap();
+impl_downcast!(RenderBackend);
 
-    // I'm pretty sure this function shouldn't atually be mutating anything idk why the library wants a &mut self
-    window.get_proc_address(fn_name_slice)
+pub struct Graphics {
+    backend: Box<dyn RenderBackend>,
+    camera: Camera,
+    sprites: Rc<RefCell<SpritePool>>,
+    atlases: Vec<SpriteAtlas>,
+    sorting_layers: Vec<SortingLayer>,
+    scaling_policy: ScalingPolicy,
+    depth_buffer: bool,
+    draw_list: Vec<SpriteInstance>,
+    batches: Vec<SpriteBatch>
 }
 
 impl Graphics {
@@ -206,268 +335,257 @@ impl Graphics {
         glfw.get_time() as f32
     }
 
-    pub fn create_window() -> Result<Graphics, EngineError> {
-        if !Graphics::gl_initialized() {
-            return Err("GL not initialized!".into());
+    pub fn create_window(config: &WindowConfig) -> Result<Graphics, EngineError> {
+        let backend = GlBackend::create_window(config);
+
+        if backend.is_err() {
+            return Err(backend.err().unwrap());
         }
 
-        let glfw = unsafe {GLFW.assume_init_mut()};
-     
-        // Create a windowed mode window and its OpenGL context
-        let (mut window, events) = {
-            let op = glfw.create_window(800, 600, "Wob", glfw::WindowMode::Windowed);
+        Ok(Graphics::with_backend(Box::new(backend.unwrap())))
+    }
 
-            if op.is_none() {
-                return Err("Failed to create GLFW window.".into());
-            }
+    pub fn create_headless() -> Graphics {
+        Graphics::with_backend(Box::new(HeadlessBackend::new()))
+    }
 
-            op.unwrap()
-        };
-    
-        // Make the window's context current
-        window.make_current();
-        window.set_key_polling(true);
-
-        let mut gfx = Graphics { window,
-            events,
-            terrain_shader: Shader::null_shader(),
-            sprite_shader: Shader::null_shader(),
-            sprite_vbo: 0,
-            sprite_vao: 0,
-            terrain_vbo: 0,
-            terrain_vao: 0,
+    pub fn with_backend(backend: Box<dyn RenderBackend>) -> Graphics {
+        Graphics {
+            backend,
             camera: Camera { pos: Vector3::ZERO, rot: Vector3::ZERO, size: Vector2::UNIT, near: 0.1, far: 1000.0 },
-            sprites: [0.0; INSTANCES * 4],
-            sprite_ids: [0; INSTANCES]
-        };
-
-        let gfx = RefCell::new(gfx);
-
-        // Do gl stuff
-        unsafe {
-            load_global_gl(&|fn_name| get_proc_address(&mut gfx.borrow_mut().window, fn_name));
-            let mut gfx = gfx.into_inner();
-            gfx.terrain_shader = Shader::load_shader_program("Terrain Shader", VERT_SHADER, FRAG_SHADER, &[]);
-            
-            glClearColor(0.2, 0.3, 0.3, 1.0);
-    
-            gfx.sprite_shader = Shader::load_shader_program("Sprite Shader", SPRITE_VERT_SHADER, SPRITE_FRAG_SHADER, &[ShaderArg("$sheet_size", "3"), ShaderArg("$instance_count", &INSTANCES.to_string())]);
-    
-            let mut vao: u32 = 0;
-            glGenVertexArrays(1, &mut vao);
-            if (vao as i32) < 0 {
-                return Err("Error creaing VAO!".into());
-            }
-            
-            glBindVertexArray(vao);
-    
-            let mut vbo: u32 = 0;
-            glGenBuffers(1, &mut vbo);
-            if (vbo as i32) < 0 {
-                return Err("Error creaing VBO!".into());
-            }
-            
-            gfx.terrain_vao = vao;
-            gfx.terrain_vbo = vbo;
-    
-            glBindBuffer(GL_ARRAY_BUFFER, vbo as u32);
-    
-            // Enable pos attribute pointer
-            glVertexAttribPointer(
-                0,
-                3,
-                GL_FLOAT,
-                0,
-                24,
-                0 as *const _,
-            );
-            glEnableVertexAttribArray(0);
-    
-            // Enable color attribute pointer
-            glVertexAttribPointer(
-                1,
-                3,
-                GL_FLOAT,
-                0,
-                24,
-                12 as *const _,
-            );
-            glEnableVertexAttribArray(1);
-    
-            // Create Sprite VAO
-            let mut vao: u32 = 0;
-            glGenVertexArrays(1, &mut vao);
-            if (vao as i32) < 0 {
-                return Err("Error creaing VAO!".into());
-            }
-            
-            glBindVertexArray(vao);
-    
-            let mut vbo: u32 = 0;
-            glGenBuffers(1, &mut vbo);
-            if (vbo as i32) < 0 {
-                return Err("Error creaing VBO!".into());
-            }
-    
-            gfx.sprite_vao = vao;
-            gfx.sprite_vbo = vbo;
-            
-            glBindBuffer(GL_ARRAY_BUFFER, vbo as u32);
-    
-            // Enable pos attribute pointer
-            glVertexAttribPointer(
-                0,
-                3,
-                GL_FLOAT,
-                0,
-                20,
-                0 as *const _,
-            );
-            glEnableVertexAttribArray(0);
-    
-            // Enable uv attribute pointer
-            glVertexAttribPointer(
-                1,
-                3,
-                GL_FLOAT,
-                0,
-                20,
-                12 as *const _,
-            );
-            glEnableVertexAttribArray(1);
-            
-            let f = File::open("testgame_spritesheet.png").unwrap();
-            
-            let tex = gfx.sprite_shader.load_texture(f);
-
-            let loc = glGetUniformLocation(gfx.sprite_shader.get_program(), b"MainTex\0" as *const u8);
-
-            if loc >= 0 {
-                glUniform1i(loc, tex as i32);
-            }
-
-            // Send verticies to gpu
-            glBufferData(
-                GL_ARRAY_BUFFER,
-                (SPRITE_VERTICIES.len() * size_of::<SpriteVertex>()) as isize,
-                SPRITE_VERTICIES.as_ptr().cast(),
-                GL_STATIC_DRAW,
-            );
-
-            glBlendFunc(GL_SRC_ALPHA, GL_ONE_MINUS_SRC_ALPHA);
-            glEnable( GL_BLEND );
-    
-            glBindBuffer(GL_ARRAY_BUFFER, 0);
-            glBindVertexArray(0);
-
-            Ok(gfx)
+            sprites: SpritePool::new(),
+            atlases: Vec::new(),
+            sorting_layers: vec![
+                SortingLayer { name: "Background".to_owned(), value: -100, y_sort: false },
+                SortingLayer { name: "Default".to_owned(), value: 0, y_sort: false },
+                SortingLayer { name: "Foreground".to_owned(), value: 100, y_sort: false },
+                SortingLayer { name: "UI".to_owned(), value: 200, y_sort: false }
+            ],
+            scaling_policy: ScalingPolicy::Stretch,
+            depth_buffer: false,
+            draw_list: Vec::new(),
+            batches: Vec::new()
         }
     }
     
     pub fn buffer_terrain_verticies(&mut self, verticies: &[TerrainVertex]) {
-        unsafe {
-            glBindVertexArray(self.terrain_vao);
-            glBindBuffer(GL_ARRAY_BUFFER, self.terrain_vbo);
-
-            // Send verticies to gpu
-            glBufferData(
-                GL_ARRAY_BUFFER,
-                (verticies.len() * size_of::<TerrainVertex>()) as isize,
-                verticies.as_ptr().cast(),
-                GL_STATIC_DRAW,
-            );
-
-            glBindBuffer(GL_ARRAY_BUFFER, 0);
-            glBindVertexArray(0);
-        }
+        self.backend.buffer_terrain_verticies(verticies);
     }
 
-    pub fn render(&self) {
-        unsafe {
-            glClear(GL_COLOR_BUFFER_BIT);
+    pub fn render(&mut self) {
+        self.draw_list.clear();
+        self.draw_list.extend(self.sprites.borrow().get_instances().iter().filter(|s| s.sprite_id > 0));
 
-            // Get transformation matrix
-            let view_matrix = self.camera.get_viewmatrix();
-            let ortho = self.camera.ortho();
-            let t = view_matrix * ortho;
+        let y_sorted_layers: Vec<i32> = self.sorting_layers.iter().filter(|l| l.y_sort).map(|l| l.value).collect();
+        self.draw_list.sort_by(|a, b| compare_draw_order(a, b, &y_sorted_layers));
 
-            glBindVertexArray(self.terrain_vao);
-            glBindBuffer(GL_ARRAY_BUFFER, self.terrain_vbo);
-            
-            let program = self.terrain_shader.get_program();
-            glUseProgram(program);
-            let loc: i32 = glGetUniformLocation(program, b"transform\0" as *const u8);
-        
-            if loc >= 0 {
-                glUniformMatrix4fv(loc, 1, 0, &t.values[0] as *const f32);
-            }
-        
-            glDrawArrays(GL_TRIANGLES, 0, 3);
-
-            glBindVertexArray(self.sprite_vao);
-            glBindBuffer(GL_ARRAY_BUFFER, self.sprite_vbo);
-            
-            let program = self.sprite_shader.get_program();
-            glUseProgram(program);
-            
-            let loc: i32 = glGetUniformLocation(program, b"view_matrix\0" as *const u8);
-        
-            if loc >= 0 {
-                glUniformMatrix4fv(loc, 1, 0, &view_matrix.values[0] as *const f32);
-            }
-            
-            let loc: i32 = glGetUniformLocation(program, b"projection_matrix\0" as *const u8);
-        
-            if loc >= 0 {
-                glUniformMatrix4fv(loc, 1, 0, &ortho.values[0] as *const f32);
-            }
-            
-            let loc: i32 = glGetUniformLocation(self.sprite_shader.get_program(), b"sprite_info\0" as *const u8);
-        
-            if loc >= 0 {
-                glUniform4fv(loc, 3, TEST_SPRITE_INFOS.as_ptr().cast());
-            }
+        // Later in the draw order is closer to the camera
+        let count = self.draw_list.len();
+        for (i, sprite) in self.draw_list.iter_mut().enumerate() {
+            sprite.depth = 1.0 - 2.0 * (i + 1) as f32 / (count + 1) as f32;
+        }
 
-            let loc: i32 = glGetUniformLocation(program, b"sprites\0" as *const u8);
-            if loc >= 0 {
-                glUniform4fv(loc, INSTANCES as i32, self.sprites.as_ptr().cast());
-            }
+        // With a depth buffer, opaque sprites no longer need painter's order. They go first, grouped only by atlas.
+        if self.depth_buffer {
+            self.draw_list.sort_by_key(|s| if s.opaque != 0 { (0, s.atlas) } else { (1, 0) });
+        }
 
-            let loc: i32 = glGetUniformLocation(program, b"sprite_id\0" as *const u8);
-            if loc >= 0 {
-                glUniform1iv(loc, INSTANCES as i32, self.sprite_ids.as_ptr().cast());
-            }
+        batch_by_atlas(&self.draw_list, &mut self.batches, self.depth_buffer);
 
-            glDrawArraysInstanced(GL_TRIANGLES, 0, 6, INSTANCES as i32);
-        }
+        let (viewport, camera) = self.get_view();
+        let frame = FrameData { camera: &camera, viewport, sprites: &self.draw_list, batches: &self.batches, depth_buffer: self.depth_buffer };
+
+        self.backend.render(&frame);
     }
 
     pub fn window_alive(&self) -> bool {
-        !self.window.should_close()
+        self.backend.window_alive()
     }
 
     pub fn close_window(&mut self) {
-        self.window.set_should_close(true);
+        self.backend.close_window();
     }
 
-    pub fn get_window_events(&self) -> FlushedMessages<'_, (f64, WindowEvent)> {
-        let glfw = unsafe {GLFW.assume_init_mut()};
-
-        glfw.poll_events();
-        glfw::flush_messages(&self.events)
+    pub fn get_window_events(&mut self) -> Vec<(f64, WindowEvent)> {
+        self.backend.get_window_events()
     }
 
     pub fn swap_window_buffers(&mut self) {
-        self.window.swap_buffers();
+        self.backend.swap_window_buffers();
     }
 
-    pub fn update_sprite(&mut self, sprite: Sprite, idx: usize) {
-        self.sprites[idx * 4] = sprite.x;
-        self.sprites[idx * 4 + 1] = sprite.y;
-        self.sprites[idx * 4 + 2] = sprite.w;
-        self.sprites[idx * 4 + 3] = sprite.h;
+    pub fn get_window_size(&self) -> Vector2 {
+        let (w, h) = self.backend.get_window_size();
+
+        Vector2::new(w as f32, h as f32)
+    }
 
-        self.sprite_ids[idx] = sprite.sprite_id;
+    pub fn set_window_mode(&mut self, mode: WindowMode, monitor: usize) -> Result<(), EngineError> {
+        self.backend.set_window_mode(mode, monitor)
+    }
+
+    pub fn get_clipboard(&self) -> Option<String> {
+        self.backend.get_clipboard()
+    }
+
+    pub fn set_clipboard(&mut self, text: &str) {
+        self.backend.set_clipboard(text);
+    }
+
+    /// Decides how the camera's view is fitted to the window when their shapes differ. Defaults to `ScalingPolicy::Stretch`, which fills the window like the engine always has.
+    pub fn set_scaling_policy(&mut self, policy: ScalingPolicy) {
+        self.scaling_policy = policy;
+    }
+
+    pub fn get_scaling_policy(&self) -> ScalingPolicy {
+        self.scaling_policy
+    }
+
+    /// The viewport for the current window size, and the camera as it will be rendered into it.
+    fn get_view(&self) -> (Viewport, Camera) {
+        let (viewport, size) = self.scaling_policy.apply(self.camera.size, self.backend.get_framebuffer_size());
+
+        (viewport, Camera { size, ..self.camera })
+    }
+
+    /// Area of the framebuffer that is drawn to. Anything outside it is black bars.
+    pub fn get_viewport(&self) -> Viewport {
+        self.get_view().0
+    }
+
+    /// Size of the world area that is actually visible, after the scaling policy has been applied to `Camera.size`.
+    pub fn get_view_size(&self) -> Vector2 {
+        self.get_view().1.size
+    }
+
+    /// Factor from screen coordinates to framebuffer pixels
+    fn get_pixel_scale(&self) -> Vector2 {
+        let (window_w, window_h) = self.backend.get_window_size();
+        let (fb_w, fb_h) = self.backend.get_framebuffer_size();
+
+        Vector2::new(fb_w as f32 / window_w.max(1) as f32, fb_h as f32 / window_h.max(1) as f32)
+    }
+
+    /// Converts a cursor position to a point in the world, as seen by the current camera.
+    pub fn screen_to_world(&self, screen_pos: Vector2) -> Vector2 {
+        let (viewport, camera) = self.get_view();
+        let scale = self.get_pixel_scale();
+        let pixel = Vector2::new(screen_pos.x * scale.x - viewport.x as f32, screen_pos.y * scale.y - viewport.y as f32);
+
+        camera.screen_to_world(pixel, Vector2::new(viewport.width as f32, viewport.height as f32))
+    }
+
+    /// Converts a point in the world to a cursor position, as seen by the current camera.
+    pub fn world_to_screen(&self, world_pos: Vector2) -> Vector2 {
+        let (viewport, camera) = self.get_view();
+        let scale = self.get_pixel_scale();
+        let pixel = camera.world_to_screen(world_pos, Vector2::new(viewport.width as f32, viewport.height as f32));
+
+        Vector2::new((pixel.x + viewport.x as f32) / scale.x, (pixel.y + viewport.y as f32) / scale.y)
+    }
+
+    pub fn get_backend<B: RenderBackend>(&self) -> Option<&B> {
+        self.backend.downcast_ref()
+    }
+
+    pub fn get_backend_mut<B: RenderBackend>(&mut self) -> Option<&mut B> {
+        self.backend.downcast_mut()
+    }
+
+    /// Reserves a sprite slot. The slot is released again when the handle is dropped.
+    pub fn alloc_sprite(&mut self) -> SpriteHandle {
+        SpritePool::alloc(&self.sprites)
+    }
+
+    pub fn update_sprite(&mut self, sprite: Sprite, handle: &SpriteHandle) {
+        debug_assert!(handle.is_from(&self.sprites), "Sprite handle belongs to a different Graphics!");
+
+        self.sprites.borrow_mut().set(handle, sprite.into());
+    }
+
+    pub fn get_sprite(&self, handle: &SpriteHandle) -> Sprite {
+        self.sprites.borrow().get(handle).into()
+    }
+
+    /// Number of sprite slots currently in use.
+    pub fn get_sprite_count(&self) -> usize {
+        self.sprites.borrow().get_live_count()
+    }
+
+    /// Adds a sprite sheet and returns its atlas id.
+    pub fn add_atlas(&mut self, mut atlas: SpriteAtlas) -> usize {
+        let id = self.atlases.len();
+
+        atlas.set_clip_atlas(id);
+        self.backend.add_atlas(&atlas);
+        self.atlases.push(atlas);
+
+        id
+    }
+
+    pub fn load_atlas<P: AsRef<Path>, Q: AsRef<Path>>(&mut self, image_path: P, metadata_path: Q) -> Result<usize, EngineError> {
+        let atlas = SpriteAtlas::load(image_path, metadata_path);
+
+        if atlas.is_err() {
+            return Err(atlas.err().unwrap());
+        }
+
+        Ok(self.add_atlas(atlas.unwrap()))
+    }
+
+    /// Loads an Aseprite sprite sheet export. See `SpriteAtlas::load_aseprite`.
+    pub fn load_aseprite<P: AsRef<Path>>(&mut self, json_path: P) -> Result<usize, EngineError> {
+        let atlas = SpriteAtlas::load_aseprite(json_path);
+
+        if atlas.is_err() {
+            return Err(atlas.err().unwrap());
+        }
+
+        Ok(self.add_atlas(atlas.unwrap()))
+    }
+
+    pub fn get_atlas(&self, atlas: usize) -> Option<&SpriteAtlas> {
+        self.atlases.get(atlas)
+    }
+
+    pub fn get_atlases(&self) -> &[SpriteAtlas] {
+        &self.atlases
+    }
+
+    /// Looks up a frame by name, searching atlases in the order they were added. Returns the atlas id and sprite id.
+    pub fn find_frame(&self, frame_name: &str) -> Option<(usize, i32)> {
+        self.atlases.iter().enumerate().find_map(|(i, atlas)| atlas.get_frame_id(frame_name).map(|id| (i, id)))
+    }
+
+    /// Adds a sorting layer, or changes the value of an existing one.
+    pub fn add_sorting_layer(&mut self, name: &str, value: i32) {
+        match self.sorting_layers.iter_mut().find(|l| l.name == name) {
+            Some(layer) => layer.value = value,
+            None => self.sorting_layers.push(SortingLayer { name: name.to_owned(), value, y_sort: false })
+        }
+    }
+
+    pub fn get_sorting_layer(&self, name: &str) -> Option<&SortingLayer> {
+        self.sorting_layers.iter().find(|l| l.name == name)
+    }
+
+    pub fn get_sorting_layers(&self) -> &[SortingLayer] {
+        &self.sorting_layers
+    }
+
+    pub fn set_y_sort(&mut self, layer: &str, y_sort: bool) {
+        if let Some(layer) = self.sorting_layers.iter_mut().find(|l| l.name == layer) {
+            layer.y_sort = y_sort;
+        }
+    }
+
+    /// Draws opaque sprites with the depth buffer instead of strictly in painter's order, so they can be batched by atlas alone.
+    pub fn set_depth_buffer(&mut self, enabled: bool) {
+        self.depth_buffer = enabled;
+    }
+
+    pub fn get_depth_buffer(&self) -> bool {
+        self.depth_buffer
     }
 
     pub fn get_camera(&self) -> &Camera {
@@ -477,4 +595,79 @@ impl Graphics {
     pub fn get_camera_mut(&mut self) -> &mut Camera {
         &mut self.camera
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use software::SoftwareBackend;
+
+    /// Software rendered graphics with the test game's atlas loaded and a 2.4x1.8 camera. Shared with the snapshot tests.
+    pub(super) fn software_graphics(width: u32, height: u32) -> Graphics {
+        let mut gfx = Graphics::with_backend(Box::new(SoftwareBackend::new(width, height)));
+        gfx.load_atlas("testgame_spritesheet.png", "testgame_spritesheet.atlas").unwrap();
+        gfx.get_camera_mut().size = Vector2::new(0.8, 0.6) * 3.0;
+
+        gfx
+    }
+
+    #[test]
+    fn camera_screen_world_round_trip() {
+        let camera = Camera { pos: Vector3::new(0.5, 0.25, 0.0), rot: Vector3::ZERO, size: Vector2::new(2.4, 1.8), near: 0.1, far: 1000.0 };
+        let screen_size = Vector2::new(160.0, 120.0);
+
+        // The camera's position is the center of the screen, and the top left corner is half its size away
+        assert!((camera.world_to_screen(Vector2::new(0.5, 0.25), screen_size) - Vector2::new(80.0, 60.0)).magnitude() < 1e-3);
+        assert!((camera.screen_to_world(Vector2::ZERO, screen_size) - Vector2::new(-0.7, 1.15)).magnitude() < 1e-4);
+
+        let world = Vector2::new(-0.2, 0.4);
+        let back = camera.screen_to_world(camera.world_to_screen(world, screen_size), screen_size);
+        assert!((back - world).magnitude() < 1e-4, "{} came back as {}", world, back);
+    }
+
+    #[test]
+    fn screen_world_conversion_matches_rendered_pixels() {
+        let mut gfx = software_graphics(160, 120);
+        gfx.get_camera_mut().pos = Vector3::new(0.5, 0.25, 0.0);
+
+        let world = Vector2::new(-0.2, 0.4);
+        let screen = gfx.world_to_screen(world);
+        let back = gfx.screen_to_world(screen);
+        assert!((back - world).magnitude() < 1e-4, "{} came back as {}", world, back);
+
+        // 0.7 left of and 0.15 above the camera, with 2.4x1.8 world units across the 160x120 frame
+        assert!((screen - Vector2::new(80.0 - 0.7 / 2.4 * 160.0, 60.0 - 0.15 / 1.8 * 120.0)).magnitude() < 1e-3, "{}", screen);
+
+        let handle = gfx.alloc_sprite();
+        gfx.render();
+        let empty = gfx.get_backend::<SoftwareBackend>().unwrap().get_frame().clone();
+
+        gfx.update_sprite(Sprite { sprite_id: 1, x: world.x, y: world.y, w: 0.05, h: 0.05, ..Sprite::default() }, &handle);
+        gfx.render();
+        let frame = gfx.get_backend::<SoftwareBackend>().unwrap().get_frame();
+
+        assert_ne!(frame.get_pixel(screen.x as u32, screen.y as u32), empty.get_pixel(screen.x as u32, screen.y as u32));
+    }
+
+    #[test]
+    fn screen_world_conversion_accounts_for_letterbox_bars() {
+        let mut gfx = software_graphics(200, 120);
+        gfx.get_camera_mut().pos = Vector3::new(0.5, 0.25, 0.0);
+        gfx.set_scaling_policy(ScalingPolicy::Letterbox);
+
+        assert_eq!(gfx.get_viewport(), Viewport { x: 20, y: 0, width: 160, height: 120 });
+
+        // The camera's position is the center of the window, not of the 160 pixels left of the right bar
+        assert!((gfx.world_to_screen(Vector2::new(0.5, 0.25)) - Vector2::new(100.0, 60.0)).magnitude() < 1e-3);
+        assert!((gfx.screen_to_world(Vector2::new(20.0, 0.0)) - Vector2::new(-0.7, 1.15)).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn stretch_is_the_default_scaling_policy() {
+        let gfx = software_graphics(200, 120);
+
+        assert_eq!(gfx.get_scaling_policy(), ScalingPolicy::Stretch);
+        assert_eq!(gfx.get_viewport(), Viewport { x: 0, y: 0, width: 200, height: 120 });
+        assert!((gfx.get_view_size() - Vector2::new(2.4, 1.8)).magnitude() < 1e-4);
+    }
+}
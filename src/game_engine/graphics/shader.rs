@@ -6,9 +6,13 @@ use std::io::Read;
 use std::io::Seek;
 use std::rc::Rc;
 
+use image::RgbaImage;
+
 use gl33::global_loader::*;
 use gl33::gl_enumerations::*;
 
+use crate::game_engine::err::EngineError;
+
 #[derive(Clone)]
 pub struct ShaderArg<'a>(pub &'a str, pub &'a str);
 
@@ -78,6 +82,40 @@ fn insert_shader_args(shader: &str, args: &[ShaderArg]) -> String {
     out
 }
 
+/// Decodes an image into the RGBA layout that gets uploaded to the GPU.
+pub fn decode_texture<R: Read + Seek>(readable: R) -> Result<RgbaImage, EngineError> {
+    let buf = BufReader::new(readable);
+
+    let reader = image::io::Reader::new(buf).with_guessed_format();
+    if reader.is_err() {
+        return Err(format!("Failed to read image: {}", reader.err().unwrap()).into());
+    }
+
+    let img = reader.unwrap().decode();
+    if img.is_err() {
+        return Err(format!("Failed to decode image: {}", img.err().unwrap()).into());
+    }
+
+    Ok(img.unwrap().to_rgba8())
+}
+
+/// Uploads an image as a new RGBA texture and returns its name.
+pub unsafe fn create_texture(img: &RgbaImage) -> u32 {
+    let ptr = img.as_ptr();
+    let mut texture: u32 = 0;
+
+    // Create Texture
+    glGenTextures(1, &mut texture);
+    glBindTexture(GL_TEXTURE_2D, texture);
+    glTexParameteri(GL_TEXTURE_2D,GL_TEXTURE_MAG_FILTER,GL_LINEAR.0 as i32); //scale linearly when image bigger than texture
+    glTexParameteri(GL_TEXTURE_2D,GL_TEXTURE_MIN_FILTER,GL_LINEAR.0 as i32); //scale linearly when image smalled than texture
+    glTexImage2D(GL_TEXTURE_2D, 0, GL_RGBA8.0 as i32, img.width() as i32, img.height() as i32, 0,
+    GL_RGBA, GL_UNSIGNED_BYTE, ptr.cast());
+    glEnable(GL_TEXTURE_2D);
+
+    texture
+}
+
 impl Shader {
     pub fn null_shader() -> Shader {
         Shader {name: Rc::new(RefCell::new(String::from("NULL"))), program: Rc::new(RefCell::new(0)), textures: Rc::new(RefCell::new(Vec::new())) }
@@ -173,25 +211,17 @@ impl Shader {
         *self.program.borrow_mut() = 0;
     }
 
-    pub unsafe fn load_texture<R: Read + Seek>(&mut self, readable: R) -> u32 {
-        let buf = BufReader::new(readable);
-        let img = image::io::Reader::new(buf).with_guessed_format().unwrap().decode().unwrap();
-        let img = img.to_rgba8();
-        let ptr = img.as_ptr();
-        let mut texture: u32 = 0;
-
-        // Create Texture
-        glGenTextures(1, &mut texture);
-        glBindTexture(GL_TEXTURE_2D, texture);
-        glTexParameteri(GL_TEXTURE_2D,GL_TEXTURE_MAG_FILTER,GL_LINEAR.0 as i32); //scale linearly when image bigger than texture
-        glTexParameteri(GL_TEXTURE_2D,GL_TEXTURE_MIN_FILTER,GL_LINEAR.0 as i32); //scale linearly when image smalled than texture
-        glTexImage2D(GL_TEXTURE_2D, 0, GL_RGBA8.0 as i32, img.width() as i32, img.height() as i32, 0,
-        GL_RGBA, GL_UNSIGNED_BYTE, ptr.cast());
-        glEnable(GL_TEXTURE_2D);
+    pub unsafe fn load_texture<R: Read + Seek>(&mut self, readable: R) -> Result<u32, EngineError> {
+        let img = decode_texture(readable);
+        if img.is_err() {
+            return Err(img.err().unwrap());
+        }
+
+        let texture = create_texture(&img.unwrap());
 
         self.textures.borrow_mut().push(texture);
 
-        texture
+        Ok(texture)
     }
 
     pub fn get_textures(&self) -> Ref<[u32]> {
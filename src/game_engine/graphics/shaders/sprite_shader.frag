@@ -1,13 +1,18 @@
 #version 330 core
-#define SPRITE_COUNT $instance_count
 
 uniform sampler2D MainTex;
+uniform float alpha_cutoff;
 
 smooth in vec2 tex_coord;
 flat in int id;
+flat in vec4 tint;
 
 out vec4 outputColor;
 
 void main() {
-    outputColor = texture(MainTex, tex_coord) * float(id > 0);
+    outputColor = texture(MainTex, tex_coord) * tint * float(id > 0);
+
+    if (outputColor.a < alpha_cutoff) {
+        discard;
+    }
 }
\ No newline at end of file
@@ -1,30 +1,48 @@
 #version 330 core
-#define SPRITES_IN_SHEET $sheet_size
-#define SPRITE_COUNT $instance_count
 
 layout (location = 0) in vec3 pos;
 layout (location = 1) in vec2 uv;
 
+// Per-instance
+layout (location = 2) in vec4 instance_rect;
+layout (location = 3) in vec4 instance_transform;
+layout (location = 4) in vec4 instance_tint;
+layout (location = 5) in int instance_sprite_id;
+layout (location = 6) in float instance_depth;
+
 uniform mat4 view_matrix;
 uniform mat4 projection_matrix;
-uniform vec4 sprite_info[SPRITES_IN_SHEET];
-uniform vec4 sprites[SPRITE_COUNT];
-uniform int sprite_id[SPRITE_COUNT];
+// Two texels per frame: the UV rect (bottom left, size), then the pivot
+uniform samplerBuffer frame_table;
 
 smooth out vec2 tex_coord;
 flat out int id;
+flat out vec4 tint;
 
 void main() {
-    vec2 offset = (view_matrix * vec4(sprites[gl_InstanceID].xy, 0.0, 1.0)).xy;
-    vec2 size = sprites[gl_InstanceID].zw;
-    vec3 p = pos * vec3(size, 1);
+    vec2 offset = (view_matrix * vec4(instance_rect.xy, 0.0, 1.0)).xy;
+    vec2 size = instance_rect.zw;
+    vec4 transform = instance_transform;
+    int s_id = instance_sprite_id;
+    vec4 frame_rect = texelFetch(frame_table, s_id * 2);
+    vec2 frame_pivot = texelFetch(frame_table, s_id * 2 + 1).xy;
+
+    // Move the quad so the pivot sits on the sprite's position. Flipping mirrors the pivot along with the image.
+    vec2 pivot = mix(frame_pivot, 1.0 - frame_pivot, transform.yz);
+    vec3 p = vec3((pos.xy + 0.5 - pivot) * size, pos.z);
+
+    float c = cos(transform.x);
+    float s = sin(transform.x);
+    p.xy = vec2(p.x * c - p.y * s, p.x * s + p.y * c);
 
     gl_Position = projection_matrix * vec4(p.xy + offset, p.z, 1.0);
+    gl_Position.z = instance_depth * gl_Position.w;
 
-    int s_id = sprite_id[gl_InstanceID];
     id = s_id;
-    vec2 uv_bl = sprite_info[s_id].xy;
-    vec2 uv_w = sprite_info[s_id].zw;
+    vec2 uv_bl = frame_rect.xy;
+    vec2 uv_w = frame_rect.zw;
+    vec2 flipped_uv = mix(uv, 1.0 - uv, transform.yz);
 
-    tex_coord = uv_bl + uv * uv_w;
+    tex_coord = uv_bl + flipped_uv * uv_w;
+    tint = instance_tint * vec4(1.0, 1.0, 1.0, transform.w);
 }
\ No newline at end of file
@@ -0,0 +1,293 @@
+use std::path::PathBuf;
+
+use image::RgbaImage;
+
+use super::shader::decode_texture;
+use crate::game_engine::err::EngineError;
+
+const SNAPSHOT_DIR: &str = "snapshots";
+
+/// Set this environment variable to overwrite golden images with whatever is currently rendered.
+const UPDATE_VAR: &str = "UPDATE_SNAPSHOTS";
+
+/// How far a frame may drift from its golden image before it counts as a regression.
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    /// Largest per-channel difference (0-255) that still counts as the same pixel.
+    pub channel: u8,
+    /// Fraction of pixels (0.0-1.0) allowed to differ by more than `channel`.
+    pub pixels: f32
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance { channel: 2, pixels: 0.001 }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ImageDiff {
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    pub max_channel_diff: u8
+}
+
+impl ImageDiff {
+    pub fn within(&self, tolerance: Tolerance) -> bool {
+        self.differing_pixels as f32 <= self.total_pixels as f32 * tolerance.pixels
+    }
+}
+
+pub fn compare_images(actual: &RgbaImage, expected: &RgbaImage, channel_tolerance: u8) -> Result<ImageDiff, EngineError> {
+    if actual.dimensions() != expected.dimensions() {
+        return Err(format!("Image size mismatch: got {:?}, expected {:?}", actual.dimensions(), expected.dimensions()).into());
+    }
+
+    let mut diff = ImageDiff { differing_pixels: 0, total_pixels: (actual.width() * actual.height()) as usize, max_channel_diff: 0 };
+
+    for (a, e) in actual.pixels().zip(expected.pixels()) {
+        let d = (0..4).map(|c| a[c].abs_diff(e[c])).max().unwrap();
+
+        diff.max_channel_diff = diff.max_channel_diff.max(d);
+        if d > channel_tolerance {
+            diff.differing_pixels += 1;
+        }
+    }
+
+    Ok(diff)
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(SNAPSHOT_DIR).join(format!("{}.png", name))
+}
+
+/// Compares `frame` against `snapshots/<name>.png`. On failure the frame is written next to the golden as
+/// `<name>.actual.png` so it can be inspected. Run with `UPDATE_SNAPSHOTS=1` to accept the new output.
+pub fn check_snapshot(name: &str, frame: &RgbaImage, tolerance: Tolerance) -> Result<(), EngineError> {
+    let path = golden_path(name);
+
+    if std::env::var_os(UPDATE_VAR).is_some() {
+        let result = std::fs::create_dir_all(SNAPSHOT_DIR).map(|_| frame.save(&path));
+
+        return match result {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(e)) => Err(format!("Failed to write snapshot {}: {}", path.display(), e).into()),
+            Err(e) => Err(format!("Failed to create {}: {}", SNAPSHOT_DIR, e).into())
+        };
+    }
+
+    let f = std::fs::File::open(&path);
+    if f.is_err() {
+        return Err(format!("Missing golden image {} (run with {}=1 to create it)", path.display(), UPDATE_VAR).into());
+    }
+
+    let expected = decode_texture(f.unwrap());
+    if expected.is_err() {
+        return Err(format!("Golden image {}: {}", path.display(), expected.err().unwrap().get_error_message()).into());
+    }
+
+    let expected = expected.unwrap();
+    let diff = compare_images(frame, &expected, tolerance.channel);
+
+    let diff = match diff {
+        Ok(diff) if diff.within(tolerance) => return Ok(()),
+        Ok(diff) => format!("{} of {} pixels differ (max channel diff {})", diff.differing_pixels, diff.total_pixels, diff.max_channel_diff),
+        Err(e) => e.get_error_message().to_owned()
+    };
+
+    let actual_path = PathBuf::from(SNAPSHOT_DIR).join(format!("{}.actual.png", name));
+    let _ = frame.save(&actual_path);
+
+    Err(format!("Snapshot \"{}\" does not match: {}. Actual frame written to {}", name, diff, actual_path.display()).into())
+}
+
+pub fn assert_snapshot(name: &str, frame: &RgbaImage, tolerance: Tolerance) {
+    if let Err(e) = check_snapshot(name, frame, tolerance) {
+        panic!("{}", e.get_error_message());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_engine::{Sprite, Color, Vector2, Vector3, VERTICES};
+    use crate::game_engine::graphics::{Graphics, SpriteHandle, atlas::SpriteAtlas, software::SoftwareBackend};
+    use crate::game_engine::graphics::tests::software_graphics;
+    use crate::game_engine::graphics::window::ScalingPolicy;
+
+    fn render_scene(camera_pos: Vector3) -> RgbaImage {
+        let mut gfx = software_graphics(160, 120);
+        gfx.buffer_terrain_verticies(&VERTICES);
+
+        gfx.get_camera_mut().pos = camera_pos;
+
+        let sprites: Vec<SpriteHandle> = (0..3).map(|_| gfx.alloc_sprite()).collect();
+        gfx.update_sprite(Sprite { sprite_id: 1, x: 0.0, y: -0.75, w: 2.0, h: 0.5, ..Sprite::default() }, &sprites[0]);
+        gfx.update_sprite(Sprite { sprite_id: 1, x: 0.5, y: -0.125, w: 0.5, h: 0.75, ..Sprite::default() }, &sprites[1]);
+        gfx.update_sprite(Sprite { sprite_id: 2, x: -0.5, y: 0.0, w: 0.5, h: 1.0, ..Sprite::default() }, &sprites[2]);
+        gfx.render();
+
+        gfx.get_backend::<SoftwareBackend>().unwrap().get_frame().clone()
+    }
+
+    #[test]
+    fn demo_scene() {
+        assert_snapshot("demo_scene", &render_scene(Vector3::ZERO), Tolerance::default());
+    }
+
+    #[test]
+    fn demo_scene_camera_offset() {
+        assert_snapshot("demo_scene_camera_offset", &render_scene(Vector3::new(0.5, 0.25, 0.0)), Tolerance::default());
+    }
+
+    #[test]
+    fn sprite_rotation_flip_and_tint() {
+        let mut gfx = software_graphics(160, 120);
+
+        let sprites: Vec<SpriteHandle> = (0..3).map(|_| gfx.alloc_sprite()).collect();
+        gfx.update_sprite(Sprite { sprite_id: 2, x: -0.75, y: 0.0, w: 0.5, h: 1.0, rot: 0.5, ..Sprite::default() }, &sprites[0]);
+        gfx.update_sprite(Sprite { sprite_id: 2, x: 0.0, y: 0.0, w: 0.5, h: 1.0, flip_x: true, flip_y: true, ..Sprite::default() }, &sprites[1]);
+        gfx.update_sprite(Sprite { sprite_id: 2, x: 0.75, y: 0.0, w: 0.5, h: 1.0, tint: Color::RED, opacity: 0.5, ..Sprite::default() }, &sprites[2]);
+        gfx.render();
+
+        let frame = gfx.get_backend::<SoftwareBackend>().unwrap().get_frame();
+        assert_snapshot("sprite_rotation_flip_and_tint", frame, Tolerance::default());
+    }
+
+    #[test]
+    fn sprite_pivot() {
+        let mut gfx = software_graphics(160, 120);
+
+        // Same stick figure, pivoted at the feet instead of the center
+        let image = gfx.get_atlas(0).unwrap().get_image().clone();
+        let frames = SpriteAtlas::parse_metadata("player_feet 0 270 270 540 0.5 0.0").unwrap();
+        let atlas = gfx.add_atlas(SpriteAtlas::new(image, frames).unwrap());
+
+        let sprites: Vec<SpriteHandle> = (0..2).map(|_| gfx.alloc_sprite()).collect();
+        gfx.update_sprite(Sprite { sprite_id: 1, atlas, x: -0.5, y: -0.5, w: 0.5, h: 1.0, ..Sprite::default() }, &sprites[0]);
+        gfx.update_sprite(Sprite { sprite_id: 1, atlas, x: 0.5, y: -0.5, w: 0.5, h: 1.0, rot: -1.0, ..Sprite::default() }, &sprites[1]);
+        gfx.render();
+
+        let frame = gfx.get_backend::<SoftwareBackend>().unwrap().get_frame();
+        assert_snapshot("sprite_pivot", frame, Tolerance::default());
+    }
+
+    #[test]
+    fn aseprite_sheet_matches_atlas() {
+        let mut gfx = Graphics::with_backend(Box::new(SoftwareBackend::new(160, 120)));
+        gfx.load_aseprite("testgame_spritesheet.json").unwrap();
+        gfx.buffer_terrain_verticies(&VERTICES);
+        gfx.get_camera_mut().size = Vector2::new(0.8, 0.6) * 3.0;
+
+        let atlas = gfx.get_atlas(0).unwrap();
+        let ground = atlas.get_frame_id("ground").unwrap();
+        let player = atlas.get_clip("player_idle").unwrap().frames[0].sprite_id;
+
+        let sprites: Vec<SpriteHandle> = (0..3).map(|_| gfx.alloc_sprite()).collect();
+        gfx.update_sprite(Sprite { sprite_id: ground, x: 0.0, y: -0.75, w: 2.0, h: 0.5, ..Sprite::default() }, &sprites[0]);
+        gfx.update_sprite(Sprite { sprite_id: ground, x: 0.5, y: -0.125, w: 0.5, h: 0.75, ..Sprite::default() }, &sprites[1]);
+        gfx.update_sprite(Sprite { sprite_id: player, x: -0.5, y: 0.0, w: 0.5, h: 1.0, ..Sprite::default() }, &sprites[2]);
+        gfx.render();
+
+        let frame = gfx.get_backend::<SoftwareBackend>().unwrap().get_frame();
+        assert_snapshot("demo_scene", frame, Tolerance::default());
+    }
+
+    #[test]
+    fn multiple_atlases() {
+        let mut gfx = software_graphics(160, 120);
+        gfx.load_atlas("test_spritesheet.png", "test_spritesheet.atlas").unwrap();
+
+        let (numbers, two) = gfx.find_frame("number_2").unwrap();
+        let (_, seven) = gfx.find_frame("number_7").unwrap();
+        let (sheet, player) = gfx.find_frame("player_idle_0").unwrap();
+
+        // Interleaved on purpose so the sprites have to be regrouped by atlas
+        let sprites: Vec<SpriteHandle> = (0..4).map(|_| gfx.alloc_sprite()).collect();
+        gfx.update_sprite(Sprite { sprite_id: two, atlas: numbers, x: -0.5, y: 0.25, w: 0.5, h: 0.5, ..Sprite::default() }, &sprites[0]);
+        gfx.update_sprite(Sprite { sprite_id: player, atlas: sheet, x: -0.5, y: -0.25, w: 0.5, h: 1.0, ..Sprite::default() }, &sprites[1]);
+        gfx.update_sprite(Sprite { sprite_id: seven, atlas: numbers, x: 0.5, y: 0.25, w: 0.5, h: 0.5, ..Sprite::default() }, &sprites[2]);
+        gfx.update_sprite(Sprite { sprite_id: player, atlas: sheet, x: 0.5, y: -0.25, w: 0.5, h: 1.0, flip_x: true, ..Sprite::default() }, &sprites[3]);
+        gfx.render();
+
+        let frame = gfx.get_backend::<SoftwareBackend>().unwrap().get_frame();
+        assert_snapshot("multiple_atlases", frame, Tolerance::default());
+    }
+
+    fn render_layered(depth_buffer: bool) -> RgbaImage {
+        let mut gfx = software_graphics(160, 120);
+        gfx.load_atlas("test_spritesheet.png", "test_spritesheet.atlas").unwrap();
+        gfx.add_sorting_layer("Props", 50);
+        gfx.set_y_sort("Props", true);
+        gfx.set_depth_buffer(depth_buffer);
+
+        let tile = |name: &str| gfx.find_frame(name).unwrap();
+        let (numbers, one) = tile("number_1");
+        let (_, two) = tile("number_2");
+        let (_, three) = tile("number_3");
+        let (_, four) = tile("number_4");
+        let props = gfx.get_sorting_layer("Props").unwrap().value;
+
+        // Allocated top to bottom, so slot order is the opposite of the expected draw order
+        let sprites: Vec<SpriteHandle> = (0..6).map(|_| gfx.alloc_sprite()).collect();
+        let base = Sprite { atlas: numbers, w: 0.6, h: 0.6, opaque: true, ..Sprite::default() };
+        gfx.update_sprite(Sprite { sprite_id: one, x: -0.6, y: 0.1, layer: 200, ..base }, &sprites[0]);
+        gfx.update_sprite(Sprite { sprite_id: two, x: -0.4, y: 0.0, order_in_layer: 1, ..base }, &sprites[1]);
+        gfx.update_sprite(Sprite { sprite_id: three, x: -0.2, y: -0.1, ..base }, &sprites[2]);
+        gfx.update_sprite(Sprite { sprite_id: four, x: 0.4, y: -0.2, layer: props, ..base }, &sprites[3]);
+        gfx.update_sprite(Sprite { sprite_id: one, x: 0.6, y: 0.0, layer: props, ..base }, &sprites[4]);
+        gfx.update_sprite(Sprite { sprite_id: two, x: 0.8, y: 0.2, layer: props, ..base }, &sprites[5]);
+        gfx.render();
+
+        gfx.get_backend::<SoftwareBackend>().unwrap().get_frame().clone()
+    }
+
+    #[test]
+    fn sorting_layers_and_y_sort() {
+        assert_snapshot("sorting_layers_and_y_sort", &render_layered(false), Tolerance::default());
+    }
+
+    #[test]
+    fn depth_buffer_matches_painters_order() {
+        assert_snapshot("sorting_layers_and_y_sort", &render_layered(true), Tolerance::default());
+    }
+
+    #[test]
+    fn letterbox_keeps_aspect_ratio() {
+        let mut gfx = software_graphics(200, 120);
+        gfx.buffer_terrain_verticies(&VERTICES);
+        gfx.set_scaling_policy(ScalingPolicy::Letterbox);
+
+        let sprites: Vec<SpriteHandle> = (0..3).map(|_| gfx.alloc_sprite()).collect();
+        gfx.update_sprite(Sprite { sprite_id: 1, x: 0.0, y: -0.75, w: 2.0, h: 0.5, ..Sprite::default() }, &sprites[0]);
+        gfx.update_sprite(Sprite { sprite_id: 1, x: 0.5, y: -0.125, w: 0.5, h: 0.75, ..Sprite::default() }, &sprites[1]);
+        gfx.update_sprite(Sprite { sprite_id: 2, x: -0.5, y: 0.0, w: 0.5, h: 1.0, ..Sprite::default() }, &sprites[2]);
+        gfx.render();
+
+        // A 4:3 view in a 5:3 window gets 20 pixel bars on each side, with the demo scene unchanged in between
+        let frame = gfx.get_backend::<SoftwareBackend>().unwrap().get_frame();
+        let inner = image::imageops::crop_imm(frame, 20, 0, 160, 120).to_image();
+
+        assert_eq!(*frame.get_pixel(5, 60), image::Rgba([0, 0, 0, 255]));
+        assert_eq!(*frame.get_pixel(195, 60), image::Rgba([0, 0, 0, 255]));
+        assert_snapshot("demo_scene", &inner, Tolerance::default());
+    }
+
+    #[test]
+    fn identical_images_have_no_diff() {
+        let frame = render_scene(Vector3::ZERO);
+        let diff = compare_images(&frame, &frame, 0).unwrap();
+
+        assert_eq!(diff.differing_pixels, 0);
+        assert_eq!(diff.max_channel_diff, 0);
+    }
+
+    #[test]
+    fn moved_camera_is_caught() {
+        let a = render_scene(Vector3::ZERO);
+        let b = render_scene(Vector3::new(0.5, 0.25, 0.0));
+        let diff = compare_images(&a, &b, Tolerance::default().channel).unwrap();
+
+        assert!(!diff.within(Tolerance::default()));
+    }
+}
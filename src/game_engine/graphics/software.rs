@@ -0,0 +1,282 @@
+use std::path::Path;
+
+use glfw::WindowEvent;
+use image::{RgbaImage, Rgba};
+
+use super::atlas::SpriteAtlas;
+use super::window::{Viewport, WindowMode};
+use super::{RenderBackend, FrameData, TerrainVertex, SpriteInfo, SpriteInstance, SPRITE_VERTICIES, transform_gl};
+use crate::game_engine::err::EngineError;
+use crate::game_engine::Vector2;
+use crate::game_engine::matrix::Mat4x4;
+
+const CLEAR_COLOR: [f32; 4] = [0.2, 0.3, 0.3, 1.0];
+
+/// Rasterizes frames on the CPU into an RGBA image. Mirrors what the GL shaders do closely enough for snapshot tests.
+pub struct SoftwareBackend {
+    alive: bool,
+    width: u32,
+    height: u32,
+    atlases: Vec<SoftwareAtlas>,
+    terrain: Vec<TerrainVertex>,
+    color: Vec<[f32; 4]>,
+    depth: Vec<f32>,
+    viewport: Viewport,
+    frame: RgbaImage,
+    clipboard: Option<String>
+}
+
+struct SoftwareAtlas {
+    sheet: RgbaImage,
+    sprite_infos: Vec<SpriteInfo>,
+    sprite_pivots: Vec<Vector2>
+}
+
+fn edge(a: (f32, f32), b: (f32, f32), p: (f32, f32)) -> f32 {
+    (b.0 - a.0) * (p.1 - a.1) - (b.1 - a.1) * (p.0 - a.0)
+}
+
+impl SoftwareBackend {
+    /// Creates a backend with no atlases. Sprites aren't drawn until one is added.
+    pub fn new(width: u32, height: u32) -> SoftwareBackend {
+        SoftwareBackend {
+            alive: true,
+            width,
+            height,
+            atlases: Vec::new(),
+            terrain: Vec::new(),
+            color: vec![CLEAR_COLOR; (width * height) as usize],
+            depth: vec![1.0; (width * height) as usize],
+            viewport: Viewport::full((width, height)),
+            frame: RgbaImage::new(width, height),
+            clipboard: None
+        }
+    }
+
+    /// The last rendered frame. Row 0 is the top of the screen.
+    pub fn get_frame(&self) -> &RgbaImage {
+        &self.frame
+    }
+
+    pub fn save_frame<P: AsRef<Path>>(&self, path: P) -> Result<(), EngineError> {
+        let result = self.frame.save(path);
+
+        if result.is_err() {
+            return Err(format!("Failed to save frame: {}", result.err().unwrap()).into());
+        }
+
+        Ok(())
+    }
+
+    fn to_screen(&self, clip: [f32; 4]) -> (f32, f32) {
+        let x = clip[0] / clip[3];
+        let y = clip[1] / clip[3];
+        let vp = &self.viewport;
+
+        (vp.x as f32 + (x + 1.0) * 0.5 * vp.width as f32, vp.y as f32 + (1.0 - y) * 0.5 * vp.height as f32)
+    }
+
+    fn sample(&self, atlas: usize, u: f32, v: f32) -> [f32; 4] {
+        let sheet = &self.atlases[atlas].sheet;
+        let x = ((u * sheet.width() as f32) as i64).clamp(0, sheet.width() as i64 - 1) as u32;
+        let y = ((v * sheet.height() as f32) as i64).clamp(0, sheet.height() as i64 - 1) as u32;
+        let p = sheet.get_pixel(x, y);
+
+        [p[0] as f32 / 255.0, p[1] as f32 / 255.0, p[2] as f32 / 255.0, p[3] as f32 / 255.0]
+    }
+
+    fn blend(&mut self, x: u32, y: u32, src: [f32; 4]) {
+        let dst = &mut self.color[(y * self.width + x) as usize];
+        let a = src[3];
+
+        for c in 0..4 {
+            dst[c] = src[c] * a + dst[c] * (1.0 - a);
+        }
+    }
+
+    /// Fills a screen-space triangle, calling `shade` with the barycentric weights of every covered pixel center.
+    fn raster_triangle<F: FnMut(&mut SoftwareBackend, u32, u32, [f32; 3])>(&mut self, p: [(f32, f32); 3], mut shade: F) {
+        let area = edge(p[0], p[1], p[2]);
+
+        if area == 0.0 {
+            return;
+        }
+
+        // Clipped to the viewport, like GL's scissor test
+        let vp = self.viewport;
+        let min_x = p.iter().map(|v| v.0).fold(f32::MAX, f32::min).floor().max(vp.x as f32) as u32;
+        let max_x = p.iter().map(|v| v.0).fold(f32::MIN, f32::max).ceil().min((vp.x + vp.width) as f32) as u32;
+        let min_y = p.iter().map(|v| v.1).fold(f32::MAX, f32::min).floor().max(vp.y as f32) as u32;
+        let max_y = p.iter().map(|v| v.1).fold(f32::MIN, f32::max).ceil().min((vp.y + vp.height) as f32) as u32;
+
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let c = (x as f32 + 0.5, y as f32 + 0.5);
+                let w0 = edge(p[1], p[2], c) / area;
+                let w1 = edge(p[2], p[0], c) / area;
+                let w2 = edge(p[0], p[1], c) / area;
+
+                if w0 >= 0.0 && w1 >= 0.0 && w2 >= 0.0 {
+                    shade(self, x, y, [w0, w1, w2]);
+                }
+            }
+        }
+    }
+
+    fn draw_terrain(&mut self, transform: &Mat4x4) {
+        let terrain = self.terrain.clone();
+
+        for tri in terrain.chunks_exact(3) {
+            let p = [0, 1, 2].map(|i| self.to_screen(transform_gl(transform, [tri[i].x, tri[i].y, tri[i].z, 1.0])));
+
+            self.raster_triangle(p, |backend, x, y, w| {
+                let r = tri[0].r * w[0] + tri[1].r * w[1] + tri[2].r * w[2];
+                let g = tri[0].g * w[0] + tri[1].g * w[1] + tri[2].g * w[2];
+                let b = tri[0].b * w[0] + tri[1].b * w[1] + tri[2].b * w[2];
+
+                backend.blend(x, y, [r, g, b, 1.0]);
+            });
+        }
+    }
+
+    fn draw_sprites(&mut self, frame: &FrameData, view_matrix: &Mat4x4, projection_matrix: &Mat4x4) {
+        for batch in frame.batches {
+            if batch.atlas >= self.atlases.len() {
+                continue;
+            }
+
+            for instance in &frame.sprites[batch.start..batch.start + batch.count] {
+                self.draw_sprite(batch.atlas, instance, frame.depth_buffer, batch.opaque, view_matrix, projection_matrix);
+            }
+        }
+    }
+
+    fn draw_sprite(&mut self, atlas: usize, instance: &SpriteInstance, depth_test: bool, opaque: bool, view_matrix: &Mat4x4, projection_matrix: &Mat4x4) {
+        let atlas_data = &self.atlases[atlas];
+
+        // The fragment shader multiplies by zero for these, which blends to nothing.
+        if instance.sprite_id <= 0 || instance.sprite_id as usize >= atlas_data.sprite_infos.len() {
+            return;
+        }
+
+        let info = atlas_data.sprite_infos[instance.sprite_id as usize];
+        let pivot = atlas_data.sprite_pivots[instance.sprite_id as usize];
+        let rect = &instance.rect;
+        let transform = &instance.transform;
+        let tint = [instance.tint[0], instance.tint[1], instance.tint[2], instance.tint[3] * transform[3]];
+        let offset = transform_gl(view_matrix, [rect[0], rect[1], 0.0, 1.0]);
+        let (s, c) = transform[0].sin_cos();
+        let depth = instance.depth;
+        let cutoff = if opaque { 0.5 } else { 0.0 };
+        let pivot_x = if transform[1] != 0.0 { 1.0 - pivot.x } else { pivot.x };
+        let pivot_y = if transform[2] != 0.0 { 1.0 - pivot.y } else { pivot.y };
+
+        let verts: Vec<((f32, f32), (f32, f32))> = SPRITE_VERTICIES.iter().map(|v| {
+            let (x, y) = ((v.x + 0.5 - pivot_x) * rect[2], (v.y + 0.5 - pivot_y) * rect[3]);
+            let (x, y) = (x * c - y * s, x * s + y * c);
+            let clip = transform_gl(projection_matrix, [x + offset[0], y + offset[1], v.z, 1.0]);
+
+            let u = if transform[1] != 0.0 { 1.0 - v.u } else { v.u };
+            let uv_v = if transform[2] != 0.0 { 1.0 - v.v } else { v.v };
+
+            (self.to_screen(clip), (info.u + u * info.w, info.v + uv_v * info.h))
+        }).collect();
+
+        for tri in verts.chunks_exact(3) {
+            let p = [tri[0].0, tri[1].0, tri[2].0];
+            let uv = [tri[0].1, tri[1].1, tri[2].1];
+
+            self.raster_triangle(p, |backend, x, y, w| {
+                let i = (y * backend.width + x) as usize;
+
+                if depth_test && depth >= backend.depth[i] {
+                    return;
+                }
+
+                let u = uv[0].0 * w[0] + uv[1].0 * w[1] + uv[2].0 * w[2];
+                let v = uv[0].1 * w[0] + uv[1].1 * w[1] + uv[2].1 * w[2];
+                let color = backend.sample(atlas, u, v);
+                let color = [color[0] * tint[0], color[1] * tint[1], color[2] * tint[2], color[3] * tint[3]];
+
+                if color[3] < cutoff {
+                    return;
+                }
+
+                backend.blend(x, y, color);
+
+                if opaque {
+                    backend.depth[i] = depth;
+                }
+            });
+        }
+    }
+}
+
+impl RenderBackend for SoftwareBackend {
+    fn add_atlas(&mut self, atlas: &SpriteAtlas) {
+        self.atlases.push(SoftwareAtlas { sheet: atlas.get_image().clone(), sprite_infos: atlas.get_sprite_infos(), sprite_pivots: atlas.get_pivots() });
+    }
+
+    fn buffer_terrain_verticies(&mut self, verticies: &[TerrainVertex]) {
+        self.terrain = verticies.to_vec();
+    }
+
+    fn render(&mut self, frame: &FrameData) {
+        self.viewport = frame.viewport;
+        self.color.fill([0.0, 0.0, 0.0, 1.0]);
+        self.depth.fill(1.0);
+
+        for y in self.viewport.y..(self.viewport.y + self.viewport.height).min(self.height) {
+            let row = (y * self.width) as usize;
+            let (start, end) = (self.viewport.x as usize, (self.viewport.x + self.viewport.width).min(self.width) as usize);
+
+            self.color[row + start..row + end].fill(CLEAR_COLOR);
+        }
+
+        let view_matrix = frame.camera.get_viewmatrix();
+        let ortho = frame.camera.ortho();
+
+        self.draw_terrain(&(view_matrix * ortho));
+        self.draw_sprites(frame, &view_matrix, &ortho);
+
+        for (i, c) in self.color.iter().enumerate() {
+            let px = c.map(|v| (v.clamp(0.0, 1.0) * 255.0).round() as u8);
+
+            self.frame.put_pixel(i as u32 % self.width, i as u32 / self.width, Rgba(px));
+        }
+    }
+
+    fn window_alive(&self) -> bool {
+        self.alive
+    }
+
+    fn close_window(&mut self) {
+        self.alive = false;
+    }
+
+    fn get_window_events(&mut self) -> Vec<(f64, WindowEvent)> {
+        Vec::new()
+    }
+
+    fn swap_window_buffers(&mut self) {}
+
+    fn get_window_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn get_framebuffer_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn set_window_mode(&mut self, _mode: WindowMode, _monitor: usize) -> Result<(), EngineError> {
+        Ok(())
+    }
+
+    fn get_clipboard(&self) -> Option<String> {
+        self.clipboard.clone()
+    }
+
+    fn set_clipboard(&mut self, text: &str) {
+        self.clipboard = Some(text.to_owned());
+    }
+}
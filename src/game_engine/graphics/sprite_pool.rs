@@ -0,0 +1,169 @@
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::rc::{Rc, Weak};
+
+use super::SpriteInstance;
+
+/// Sprite instance slots, handed out from a free-list.
+#[derive(Default)]
+pub struct SpritePool {
+    instances: Vec<SpriteInstance>,
+    /// Sorted, so the lowest slot gets reused first and the tail can be trimmed without searching
+    free: BTreeSet<usize>,
+    live: usize
+}
+
+/// Owns one slot in the sprite pool. The slot is cleared and given back when the handle is dropped.
+#[derive(Debug)]
+pub struct SpriteHandle {
+    index: usize,
+    pool: Weak<RefCell<SpritePool>>
+}
+
+impl SpritePool {
+    pub fn new() -> Rc<RefCell<SpritePool>> {
+        Rc::new(RefCell::new(SpritePool::default()))
+    }
+
+    pub fn alloc(pool: &Rc<RefCell<SpritePool>>) -> SpriteHandle {
+        let mut p = pool.borrow_mut();
+
+        let index = match p.free.pop_first() {
+            Some(index) => index,
+            None => {
+                p.instances.push(SpriteInstance::default());
+                p.instances.len() - 1
+            }
+        };
+
+        p.live += 1;
+
+        SpriteHandle { index, pool: Rc::downgrade(pool) }
+    }
+
+    fn release(&mut self, index: usize) {
+        self.instances[index] = SpriteInstance::default();
+        self.free.insert(index);
+        self.live -= 1;
+
+        // Trim free slots off the end so they aren't drawn
+        while !self.instances.is_empty() && self.free.last() == Some(&(self.instances.len() - 1)) {
+            self.free.pop_last();
+            self.instances.pop();
+        }
+    }
+
+    pub fn set(&mut self, handle: &SpriteHandle, instance: SpriteInstance) {
+        self.instances[handle.index] = instance;
+    }
+
+    pub fn get(&self, handle: &SpriteHandle) -> SpriteInstance {
+        self.instances[handle.index]
+    }
+
+    /// All slots up to the last one in use. Free slots in between have a sprite id of 0.
+    pub fn get_instances(&self) -> &[SpriteInstance] {
+        &self.instances
+    }
+
+    /// Number of slots currently handed out.
+    pub fn get_live_count(&self) -> usize {
+        self.live
+    }
+}
+
+impl SpriteHandle {
+    /// Whether the handle belongs to `pool`.
+    pub fn is_from(&self, pool: &Rc<RefCell<SpritePool>>) -> bool {
+        self.pool.ptr_eq(&Rc::downgrade(pool))
+    }
+}
+
+impl Drop for SpriteHandle {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.upgrade() {
+            pool.borrow_mut().release(self.index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn instance(sprite_id: i32) -> SpriteInstance {
+        SpriteInstance { sprite_id, ..SpriteInstance::default() }
+    }
+
+    #[test]
+    fn dropping_a_handle_frees_its_slot() {
+        let pool = SpritePool::new();
+        let a = SpritePool::alloc(&pool);
+        let b = SpritePool::alloc(&pool);
+        let c = SpritePool::alloc(&pool);
+        pool.borrow_mut().set(&b, instance(7));
+
+        drop(b);
+
+        // The hole stays, cleared, so the slots after it don't move
+        let p = pool.borrow();
+        assert_eq!(p.get_live_count(), 2);
+        assert_eq!(p.get_instances().len(), 3);
+        assert_eq!(p.get_instances()[1].sprite_id, 0);
+        drop(p);
+
+        drop((a, c));
+        assert_eq!(pool.borrow().get_live_count(), 0);
+        assert!(pool.borrow().get_instances().is_empty());
+    }
+
+    #[test]
+    fn lowest_free_slot_is_reused_first() {
+        let pool = SpritePool::new();
+        let handles: Vec<SpriteHandle> = (0..5).map(|_| SpritePool::alloc(&pool)).collect();
+        let mut handles: Vec<Option<SpriteHandle>> = handles.into_iter().map(Some).collect();
+
+        handles[3] = None;
+        handles[1] = None;
+
+        let reused = SpritePool::alloc(&pool);
+        assert_eq!(reused.index, 1);
+        let reused_again = SpritePool::alloc(&pool);
+        assert_eq!(reused_again.index, 3);
+        assert_eq!(SpritePool::alloc(&pool).index, 5);
+    }
+
+    #[test]
+    fn freed_tail_is_trimmed_past_earlier_holes() {
+        let pool = SpritePool::new();
+        let mut handles: Vec<Option<SpriteHandle>> = (0..6).map(|_| Some(SpritePool::alloc(&pool))).collect();
+
+        // Holes at 1, 3 and 4, then the last slot goes and takes 3 and 4 with it
+        handles[1] = None;
+        handles[3] = None;
+        handles[4] = None;
+        assert_eq!(pool.borrow().get_instances().len(), 6);
+
+        handles[5] = None;
+        assert_eq!(pool.borrow().get_instances().len(), 3);
+        assert_eq!(pool.borrow().free.iter().copied().collect::<Vec<usize>>(), vec![1]);
+
+        handles[2] = None;
+        handles[0] = None;
+        assert!(pool.borrow().get_instances().is_empty());
+        assert!(pool.borrow().free.is_empty());
+    }
+
+    #[test]
+    fn handles_outliving_the_pool_do_nothing() {
+        let pool = SpritePool::new();
+        let handle = SpritePool::alloc(&pool);
+        let other = SpritePool::new();
+
+        assert!(handle.is_from(&pool));
+        assert!(!handle.is_from(&other));
+
+        drop(pool);
+        drop(handle);
+    }
+}
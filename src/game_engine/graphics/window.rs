@@ -0,0 +1,154 @@
+use crate::game_engine::Vector2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    /// Exclusive fullscreen at the monitor's current video mode.
+    Fullscreen,
+    /// An undecorated window covering the whole monitor.
+    Borderless
+}
+
+/// Settings for the window created by `Engine::init_with_config`.
+#[derive(Clone, Debug)]
+pub struct WindowConfig {
+    /// Size in screen coordinates when windowed. Fullscreen and borderless use the monitor's size.
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    pub vsync: bool,
+    pub resizable: bool,
+    pub mode: WindowMode,
+    /// Index into the connected monitors. 0 is the primary monitor.
+    pub monitor: usize
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig { width: 800, height: 600, title: "Wob".to_owned(), vsync: true, resizable: true, mode: WindowMode::Windowed, monitor: 0 }
+    }
+}
+
+/// How the camera's view is fitted to a window of a different shape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScalingPolicy {
+    /// Squashes `Camera.size` to fill the window. Distorts the image if the aspect ratios differ.
+    Stretch,
+    /// Keeps `Camera.size.y` and shows more or less to the sides to match the window. `Camera.size.x` is ignored.
+    FixedHeight,
+    /// Keeps `Camera.size.x` and shows more or less above and below to match the window. `Camera.size.y` is ignored.
+    FixedWidth,
+    /// Shows exactly `Camera.size` as large as it fits, with black bars filling the rest of the window.
+    Letterbox,
+    /// Shows exactly `Camera.size` at `width`x`height` pixels, scaled up by the largest whole number that fits, with black bars around it.
+    PixelPerfect { width: u32, height: u32 }
+}
+
+/// Area of the framebuffer that is drawn to, in pixels from the top left.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32
+}
+
+impl Viewport {
+    pub fn full(framebuffer_size: (u32, u32)) -> Viewport {
+        Viewport { x: 0, y: 0, width: framebuffer_size.0, height: framebuffer_size.1 }
+    }
+
+    /// A `width`x`height` viewport centered in the framebuffer.
+    fn centered(framebuffer_size: (u32, u32), width: u32, height: u32) -> Viewport {
+        let (width, height) = (width.min(framebuffer_size.0), height.min(framebuffer_size.1));
+
+        Viewport { x: (framebuffer_size.0 - width) / 2, y: (framebuffer_size.1 - height) / 2, width, height }
+    }
+}
+
+impl ScalingPolicy {
+    /// Returns the viewport to draw to and the size of the world area the camera should show in it.
+    pub fn apply(&self, camera_size: Vector2, framebuffer_size: (u32, u32)) -> (Viewport, Vector2) {
+        let (fb_w, fb_h) = (framebuffer_size.0.max(1) as f32, framebuffer_size.1.max(1) as f32);
+        let full = Viewport::full(framebuffer_size);
+
+        match *self {
+            ScalingPolicy::Stretch => (full, camera_size),
+            ScalingPolicy::FixedHeight => (full, Vector2::new(camera_size.y * fb_w / fb_h, camera_size.y)),
+            ScalingPolicy::FixedWidth => (full, Vector2::new(camera_size.x, camera_size.x * fb_h / fb_w)),
+            ScalingPolicy::Letterbox => {
+                let scale = f32::min(fb_w / camera_size.x, fb_h / camera_size.y);
+                let viewport = Viewport::centered(framebuffer_size, (camera_size.x * scale).round() as u32, (camera_size.y * scale).round() as u32);
+
+                (viewport, camera_size)
+            },
+            ScalingPolicy::PixelPerfect { width, height } => {
+                // Never below 1x, even if the window is smaller than the target
+                let scale = u32::max(1, u32::min(framebuffer_size.0 / width.max(1), framebuffer_size.1 / height.max(1)));
+
+                (Viewport::centered(framebuffer_size, width * scale, height * scale), camera_size)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 4:3 camera in a 5:3 framebuffer
+    const CAMERA: Vector2 = Vector2 { x: 2.4, y: 1.8 };
+    const FRAMEBUFFER: (u32, u32) = (200, 120);
+
+    fn assert_size(actual: Vector2, expected: Vector2) {
+        assert!((actual - expected).magnitude() < 1e-4, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn stretch_fills_the_window() {
+        let (viewport, size) = ScalingPolicy::Stretch.apply(CAMERA, FRAMEBUFFER);
+
+        assert_eq!(viewport, Viewport::full(FRAMEBUFFER));
+        assert_size(size, CAMERA);
+    }
+
+    #[test]
+    fn fixed_height_and_width_widen_the_view() {
+        let (viewport, size) = ScalingPolicy::FixedHeight.apply(CAMERA, FRAMEBUFFER);
+        assert_eq!(viewport, Viewport::full(FRAMEBUFFER));
+        assert_size(size, Vector2::new(3.0, 1.8));
+
+        let (viewport, size) = ScalingPolicy::FixedWidth.apply(CAMERA, FRAMEBUFFER);
+        assert_eq!(viewport, Viewport::full(FRAMEBUFFER));
+        assert_size(size, Vector2::new(2.4, 1.44));
+    }
+
+    #[test]
+    fn letterbox_centers_the_view() {
+        let (viewport, size) = ScalingPolicy::Letterbox.apply(CAMERA, FRAMEBUFFER);
+        assert_eq!(viewport, Viewport { x: 20, y: 0, width: 160, height: 120 });
+        assert_size(size, CAMERA);
+
+        // Bars above and below when the window is too tall instead
+        let (viewport, _) = ScalingPolicy::Letterbox.apply(CAMERA, (160, 200));
+        assert_eq!(viewport, Viewport { x: 0, y: 40, width: 160, height: 120 });
+    }
+
+    #[test]
+    fn pixel_perfect_scales_by_whole_numbers() {
+        let policy = ScalingPolicy::PixelPerfect { width: 80, height: 60 };
+
+        assert_eq!(policy.apply(CAMERA, FRAMEBUFFER).0, Viewport { x: 20, y: 0, width: 160, height: 120 });
+        assert_eq!(policy.apply(CAMERA, (250, 190)).0, Viewport { x: 5, y: 5, width: 240, height: 180 });
+
+        // Never below 1x, cropped to the window instead
+        assert_eq!(policy.apply(CAMERA, (40, 30)).0, Viewport { x: 0, y: 0, width: 40, height: 30 });
+    }
+
+    #[test]
+    fn empty_framebuffer_does_not_divide_by_zero() {
+        let (_, size) = ScalingPolicy::FixedHeight.apply(CAMERA, (0, 0));
+
+        assert!(size.x.is_finite() && size.y.is_finite());
+    }
+}
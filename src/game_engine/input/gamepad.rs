@@ -0,0 +1,538 @@
+use std::ptr::addr_of;
+
+use downcast_rs::{Downcast, impl_downcast};
+use glfw::{GamepadAxis, GamepadButton, JoystickId};
+
+use crate::game_engine::err::EngineError;
+use crate::game_engine::graphics::GLFW;
+
+/// GLFW supports this many joysticks at once.
+pub const MAX_GAMEPADS: usize = 16;
+
+/// Raw state of one gamepad, laid out like GLFW's standard gamepad.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gamepad {
+    pub name: String,
+    /// Indexed by `GamepadButton as usize`
+    pub buttons: [bool; 15],
+    /// Indexed by `GamepadAxis as usize`. Sticks go from -1 to 1 with +y down, triggers from -1 (released) to 1.
+    pub axes: [f32; 6]
+}
+
+impl Gamepad {
+    /// A gamepad with nothing pressed.
+    pub fn new(name: &str) -> Gamepad {
+        Gamepad { name: name.to_owned(), buttons: [false; 15], axes: [0.0, 0.0, 0.0, 0.0, -1.0, -1.0] }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GamepadEvent {
+    Connected(usize),
+    Disconnected(usize)
+}
+
+/// One line of an SDL_GameControllerDB mapping file: `guid,name,a:b0,b:b1,...,platform:Linux,`
+#[derive(Clone, Debug, PartialEq)]
+pub struct GamepadMapping {
+    pub guid: String,
+    pub name: String,
+    pub fields: Vec<(String, String)>
+}
+
+impl GamepadMapping {
+    /// Parses every mapping in `db`, skipping blank lines and `#` comments. Malformed lines are skipped as well, with one
+    /// error for each, so a single bad entry doesn't throw away the rest of a database.
+    pub fn parse_db(db: &str) -> (Vec<GamepadMapping>, Vec<EngineError>) {
+        let mut mappings = Vec::new();
+        let mut errors = Vec::new();
+
+        for (i, line) in db.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            match GamepadMapping::parse_line(line) {
+                Ok(mapping) => mappings.push(mapping),
+                Err(e) => errors.push(format!("Gamepad mapping line {}: {}", i + 1, e).into())
+            }
+        }
+
+        (mappings, errors)
+    }
+
+    fn parse_line(line: &str) -> Result<GamepadMapping, String> {
+        // Lines usually end with a comma, that's the only empty field allowed
+        let mut parts = line.strip_suffix(',').unwrap_or(line).split(',');
+        let guid = parts.next().unwrap_or("");
+        let name = parts.next().unwrap_or("");
+
+        if guid.len() != 32 || !guid.chars().all(|c| c.is_ascii_hexdigit()) || name.is_empty() {
+            return Err("expected \"<32 digit hex guid>,<name>,<field>:<value>,...\"".to_owned());
+        }
+
+        let mut fields = Vec::new();
+        for part in parts {
+            match part.split_once(':') {
+                Some((k, v)) if !k.is_empty() && !v.is_empty() => fields.push((k.to_owned(), v.to_owned())),
+                _ => return Err(format!("\"{}\" is not a <field>:<value> pair", part))
+            }
+        }
+
+        Ok(GamepadMapping { guid: guid.to_ascii_lowercase(), name: name.to_owned(), fields })
+    }
+}
+
+/// Where `Gamepads` gets its state from.
+pub trait GamepadSource: Downcast {
+    /// Every connected gamepad and its current state, by slot. A gamepad keeps its slot while it stays connected.
+    fn poll(&mut self) -> Vec<(usize, Gamepad)>;
+    /// Adds mappings in SDL_GameControllerDB format, so more controllers are recognised as gamepads.
+    fn add_mappings(&mut self, mappings: &[GamepadMapping]) -> Result<(), EngineError>;
+}
+
+impl_downcast!(GamepadSource);
+
+/// Reads joysticks through GLFW. Only joysticks GLFW has a gamepad mapping for are reported.
+pub struct GlfwGamepadSource;
+
+impl GamepadSource for GlfwGamepadSource {
+    fn poll(&mut self) -> Vec<(usize, Gamepad)> {
+        let glfw = unsafe { (*addr_of!(GLFW)).assume_init_ref() };
+        let mut pads = Vec::new();
+
+        for slot in 0..MAX_GAMEPADS {
+            let joystick = glfw.get_joystick(JoystickId::from_i32(slot as i32).unwrap());
+
+            if !joystick.is_present() || !joystick.is_gamepad() {
+                continue;
+            }
+
+            if let Some(state) = joystick.get_gamepad_state() {
+                let mut pad = Gamepad::new(&joystick.get_gamepad_name().unwrap_or_default());
+
+                for (i, pressed) in pad.buttons.iter_mut().enumerate() {
+                    *pressed = state.get_button_state(GamepadButton::from_i32(i as i32).unwrap()) == glfw::Action::Press;
+                }
+
+                for (i, value) in pad.axes.iter_mut().enumerate() {
+                    *value = state.get_axis(GamepadAxis::from_i32(i as i32).unwrap());
+                }
+
+                pads.push((slot, pad));
+            }
+        }
+
+        pads
+    }
+
+    fn add_mappings(&mut self, mappings: &[GamepadMapping]) -> Result<(), EngineError> {
+        let glfw = unsafe { (*addr_of!(GLFW)).assume_init_ref() };
+
+        let db: String = mappings.iter().map(|m| {
+            let fields: String = m.fields.iter().map(|(k, v)| format!("{}:{},", k, v)).collect();
+
+            format!("{},{},{}\n", m.guid, m.name, fields)
+        }).collect();
+
+        if !glfw.update_gamepad_mappings(&db) {
+            return Err("GLFW rejected the gamepad mappings.".into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Gamepads driven by code, for tests and headless runs.
+#[derive(Default)]
+pub struct MockGamepadSource {
+    pads: Vec<(usize, Gamepad)>,
+    mappings: Vec<GamepadMapping>
+}
+
+impl MockGamepadSource {
+    pub fn new() -> MockGamepadSource {
+        MockGamepadSource::default()
+    }
+
+    pub fn connect(&mut self, slot: usize, name: &str) {
+        self.disconnect(slot);
+        self.pads.push((slot, Gamepad::new(name)));
+    }
+
+    pub fn disconnect(&mut self, slot: usize) {
+        self.pads.retain(|(s, _)| *s != slot);
+    }
+
+    pub fn set_button(&mut self, slot: usize, button: GamepadButton, pressed: bool) {
+        if let Some((_, pad)) = self.pads.iter_mut().find(|(s, _)| *s == slot) {
+            pad.buttons[button as usize] = pressed;
+        }
+    }
+
+    /// Sets a raw axis value, so triggers rest at -1.
+    pub fn set_axis(&mut self, slot: usize, axis: GamepadAxis, value: f32) {
+        if let Some((_, pad)) = self.pads.iter_mut().find(|(s, _)| *s == slot) {
+            pad.axes[axis as usize] = value;
+        }
+    }
+
+    pub fn get_mappings(&self) -> &[GamepadMapping] {
+        &self.mappings
+    }
+}
+
+impl GamepadSource for MockGamepadSource {
+    fn poll(&mut self) -> Vec<(usize, Gamepad)> {
+        self.pads.clone()
+    }
+
+    fn add_mappings(&mut self, mappings: &[GamepadMapping]) -> Result<(), EngineError> {
+        self.mappings.extend_from_slice(mappings);
+
+        Ok(())
+    }
+}
+
+/// The other axis of the same stick, for radial dead-zones.
+fn stick_partner(axis: GamepadAxis) -> Option<GamepadAxis> {
+    match axis {
+        GamepadAxis::AxisLeftX => Some(GamepadAxis::AxisLeftY),
+        GamepadAxis::AxisLeftY => Some(GamepadAxis::AxisLeftX),
+        GamepadAxis::AxisRightX => Some(GamepadAxis::AxisRightY),
+        GamepadAxis::AxisRightY => Some(GamepadAxis::AxisRightX),
+        _ => None
+    }
+}
+
+/// State of every connected gamepad, updated once per frame from a `GamepadSource`.
+pub struct Gamepads {
+    source: Box<dyn GamepadSource>,
+    pads: Vec<Option<Gamepad>>,
+    events: Vec<GamepadEvent>,
+    dead_zone: f32
+}
+
+impl Gamepads {
+    pub fn new(source: Box<dyn GamepadSource>) -> Gamepads {
+        Gamepads { source, pads: vec![None; MAX_GAMEPADS], events: Vec::new(), dead_zone: 0.2 }
+    }
+
+    /// Replaces the source. Gamepads from the old one show up as disconnected on the next update.
+    pub fn set_source(&mut self, source: Box<dyn GamepadSource>) {
+        self.source = source;
+    }
+
+    pub fn get_source<T: GamepadSource>(&self) -> Option<&T> {
+        self.source.downcast_ref()
+    }
+
+    pub fn get_source_mut<T: GamepadSource>(&mut self) -> Option<&mut T> {
+        self.source.downcast_mut()
+    }
+
+    /// Loads an SDL_GameControllerDB file, e.g. `gamecontrollerdb.txt`. Returns how many malformed lines were skipped,
+    /// `GamepadMapping::parse_db` says why.
+    pub fn load_mappings<P: AsRef<std::path::Path>>(&mut self, path: P) -> Result<usize, EngineError> {
+        let db = std::fs::read_to_string(path.as_ref());
+        if db.is_err() {
+            return Err(format!("Failed to read {}: {}", path.as_ref().display(), db.err().unwrap()).into());
+        }
+
+        let (mappings, errors) = GamepadMapping::parse_db(&db.unwrap());
+
+        let result = self.source.add_mappings(&mappings);
+        if result.is_err() {
+            return Err(result.err().unwrap());
+        }
+
+        Ok(errors.len())
+    }
+
+    pub(in crate::game_engine) fn poll_source(&mut self) -> Vec<(usize, Gamepad)> {
+        self.source.poll()
+    }
+
+    /// Takes in this frame's state, as returned by `poll_source` or stored in a replay.
+    pub(in crate::game_engine) fn update(&mut self, pads: Vec<(usize, Gamepad)>) {
+        self.events.clear();
+
+        let mut current: Vec<Option<Gamepad>> = vec![None; MAX_GAMEPADS];
+        for (slot, pad) in pads {
+            if slot < MAX_GAMEPADS {
+                current[slot] = Some(pad);
+            }
+        }
+
+        for (slot, (old, new)) in self.pads.iter().zip(current.iter()).enumerate() {
+            match (old, new) {
+                (None, Some(_)) => self.events.push(GamepadEvent::Connected(slot)),
+                (Some(_), None) => self.events.push(GamepadEvent::Disconnected(slot)),
+                _ => {}
+            }
+        }
+
+        self.pads = current;
+    }
+
+    /// Connects and disconnects since the last frame.
+    pub fn get_events(&self) -> &[GamepadEvent] {
+        &self.events
+    }
+
+    pub fn get_connected(&self) -> Vec<usize> {
+        (0..MAX_GAMEPADS).filter(|slot| self.pads[*slot].is_some()).collect()
+    }
+
+    /// Raw state, without the dead-zone applied.
+    pub fn get(&self, slot: usize) -> Option<&Gamepad> {
+        self.pads.get(slot).and_then(Option::as_ref)
+    }
+
+    /// Stick and trigger movement below this is ignored. Defaults to 0.2.
+    pub fn set_dead_zone(&mut self, dead_zone: f32) {
+        self.dead_zone = dead_zone.clamp(0.0, 0.99);
+    }
+
+    pub fn get_dead_zone(&self) -> f32 {
+        self.dead_zone
+    }
+
+    /// False if no gamepad is connected in `slot`.
+    pub fn get_button(&self, slot: usize, button: GamepadButton) -> bool {
+        self.get(slot).map(|pad| pad.buttons[button as usize]).unwrap_or(false)
+    }
+
+    /// Sticks go from -1 to 1 with +y down, triggers from 0 to 1. Movement inside the dead-zone reads as 0 and the rest is
+    /// rescaled so the output still starts at 0. Sticks use a round dead-zone, so diagonals aren't cut off.
+    pub fn get_axis(&self, slot: usize, axis: GamepadAxis) -> f32 {
+        let pad = match self.get(slot) {
+            Some(pad) => pad,
+            None => return 0.0
+        };
+
+        let value = pad.axes[axis as usize];
+
+        match stick_partner(axis) {
+            Some(partner) => {
+                let other = pad.axes[partner as usize];
+                let magnitude = (value * value + other * other).sqrt();
+
+                if magnitude <= self.dead_zone {
+                    return 0.0;
+                }
+
+                let scaled = ((magnitude - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0);
+
+                value / magnitude * scaled
+            },
+            None => {
+                let value = (value + 1.0) / 2.0;
+
+                if value <= self.dead_zone {
+                    return 0.0;
+                }
+
+                ((value - self.dead_zone) / (1.0 - self.dead_zone)).min(1.0)
+            }
+        }
+    }
+
+    /// Whether `button` is held on any connected gamepad.
+    pub fn any_button(&self, button: GamepadButton) -> bool {
+        self.get_connected().into_iter().any(|slot| self.get_button(slot, button))
+    }
+
+    /// Axis value furthest from 0 across every connected gamepad.
+    pub fn any_axis(&self, axis: GamepadAxis) -> f32 {
+        self.get_connected().into_iter().map(|slot| self.get_axis(slot, axis)).fold(0.0, |a, b| if b.abs() > a.abs() { b } else { a })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GUID: &str = "030000005e0400008e02000010010000";
+
+    fn mock_gamepads() -> Gamepads {
+        Gamepads::new(Box::new(MockGamepadSource::new()))
+    }
+
+    fn mock(gamepads: &mut Gamepads) -> &mut MockGamepadSource {
+        gamepads.get_source_mut::<MockGamepadSource>().unwrap()
+    }
+
+    fn poll(gamepads: &mut Gamepads) {
+        let pads = gamepads.poll_source();
+        gamepads.update(pads);
+    }
+
+    #[test]
+    fn parse_db_reads_mappings() {
+        let db = format!("# Xbox 360\n\n{},Xbox 360 Controller,a:b0,b:b1,leftx:a0,platform:Linux,\n  {},Trimmed,a:b0  \n", GUID, GUID.to_ascii_uppercase());
+        let (mappings, errors) = GamepadMapping::parse_db(&db);
+
+        assert!(errors.is_empty());
+        assert_eq!(mappings.len(), 2);
+        assert_eq!(mappings[0].guid, GUID);
+        assert_eq!(mappings[0].name, "Xbox 360 Controller");
+        assert_eq!(mappings[0].fields, vec![
+            ("a".to_owned(), "b0".to_owned()),
+            ("b".to_owned(), "b1".to_owned()),
+            ("leftx".to_owned(), "a0".to_owned()),
+            ("platform".to_owned(), "Linux".to_owned())
+        ]);
+        assert_eq!(mappings[1].guid, GUID);
+        assert_eq!(mappings[1].fields.len(), 1);
+    }
+
+    #[test]
+    fn parse_db_skips_bad_lines() {
+        let cases = [
+            ("030000005e04,Short guid,a:b0".to_owned(), "expected"),
+            ("zz0000005e0400008e02000010010000,Not hex,a:b0".to_owned(), "expected"),
+            (GUID.to_owned(), "expected"),
+            (format!(",{},a:b0", GUID), "expected"),
+            (format!("{},,a:b0", GUID), "expected"),
+            (format!("{},Pad,a:b0,nocolon", GUID), "\"nocolon\""),
+            (format!("{},Pad,a:b0,,b:b1", GUID), "\"\""),
+            (format!("{},Pad,a:,b:b1", GUID), "\"a:\""),
+            (format!("{},Pad,a:b0,,", GUID), "\"\"")
+        ];
+
+        for (line, expected) in cases {
+            let db = format!("# comment\n{}\n{},Good,a:b0,\n", line, GUID);
+            let (mappings, errors) = GamepadMapping::parse_db(&db);
+
+            // The good line after it still loads
+            assert_eq!(mappings.len(), 1, "{:?}", line);
+            assert_eq!(mappings[0].name, "Good");
+            assert_eq!(errors.len(), 1, "{:?}", line);
+
+            let err = errors[0].get_error_message();
+            assert!(err.starts_with("Gamepad mapping line 2: ") && err.contains(expected), "{:?} gave {:?}", line, err);
+        }
+    }
+
+    #[test]
+    fn load_mappings_passes_them_to_the_source() {
+        let path = std::env::temp_dir().join("gamepad_mappings_test.txt");
+        std::fs::write(&path, format!("{},Pad,a:b0,\n{},,a:b0\n", GUID, GUID)).unwrap();
+
+        let mut gamepads = mock_gamepads();
+        let skipped = gamepads.load_mappings(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(skipped, 1);
+        assert_eq!(mock(&mut gamepads).get_mappings().len(), 1);
+        assert!(gamepads.load_mappings(std::env::temp_dir().join("missing_gamepad_mappings.txt")).is_err());
+    }
+
+    #[test]
+    fn connect_and_disconnect_events() {
+        let mut gamepads = mock_gamepads();
+        poll(&mut gamepads);
+        assert!(gamepads.get_events().is_empty());
+
+        mock(&mut gamepads).connect(0, "Pad A");
+        mock(&mut gamepads).connect(3, "Pad B");
+        poll(&mut gamepads);
+        assert_eq!(gamepads.get_events(), &[GamepadEvent::Connected(0), GamepadEvent::Connected(3)]);
+        assert_eq!(gamepads.get_connected(), vec![0, 3]);
+        assert_eq!(gamepads.get(3).unwrap().name, "Pad B");
+
+        // Events only last one frame
+        poll(&mut gamepads);
+        assert!(gamepads.get_events().is_empty());
+
+        mock(&mut gamepads).disconnect(0);
+        poll(&mut gamepads);
+        assert_eq!(gamepads.get_events(), &[GamepadEvent::Disconnected(0)]);
+        assert_eq!(gamepads.get_connected(), vec![3]);
+        assert!(gamepads.get(0).is_none());
+
+        // Swapping the source drops everything from the old one
+        gamepads.set_source(Box::new(MockGamepadSource::new()));
+        poll(&mut gamepads);
+        assert_eq!(gamepads.get_events(), &[GamepadEvent::Disconnected(3)]);
+    }
+
+    #[test]
+    fn missing_gamepads_read_as_released() {
+        let mut gamepads = mock_gamepads();
+        mock(&mut gamepads).connect(1, "Pad");
+        mock(&mut gamepads).set_button(1, GamepadButton::ButtonA, true);
+        poll(&mut gamepads);
+
+        assert!(gamepads.get_button(1, GamepadButton::ButtonA));
+        assert!(gamepads.any_button(GamepadButton::ButtonA));
+        assert!(!gamepads.get_button(0, GamepadButton::ButtonA));
+        assert_eq!(gamepads.get_axis(0, GamepadAxis::AxisLeftX), 0.0);
+        assert_eq!(gamepads.get_axis(MAX_GAMEPADS, GamepadAxis::AxisLeftX), 0.0);
+    }
+
+    fn stick(x: f32, y: f32) -> (f32, f32) {
+        let mut gamepads = mock_gamepads();
+        mock(&mut gamepads).connect(0, "Pad");
+        mock(&mut gamepads).set_axis(0, GamepadAxis::AxisLeftX, x);
+        mock(&mut gamepads).set_axis(0, GamepadAxis::AxisLeftY, y);
+        poll(&mut gamepads);
+
+        (gamepads.get_axis(0, GamepadAxis::AxisLeftX), gamepads.get_axis(0, GamepadAxis::AxisLeftY))
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!((actual - expected).abs() < 1e-5, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn stick_dead_zone_is_round() {
+        // Inside the 0.2 circle, even though each axis alone is past half of it
+        assert_eq!(stick(0.14, 0.14), (0.0, 0.0));
+        assert_eq!(stick(-0.2, 0.0), (0.0, 0.0));
+
+        // Rescaled so the output starts at 0 right outside the dead-zone
+        let (x, y) = stick(0.6, 0.0);
+        assert_close(x, 0.5);
+        assert_eq!(y, 0.0);
+
+        // A diagonal keeps its direction and is scaled by its length, not per axis
+        let (x, y) = stick(0.36, -0.48);
+        assert_close(x, 0.6 * 0.5);
+        assert_close(y, -0.8 * 0.5);
+
+        // Corners of a square stick range are clamped to the unit circle
+        let (x, y) = stick(1.0, 1.0);
+        assert_close((x * x + y * y).sqrt(), 1.0);
+        assert_close(x, y);
+    }
+
+    #[test]
+    fn triggers_start_at_zero() {
+        let mut gamepads = mock_gamepads();
+        mock(&mut gamepads).connect(0, "Pad");
+        poll(&mut gamepads);
+        assert_eq!(gamepads.get_axis(0, GamepadAxis::AxisLeftTrigger), 0.0);
+
+        // Halfway down is 0.5 raw, then rescaled past the 0.2 dead-zone
+        mock(&mut gamepads).set_axis(0, GamepadAxis::AxisLeftTrigger, 0.0);
+        mock(&mut gamepads).set_axis(0, GamepadAxis::AxisRightTrigger, 1.0);
+        poll(&mut gamepads);
+        assert_close(gamepads.get_axis(0, GamepadAxis::AxisLeftTrigger), 0.375);
+        assert_close(gamepads.get_axis(0, GamepadAxis::AxisRightTrigger), 1.0);
+    }
+
+    #[test]
+    fn dead_zone_is_clamped() {
+        let mut gamepads = mock_gamepads();
+
+        gamepads.set_dead_zone(-1.0);
+        assert_eq!(gamepads.get_dead_zone(), 0.0);
+
+        gamepads.set_dead_zone(5.0);
+        assert_eq!(gamepads.get_dead_zone(), 0.99);
+    }
+}
@@ -0,0 +1,323 @@
+use std::fmt::Display;
+use std::path::Path;
+
+use glfw::{GamepadAxis, GamepadButton, Key, MouseButton};
+
+use crate::game_engine::err::EngineError;
+
+/// Every key that can be bound, for looking keys up by name. `Key::Unknown` is left out on purpose.
+pub(super) const KEYS: [Key; 120] = [
+    Key::Space, Key::Apostrophe, Key::Comma, Key::Minus, Key::Period, Key::Slash,
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::Semicolon, Key::Equal,
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::LeftBracket, Key::Backslash, Key::RightBracket, Key::GraveAccent, Key::World1, Key::World2,
+    Key::Escape, Key::Enter, Key::Tab, Key::Backspace, Key::Insert, Key::Delete,
+    Key::Right, Key::Left, Key::Down, Key::Up, Key::PageUp, Key::PageDown, Key::Home, Key::End,
+    Key::CapsLock, Key::ScrollLock, Key::NumLock, Key::PrintScreen, Key::Pause,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10, Key::F11, Key::F12, Key::F13,
+    Key::F14, Key::F15, Key::F16, Key::F17, Key::F18, Key::F19, Key::F20, Key::F21, Key::F22, Key::F23, Key::F24, Key::F25,
+    Key::Kp0, Key::Kp1, Key::Kp2, Key::Kp3, Key::Kp4, Key::Kp5, Key::Kp6, Key::Kp7, Key::Kp8, Key::Kp9,
+    Key::KpDecimal, Key::KpDivide, Key::KpMultiply, Key::KpSubtract, Key::KpAdd, Key::KpEnter, Key::KpEqual,
+    Key::LeftShift, Key::LeftControl, Key::LeftAlt, Key::LeftSuper, Key::RightShift, Key::RightControl, Key::RightAlt, Key::RightSuper,
+    Key::Menu
+];
+
+/// What every engine starts with, in the same format as `input.cfg`.
+const DEFAULT_CONFIG: &str = "\
+action jump key:Space
+action jump pad:A
+axis move_x key:A key:D
+axis move_x key:Left key:Right
+axis move_x pad:DpadLeft pad:DpadRight
+axis move_x pad_axis:LeftX- pad_axis:LeftX+
+axis move_y key:S key:W
+axis move_y key:Down key:Up
+";
+
+/// A physical input an action can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(Key),
+    MouseButton(MouseButton),
+    /// Held on any connected gamepad.
+    GamepadButton(GamepadButton),
+    /// One direction of a stick or trigger on any connected gamepad. `true` for the positive direction.
+    GamepadAxis(GamepadAxis, bool)
+}
+
+impl Binding {
+    /// Parses the config file form, e.g. `key:Space`, `mouse:Left`, `pad:A` or `pad_axis:LeftX-`. Names are the glfw
+    /// variant names, without the `Button` and `Axis` prefixes for gamepads. Mouse buttons past the middle one are
+    /// `mouse:Button4` through `mouse:Button8`.
+    pub fn parse(text: &str) -> Result<Binding, EngineError> {
+        let binding = match text.split_once(':') {
+            Some(("key", name)) => KEYS.iter().find(|k| format!("{:?}", k) == name).map(|k| Binding::Key(*k)),
+            Some(("mouse", name)) => return Binding::parse_mouse(name),
+            Some(("pad", name)) => (0..15).filter_map(GamepadButton::from_i32).find(|b| format!("{:?}", b) == format!("Button{}", name)).map(Binding::GamepadButton),
+            Some(("pad_axis", name)) => {
+                let positive = match name.chars().last() {
+                    Some('+') => Some(true),
+                    Some('-') => Some(false),
+                    _ => None
+                };
+                let name = &name[..name.len().saturating_sub(1)];
+
+                positive.and_then(|positive| {
+                    (0..6).filter_map(GamepadAxis::from_i32).find(|a| format!("{:?}", a) == format!("Axis{}", name)).map(|a| Binding::GamepadAxis(a, positive))
+                })
+            },
+            _ => None
+        };
+
+        match binding {
+            Some(binding) => Ok(binding),
+            None => Err(format!("unknown binding \"{}\"", text).into())
+        }
+    }
+
+    fn parse_mouse(name: &str) -> Result<Binding, EngineError> {
+        let button: Option<u32> = match name {
+            "Left" => Some(1),
+            "Right" => Some(2),
+            "Middle" => Some(3),
+            _ => name.strip_prefix("Button").and_then(|n| n.parse().ok())
+        };
+
+        match button {
+            None => Err(format!("unknown mouse button \"{}\"", name).into()),
+            Some(button) if !(1..=8).contains(&button) => Err(format!("mouse button {} is out of range, buttons go from 1 to 8", button).into()),
+            Some(button) => Ok(Binding::MouseButton(MouseButton::from_i32(button as i32 - 1).unwrap()))
+        }
+    }
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "key:{:?}", key),
+            Binding::MouseButton(MouseButton::Button1) => write!(f, "mouse:Left"),
+            Binding::MouseButton(MouseButton::Button2) => write!(f, "mouse:Right"),
+            Binding::MouseButton(MouseButton::Button3) => write!(f, "mouse:Middle"),
+            Binding::MouseButton(button) => write!(f, "mouse:Button{}", *button as i32 + 1),
+            Binding::GamepadButton(button) => write!(f, "pad:{}", &format!("{:?}", button)["Button".len()..]),
+            Binding::GamepadAxis(axis, positive) => write!(f, "pad_axis:{}{}", &format!("{:?}", axis)["Axis".len()..], if *positive { '+' } else { '-' })
+        }
+    }
+}
+
+/// Named actions and axes, and the inputs bound to them. Components ask `Engine` about actions instead of keys, so
+/// controls can be rebound without touching code.
+///
+/// The config file has one binding per line:
+/// ```text
+/// # action <name> <binding>
+/// action jump key:Space
+/// action jump mouse:Left
+/// action jump pad:A
+/// # axis <name> <negative binding> <positive binding>
+/// axis move_x key:A key:D
+/// axis move_x pad_axis:LeftX- pad_axis:LeftX+
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct InputMap {
+    actions: Vec<(String, Vec<Binding>)>,
+    axes: Vec<(String, Vec<(Binding, Binding)>)>
+}
+
+impl InputMap {
+    pub fn new() -> InputMap {
+        InputMap::default()
+    }
+
+    /// "move_x" and "move_y" on WASD, the arrow keys and the left stick and d-pad, and "jump" on space and A. The map
+    /// engines start with, so `WASDy` works without a config file.
+    pub fn default_bindings() -> InputMap {
+        InputMap::parse(DEFAULT_CONFIG).unwrap()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<InputMap, EngineError> {
+        let text = std::fs::read_to_string(path.as_ref());
+        if text.is_err() {
+            return Err(format!("Failed to read {}: {}", path.as_ref().display(), text.err().unwrap()).into());
+        }
+
+        InputMap::parse(&text.unwrap())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), EngineError> {
+        let result = std::fs::write(path.as_ref(), self.to_config());
+        if result.is_err() {
+            return Err(format!("Failed to write {}: {}", path.as_ref().display(), result.err().unwrap()).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn parse(config: &str) -> Result<InputMap, EngineError> {
+        let mut map = InputMap::new();
+
+        for (i, line) in config.lines().enumerate() {
+            let line_number = i + 1;
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let mut bindings = Vec::new();
+
+            for text in parts.iter().skip(2) {
+                match Binding::parse(text) {
+                    Ok(binding) => bindings.push(binding),
+                    Err(e) => return Err(format!("Input map line {}: {}", line_number, e.get_error_message()).into())
+                }
+            }
+
+            match (parts[0], bindings.as_slice()) {
+                ("action", [binding]) => map.bind_action(parts[1], *binding),
+                ("axis", [negative, positive]) => map.bind_axis(parts[1], *negative, *positive),
+                _ => return Err(format!("Input map line {}: expected \"action <name> <binding>\" or \"axis <name> <negative> <positive>\"", line_number).into())
+            }
+        }
+
+        Ok(map)
+    }
+
+    /// The map in the format `parse` reads.
+    pub fn to_config(&self) -> String {
+        let mut out = String::new();
+
+        for (name, bindings) in &self.actions {
+            for binding in bindings {
+                out += &format!("action {} {}\n", name, binding);
+            }
+        }
+
+        for (name, pairs) in &self.axes {
+            for (negative, positive) in pairs {
+                out += &format!("axis {} {} {}\n", name, negative, positive);
+            }
+        }
+
+        out
+    }
+
+    /// Adds `binding` to `action`, creating the action if needed.
+    pub fn bind_action(&mut self, action: &str, binding: Binding) {
+        match self.actions.iter_mut().find(|(name, _)| name == action) {
+            Some((_, bindings)) => {
+                if !bindings.contains(&binding) {
+                    bindings.push(binding);
+                }
+            },
+            None => self.actions.push((action.to_owned(), vec![binding]))
+        }
+    }
+
+    /// Adds a pair of inputs to `axis` that push it towards -1 and 1, creating the axis if needed.
+    pub fn bind_axis(&mut self, axis: &str, negative: Binding, positive: Binding) {
+        match self.axes.iter_mut().find(|(name, _)| name == axis) {
+            Some((_, pairs)) => {
+                if !pairs.contains(&(negative, positive)) {
+                    pairs.push((negative, positive));
+                }
+            },
+            None => self.axes.push((axis.to_owned(), vec![(negative, positive)]))
+        }
+    }
+
+    /// Replaces every binding of `action`. An empty list keeps the action around with nothing bound to it.
+    pub fn rebind_action(&mut self, action: &str, bindings: Vec<Binding>) {
+        self.actions.retain(|(name, _)| name != action);
+        self.actions.push((action.to_owned(), bindings));
+    }
+
+    pub fn rebind_axis(&mut self, axis: &str, pairs: Vec<(Binding, Binding)>) {
+        self.axes.retain(|(name, _)| name != axis);
+        self.axes.push((axis.to_owned(), pairs));
+    }
+
+    pub fn get_action_bindings(&self, action: &str) -> Option<&[Binding]> {
+        self.actions.iter().find(|(name, _)| name == action).map(|(_, b)| b.as_slice())
+    }
+
+    pub fn get_axis_bindings(&self, axis: &str) -> Option<&[(Binding, Binding)]> {
+        self.axes.iter().find(|(name, _)| name == axis).map(|(_, b)| b.as_slice())
+    }
+
+    /// Whether any input bound to `action` is at least halfway down, according to `value`. `None` if there is no such action.
+    pub fn is_action_down<F: Fn(Binding) -> f32>(&self, action: &str, value: F) -> Option<bool> {
+        self.get_action_bindings(action).map(|bindings| bindings.iter().any(|b| value(*b) >= 0.5))
+    }
+
+    /// Value of `axis` from -1 to 1, where `value` says how far down each input is from 0 to 1. `None` if there is no such axis.
+    pub fn get_axis_value<F: Fn(Binding) -> f32>(&self, axis: &str, value: F) -> Option<f32> {
+        self.get_axis_bindings(axis).map(|pairs| {
+            let sum: f32 = pairs.iter().map(|(negative, positive)| value(*positive) - value(*negative)).sum();
+
+            sum.clamp(-1.0, 1.0)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_bindings_cover_wasdy() {
+        let map = InputMap::default_bindings();
+
+        assert!(map.get_action_bindings("jump").unwrap().contains(&Binding::Key(Key::Space)));
+        assert!(map.get_axis_bindings("move_x").unwrap().contains(&(Binding::Key(Key::A), Binding::Key(Key::D))));
+        assert!(map.get_axis_bindings("move_y").unwrap().contains(&(Binding::Key(Key::S), Binding::Key(Key::W))));
+    }
+
+    #[test]
+    fn missing_names_are_none() {
+        let map = InputMap::new();
+
+        assert_eq!(map.is_action_down("jump", |_| 1.0), None);
+        assert_eq!(map.get_axis_value("move_x", |_| 1.0), None);
+    }
+
+    #[test]
+    fn config_round_trip() {
+        let map = InputMap::default_bindings();
+        let again = InputMap::parse(&map.to_config()).unwrap();
+
+        assert_eq!(again.to_config(), map.to_config());
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let err = InputMap::parse("action jump key:Space\naction jump key:Nope").err().unwrap();
+        assert!(err.get_error_message().contains("line 2"), "{}", err.get_error_message());
+
+        assert!(InputMap::parse("axis move_x key:A").is_err());
+    }
+
+    #[test]
+    fn mouse_buttons() {
+        assert_eq!(Binding::parse("mouse:Left").unwrap(), Binding::MouseButton(MouseButton::Button1));
+        assert_eq!(Binding::parse("mouse:Middle").unwrap(), Binding::MouseButton(MouseButton::Button3));
+        assert_eq!(Binding::parse("mouse:Button1").unwrap(), Binding::MouseButton(MouseButton::Button1));
+        assert_eq!(Binding::parse("mouse:Button8").unwrap(), Binding::MouseButton(MouseButton::Button8));
+        assert_eq!(Binding::parse("mouse:Button5").unwrap().to_string(), "mouse:Button5");
+
+        for text in ["mouse:Button0", "mouse:Button9", "mouse:Button4294967295"] {
+            let err = Binding::parse(text).err().unwrap();
+            assert!(err.get_error_message().contains("out of range"), "{}: {}", text, err.get_error_message());
+        }
+
+        for text in ["mouse:Button-1", "mouse:Button", "mouse:Back", "mouse:Button99999999999"] {
+            assert!(Binding::parse(text).is_err(), "{}", text);
+        }
+
+        let err = InputMap::parse("action fire mouse:Button0").err().unwrap();
+        assert!(err.get_error_message().starts_with("Input map line 1: mouse button 0"), "{}", err.get_error_message());
+    }
+}
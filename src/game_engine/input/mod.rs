@@ -0,0 +1,7 @@
+mod map;
+mod state;
+pub mod gamepad;
+pub mod replay;
+
+pub use map::{InputMap, Binding};
+pub use state::InputState;
@@ -0,0 +1,538 @@
+use std::path::Path;
+
+use glfw::{Action, Key, Modifiers, MouseButton, WindowEvent};
+
+use crate::game_engine::err::EngineError;
+
+use super::gamepad::Gamepad;
+use super::map::KEYS;
+
+const MAGIC: &[u8; 4] = b"WOBR";
+const VERSION: u8 = 1;
+
+/// Everything that came in during one frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayFrame {
+    /// Seconds of real time the frame advanced the simulation by.
+    pub delta_time: f32,
+    /// Number of fixed ticks the engine had run before the frame.
+    pub fixed_tick: u64,
+    /// Window events, with seconds since the recording started. Times are stored at `f32` precision.
+    pub events: Vec<(f64, WindowEvent)>,
+    /// Every connected gamepad, if anything about them changed since the previous frame.
+    pub gamepads: Option<Vec<(usize, Gamepad)>>
+}
+
+/// Input recorded by `Engine::start_recording`, which `Engine::play_replay` feeds back in place of the window's.
+///
+/// Only input is stored, so a replay only reproduces a run if the game does the same thing given the same input. The
+/// file is a small header followed by one record per frame. Only key, mouse button, cursor, scroll and character events
+/// are kept.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Replay {
+    pub fixed_tick_duration: f32,
+    pub frames: Vec<ReplayFrame>
+}
+
+impl Replay {
+    pub fn new(fixed_tick_duration: f32) -> Replay {
+        Replay { fixed_tick_duration, frames: Vec::new() }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, EngineError> {
+        let bytes = std::fs::read(path.as_ref());
+        if bytes.is_err() {
+            return Err(format!("Failed to read {}: {}", path.as_ref().display(), bytes.err().unwrap()).into());
+        }
+
+        Replay::from_bytes(&bytes.unwrap())
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), EngineError> {
+        let result = std::fs::write(path.as_ref(), self.to_bytes());
+        if result.is_err() {
+            return Err(format!("Failed to write {}: {}", path.as_ref().display(), result.err().unwrap()).into());
+        }
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut w = Writer(Vec::new());
+
+        w.0.extend_from_slice(MAGIC);
+        w.u8(VERSION);
+        w.f32(self.fixed_tick_duration);
+        w.varint(self.frames.len() as u64);
+
+        let mut last_tick = 0;
+        for frame in &self.frames {
+            w.f32(frame.delta_time);
+            // Ticks only ever go up by a few per frame, so store the difference
+            w.varint(frame.fixed_tick - last_tick);
+            last_tick = frame.fixed_tick;
+
+            let events: Vec<&(f64, WindowEvent)> = frame.events.iter().filter(|(_, e)| is_recorded(e)).collect();
+            w.varint(events.len() as u64);
+            for (time, event) in events {
+                w.f32(*time as f32);
+                w.event(event);
+            }
+
+            match &frame.gamepads {
+                Some(pads) => {
+                    w.u8(1);
+                    w.varint(pads.len() as u64);
+                    for (slot, pad) in pads {
+                        w.gamepad(*slot, pad);
+                    }
+                },
+                None => w.u8(0)
+            }
+        }
+
+        w.0
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Replay, EngineError> {
+        let mut r = Reader { bytes, pos: 0 };
+
+        if r.take(4) != Some(MAGIC.as_slice()) {
+            return Err("Not a replay file.".into());
+        }
+
+        match r.u8() {
+            Some(VERSION) => {},
+            Some(v) => return Err(format!("Unsupported replay version {}.", v).into()),
+            None => return Err("Replay file is truncated.".into())
+        }
+
+        r.replay().ok_or_else(|| format!("Replay file is corrupt at byte {}.", r.pos).into())
+    }
+}
+
+fn is_recorded(event: &WindowEvent) -> bool {
+    matches!(event, WindowEvent::Key(..) | WindowEvent::MouseButton(..) | WindowEvent::CursorPos(..) | WindowEvent::Scroll(..) | WindowEvent::Char(..))
+}
+
+fn action_code(action: Action) -> u8 {
+    match action {
+        Action::Release => 0,
+        Action::Press => 1,
+        Action::Repeat => 2
+    }
+}
+
+fn action_from_code(code: u8) -> Option<Action> {
+    match code {
+        0 => Some(Action::Release),
+        1 => Some(Action::Press),
+        2 => Some(Action::Repeat),
+        _ => None
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.0.push(v);
+    }
+
+    fn f32(&mut self, v: f32) {
+        self.0.extend_from_slice(&v.to_le_bytes());
+    }
+
+    /// 7 bits per byte, high bit set on all but the last
+    fn varint(&mut self, mut v: u64) {
+        while v >= 0x80 {
+            self.0.push((v as u8 & 0x7f) | 0x80);
+            v >>= 7;
+        }
+
+        self.0.push(v as u8);
+    }
+
+    /// Zigzag encoded so small negative numbers stay small
+    fn signed(&mut self, v: i32) {
+        self.varint(((v << 1) ^ (v >> 31)) as u32 as u64);
+    }
+
+    fn event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::Key(key, scancode, action, mods) => {
+                self.u8(0);
+                self.signed(*key as i32);
+                self.signed(*scancode);
+                self.u8(action_code(*action));
+                self.u8(mods.bits() as u8);
+            },
+            WindowEvent::MouseButton(button, action, mods) => {
+                self.u8(1);
+                self.u8(*button as u8);
+                self.u8(action_code(*action));
+                self.u8(mods.bits() as u8);
+            },
+            WindowEvent::CursorPos(x, y) => {
+                self.u8(2);
+                self.f32(*x as f32);
+                self.f32(*y as f32);
+            },
+            WindowEvent::Scroll(x, y) => {
+                self.u8(3);
+                self.f32(*x as f32);
+                self.f32(*y as f32);
+            },
+            WindowEvent::Char(c) => {
+                self.u8(4);
+                self.varint(*c as u64);
+            },
+            _ => unreachable!()
+        }
+    }
+
+    fn gamepad(&mut self, slot: usize, pad: &Gamepad) {
+        self.u8(slot as u8);
+        self.varint(pad.name.len() as u64);
+        self.0.extend_from_slice(pad.name.as_bytes());
+
+        let buttons = pad.buttons.iter().enumerate().fold(0u16, |bits, (i, b)| bits | ((*b as u16) << i));
+        self.0.extend_from_slice(&buttons.to_le_bytes());
+
+        for axis in pad.axes {
+            self.f32(axis);
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let slice = self.bytes.get(self.pos..self.pos + n)?;
+        self.pos += n;
+
+        Some(slice)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn f32(&mut self) -> Option<f32> {
+        self.take(4).map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> Option<u64> {
+        let mut v = 0;
+
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            v |= ((byte & 0x7f) as u64) << shift;
+
+            if byte & 0x80 == 0 {
+                return Some(v);
+            }
+        }
+
+        None
+    }
+
+    fn signed(&mut self) -> Option<i32> {
+        let v = self.varint()? as u32;
+
+        Some((v >> 1) as i32 ^ -((v & 1) as i32))
+    }
+
+    fn event(&mut self) -> Option<WindowEvent> {
+        match self.u8()? {
+            0 => {
+                let code = self.signed()?;
+                let key = KEYS.iter().copied().find(|k| *k as i32 == code).unwrap_or(Key::Unknown);
+                let scancode = self.signed()?;
+                let action = action_from_code(self.u8()?)?;
+                let mods = Modifiers::from_bits_truncate(self.u8()? as i32);
+
+                Some(WindowEvent::Key(key, scancode, action, mods))
+            },
+            1 => {
+                let button = MouseButton::from_i32(self.u8()? as i32)?;
+                let action = action_from_code(self.u8()?)?;
+                let mods = Modifiers::from_bits_truncate(self.u8()? as i32);
+
+                Some(WindowEvent::MouseButton(button, action, mods))
+            },
+            2 => Some(WindowEvent::CursorPos(self.f32()? as f64, self.f32()? as f64)),
+            3 => Some(WindowEvent::Scroll(self.f32()? as f64, self.f32()? as f64)),
+            4 => Some(WindowEvent::Char(char::from_u32(self.varint()? as u32)?)),
+            _ => None
+        }
+    }
+
+    fn gamepad(&mut self) -> Option<(usize, Gamepad)> {
+        let slot = self.u8()? as usize;
+        let len = self.varint()? as usize;
+        let name = String::from_utf8(self.take(len)?.to_vec()).ok()?;
+        let mut pad = Gamepad::new(&name);
+
+        let buttons = u16::from_le_bytes(self.take(2)?.try_into().unwrap());
+        for (i, pressed) in pad.buttons.iter_mut().enumerate() {
+            *pressed = buttons & (1 << i) != 0;
+        }
+
+        for axis in pad.axes.iter_mut() {
+            *axis = self.f32()?;
+        }
+
+        Some((slot, pad))
+    }
+
+    fn replay(&mut self) -> Option<Replay> {
+        let mut replay = Replay::new(self.f32()?);
+        let frame_count = self.varint()?;
+
+        let mut tick = 0;
+        for _ in 0..frame_count {
+            let delta_time = self.f32()?;
+            tick += self.varint()?;
+
+            let mut events = Vec::new();
+            for _ in 0..self.varint()? {
+                let time = self.f32()? as f64;
+                events.push((time, self.event()?));
+            }
+
+            let gamepads = match self.u8()? {
+                0 => None,
+                1 => {
+                    let mut pads = Vec::new();
+                    for _ in 0..self.varint()? {
+                        pads.push(self.gamepad()?);
+                    }
+
+                    Some(pads)
+                },
+                _ => return None
+            };
+
+            replay.frames.push(ReplayFrame { delta_time, fixed_tick: tick, events, gamepads });
+        }
+
+        if self.pos != self.bytes.len() {
+            return None;
+        }
+
+        Some(replay)
+    }
+}
+
+/// Builds a `Replay` frame by frame.
+pub(in crate::game_engine) struct ReplayRecorder {
+    replay: Replay,
+    /// Recording time minus the window's event time, found from the first event
+    time_offset: Option<f64>,
+    time: f64,
+    last_pads: Vec<(usize, Gamepad)>
+}
+
+impl ReplayRecorder {
+    pub fn new(fixed_tick_duration: f32) -> ReplayRecorder {
+        ReplayRecorder { replay: Replay::new(fixed_tick_duration), time_offset: None, time: 0.0, last_pads: Vec::new() }
+    }
+
+    pub fn record(&mut self, delta_time: f32, fixed_tick: u64, events: &[(f64, WindowEvent)], pads: &[(usize, Gamepad)]) {
+        self.time += delta_time as f64;
+
+        let time = self.time;
+        let offset = *self.time_offset.get_or_insert_with(|| events.first().map(|(t, _)| time - t).unwrap_or(0.0));
+        let events = events.iter().filter(|(_, e)| is_recorded(e)).map(|(t, e)| (f64::max(0.0, t + offset) as f32 as f64, e.clone())).collect();
+
+        // The first frame always stores the gamepads, so playback doesn't start from whatever was connected before
+        let gamepads = if self.replay.frames.is_empty() || pads != self.last_pads.as_slice() {
+            self.last_pads = pads.to_vec();
+            Some(self.last_pads.clone())
+        } else {
+            None
+        };
+
+        self.replay.frames.push(ReplayFrame { delta_time, fixed_tick, events, gamepads });
+    }
+
+    pub fn finish(self) -> Replay {
+        self.replay
+    }
+}
+
+/// Hands out the frames of a `Replay` in order and notices when the game stops matching it.
+pub(in crate::game_engine) struct ReplayPlayer {
+    replay: Replay,
+    next: usize,
+    pads: Vec<(usize, Gamepad)>,
+    desync: Option<usize>
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> ReplayPlayer {
+        ReplayPlayer { replay, next: 0, pads: Vec::new(), desync: None }
+    }
+
+    pub fn get_fixed_tick_duration(&self) -> f32 {
+        self.replay.fixed_tick_duration
+    }
+
+    /// The next frame, with the gamepads filled in even if they didn't change. `fixed_tick` is the number of fixed
+    /// ticks the engine has run, to check it against the recording.
+    pub fn next_frame(&mut self, fixed_tick: u64) -> Option<ReplayFrame> {
+        let mut frame = self.replay.frames.get(self.next)?.clone();
+
+        if frame.fixed_tick != fixed_tick && self.desync.is_none() {
+            self.desync = Some(self.next);
+        }
+
+        match &frame.gamepads {
+            Some(pads) => self.pads = pads.clone(),
+            None => frame.gamepads = Some(self.pads.clone())
+        }
+
+        self.next += 1;
+
+        Some(frame)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.replay.frames.len()
+    }
+
+    pub fn get_desync(&self) -> Option<usize> {
+        self.desync
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glfw::{Action, Key, Modifiers, MouseButton, WindowEvent};
+
+    use super::*;
+
+    fn sample() -> Replay {
+        let mut pad = Gamepad::new("Pad");
+        pad.buttons[0] = true;
+        pad.buttons[14] = true;
+        pad.axes[1] = -0.5;
+
+        Replay {
+            fixed_tick_duration: 1.0 / 60.0,
+            frames: vec![
+                ReplayFrame {
+                    delta_time: 0.016,
+                    fixed_tick: 0,
+                    events: vec![
+                        (0.25, WindowEvent::Key(Key::Space, 57, Action::Press, Modifiers::Shift)),
+                        (0.25, WindowEvent::Key(Key::Left, -1, Action::Repeat, Modifiers::empty())),
+                        (0.5, WindowEvent::MouseButton(MouseButton::Button2, Action::Release, Modifiers::Control)),
+                        (0.5, WindowEvent::CursorPos(320.5, -12.0)),
+                        (0.75, WindowEvent::Scroll(0.0, -1.5)),
+                        (0.75, WindowEvent::Char('é'))
+                    ],
+                    gamepads: Some(vec![(3, pad)])
+                },
+                ReplayFrame { delta_time: 0.5, fixed_tick: 1, events: Vec::new(), gamepads: None },
+                // Jumps far enough for the tick difference to take more than one varint byte
+                ReplayFrame { delta_time: 0.0, fixed_tick: 1000, events: Vec::new(), gamepads: Some(Vec::new()) }
+            ]
+        }
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let replay = sample();
+
+        assert_eq!(Replay::from_bytes(&replay.to_bytes()).unwrap(), replay);
+        assert_eq!(Replay::from_bytes(&Replay::new(0.5).to_bytes()).unwrap(), Replay::new(0.5));
+    }
+
+    #[test]
+    fn unrecorded_events_are_dropped() {
+        let mut replay = sample();
+        replay.frames[1].events.push((1.0, WindowEvent::Focus(false)));
+        replay.frames[1].events.push((1.0, WindowEvent::Char('x')));
+
+        let loaded = Replay::from_bytes(&replay.to_bytes()).unwrap();
+
+        assert_eq!(loaded.frames[1].events, vec![(1.0, WindowEvent::Char('x'))]);
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let bytes = sample().to_bytes();
+
+        for len in 0..bytes.len() {
+            assert!(Replay::from_bytes(&bytes[..len]).is_err(), "accepted the first {} of {} bytes", len, bytes.len());
+        }
+
+        let mut extra = bytes.clone();
+        extra.push(0);
+        assert!(Replay::from_bytes(&extra).err().unwrap().get_error_message().contains("corrupt"));
+    }
+
+    #[test]
+    fn bad_magic_and_version_are_rejected() {
+        let mut bytes = sample().to_bytes();
+        bytes[0] = b'X';
+        assert_eq!(Replay::from_bytes(&bytes).err().unwrap().get_error_message(), "Not a replay file.");
+
+        let mut bytes = sample().to_bytes();
+        bytes[4] = VERSION + 1;
+        assert_eq!(Replay::from_bytes(&bytes).err().unwrap().get_error_message(), format!("Unsupported replay version {}.", VERSION + 1));
+
+        assert_eq!(Replay::from_bytes(MAGIC).err().unwrap().get_error_message(), "Replay file is truncated.");
+    }
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join("replay_save_and_load_test.wobr");
+        sample().save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.unwrap(), sample());
+        assert!(Replay::load(std::env::temp_dir().join("missing_replay.wobr")).is_err());
+    }
+
+    #[test]
+    fn recorder_only_stores_gamepad_changes() {
+        let pads = vec![(0, Gamepad::new("Pad"))];
+        let mut recorder = ReplayRecorder::new(0.25);
+
+        // Window event times are moved so the first event lands on the recording's clock
+        recorder.record(0.5, 0, &[(100.0, WindowEvent::Char('a')), (100.25, WindowEvent::Focus(true))], &[]);
+        recorder.record(0.5, 2, &[(100.5, WindowEvent::Char('b'))], &[]);
+        recorder.record(0.5, 4, &[], &pads);
+        recorder.record(0.5, 6, &[], &pads);
+
+        let replay = recorder.finish();
+        assert_eq!(replay.fixed_tick_duration, 0.25);
+        assert_eq!(replay.frames[0].events, vec![(0.5, WindowEvent::Char('a'))]);
+        assert_eq!(replay.frames[1].events, vec![(1.0, WindowEvent::Char('b'))]);
+        assert_eq!(replay.frames.iter().map(|f| f.gamepads.clone()).collect::<Vec<_>>(), vec![Some(Vec::new()), None, Some(pads), None]);
+    }
+
+    #[test]
+    fn player_fills_in_gamepads_and_notices_desync() {
+        let mut player = ReplayPlayer::new(sample());
+
+        assert_eq!(player.next_frame(0).unwrap().gamepads, sample().frames[0].gamepads);
+        assert_eq!(player.next_frame(1).unwrap().gamepads, sample().frames[0].gamepads);
+        assert_eq!(player.get_desync(), None);
+        assert!(!player.is_finished());
+
+        // Frame 2 was recorded after 1000 ticks
+        player.next_frame(3);
+        assert_eq!(player.get_desync(), Some(2));
+        assert!(player.is_finished());
+        assert!(player.next_frame(4).is_none());
+    }
+}
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+
+use super::Binding;
+
+#[derive(Clone, Copy, Default)]
+struct ButtonState {
+    down: bool,
+    /// Unscaled time of the frame the current press was first seen in
+    pressed_at: f32,
+    /// Unscaled time of the last press nobody has consumed yet
+    buffered_at: Option<f32>,
+    // Presses and releases not yet seen by a frame or a fixed tick. Counted separately so a frame that runs no fixed
+    // ticks doesn't use them up before the next fixed tick gets to see them.
+    frame_presses: u32,
+    frame_releases: u32,
+    fixed_presses: u32,
+    fixed_releases: u32,
+    /// Presses plus key repeats
+    frame_repeats: u32,
+    fixed_repeats: u32,
+    // What the current frame and fixed tick see
+    frame_pressed: bool,
+    frame_released: bool,
+    frame_repeated: bool,
+    fixed_pressed: bool,
+    fixed_released: bool,
+    fixed_repeated: bool
+}
+
+/// Held state of every key and button, plus what changed since the last frame. Queries made from `fixed_update` see
+/// what changed since the last fixed tick instead, so a press is seen exactly once by `update` and once by
+/// `fixed_update`, however the two line up.
+#[derive(Default)]
+pub struct InputState {
+    buttons: HashMap<Binding, ButtonState>,
+    time: f32,
+    in_fixed_tick: bool
+}
+
+impl InputState {
+    pub fn new() -> InputState {
+        InputState::default()
+    }
+
+    pub fn press(&mut self, binding: Binding) {
+        let state = self.buttons.entry(binding).or_default();
+
+        if !state.down {
+            state.down = true;
+            state.frame_presses += 1;
+            state.fixed_presses += 1;
+            state.frame_repeats += 1;
+            state.fixed_repeats += 1;
+        }
+    }
+
+    /// The OS repeating a held key.
+    pub fn repeat(&mut self, binding: Binding) {
+        let state = self.buttons.entry(binding).or_default();
+
+        if state.down {
+            state.frame_repeats += 1;
+            state.fixed_repeats += 1;
+        }
+    }
+
+    pub fn release(&mut self, binding: Binding) {
+        let state = self.buttons.entry(binding).or_default();
+
+        if state.down {
+            state.down = false;
+            state.frame_releases += 1;
+            state.fixed_releases += 1;
+        }
+    }
+
+    /// Releases every key, e.g. when the keyboard starts being used for typing.
+    pub fn release_keys(&mut self) {
+        let held: Vec<Binding> = self.buttons.iter().filter(|(b, s)| matches!(b, Binding::Key(_)) && s.down).map(|(b, _)| *b).collect();
+
+        for binding in held {
+            self.release(binding);
+        }
+    }
+
+    /// Presses or releases `binding` if it isn't already in that state. For inputs that are polled instead of sent as events.
+    pub fn set_down(&mut self, binding: Binding, down: bool) {
+        if down {
+            self.press(binding);
+        } else {
+            self.release(binding);
+        }
+    }
+
+    /// Takes the snapshot `update` sees. `time` is the unscaled time of the new frame.
+    pub(in crate::game_engine) fn begin_frame(&mut self, time: f32) {
+        self.time = time;
+
+        for state in self.buttons.values_mut() {
+            state.frame_pressed = state.frame_presses > 0;
+            state.frame_released = state.frame_releases > 0;
+            state.frame_repeated = state.frame_repeats > 0;
+
+            if state.frame_pressed {
+                state.pressed_at = time;
+                state.buffered_at = Some(time);
+            }
+
+            state.frame_presses = 0;
+            state.frame_releases = 0;
+            state.frame_repeats = 0;
+        }
+    }
+
+    /// Takes the snapshot `fixed_update` sees, until `end_fixed_tick`.
+    pub(in crate::game_engine) fn begin_fixed_tick(&mut self) {
+        self.in_fixed_tick = true;
+
+        for state in self.buttons.values_mut() {
+            state.fixed_pressed = state.fixed_presses > 0;
+            state.fixed_released = state.fixed_releases > 0;
+            state.fixed_repeated = state.fixed_repeats > 0;
+            state.fixed_presses = 0;
+            state.fixed_releases = 0;
+            state.fixed_repeats = 0;
+        }
+    }
+
+    pub(in crate::game_engine) fn end_fixed_tick(&mut self) {
+        self.in_fixed_tick = false;
+    }
+
+    pub fn is_down(&self, binding: Binding) -> bool {
+        self.buttons.get(&binding).map(|s| s.down).unwrap_or(false)
+    }
+
+    /// Whether `binding` went down since the last frame, or the last fixed tick inside `fixed_update`. Also true for a
+    /// press that was released again before the frame started.
+    pub fn is_just_pressed(&self, binding: Binding) -> bool {
+        self.buttons.get(&binding).map(|s| if self.in_fixed_tick { s.fixed_pressed } else { s.frame_pressed }).unwrap_or(false)
+    }
+
+    pub fn is_just_released(&self, binding: Binding) -> bool {
+        self.buttons.get(&binding).map(|s| if self.in_fixed_tick { s.fixed_released } else { s.frame_released }).unwrap_or(false)
+    }
+
+    /// Like `is_just_pressed`, but also true whenever the OS repeats the held key. For moving through menus and text.
+    pub fn is_pressed_or_repeated(&self, binding: Binding) -> bool {
+        self.buttons.get(&binding).map(|s| if self.in_fixed_tick { s.fixed_repeated } else { s.frame_repeated }).unwrap_or(false)
+    }
+
+    /// Seconds of unscaled time `binding` has been held for. 0 in the frame it was pressed, and while it is up.
+    pub fn get_held_duration(&self, binding: Binding) -> f32 {
+        match self.buttons.get(&binding) {
+            Some(state) if state.down => self.time - state.pressed_at,
+            _ => 0.0
+        }
+    }
+
+    /// True if any of `bindings` was pressed within the last `window` seconds and that press hasn't been consumed yet.
+    /// Consumes the presses of all of them, so one press only triggers once.
+    pub fn consume_buffered(&mut self, bindings: &[Binding], window: f32) -> bool {
+        let time = self.time;
+        let buffered = bindings.iter().any(|b| {
+            self.buttons.get(b).and_then(|s| s.buffered_at).map(|t| time - t <= window).unwrap_or(false)
+        });
+
+        if buffered {
+            for binding in bindings {
+                if let Some(state) = self.buttons.get_mut(binding) {
+                    state.buffered_at = None;
+                }
+            }
+        }
+
+        buffered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use glfw::{Key, MouseButton};
+
+    use super::*;
+
+    const SPACE: Binding = Binding::Key(Key::Space);
+
+    /// Runs `fixed_ticks` fixed ticks the way `Engine::step` does after `begin_frame`, returning what each one saw.
+    fn fixed_ticks(input: &mut InputState, fixed_ticks: usize) -> Vec<(bool, bool)> {
+        (0..fixed_ticks).map(|_| {
+            input.begin_fixed_tick();
+            let seen = (input.is_just_pressed(SPACE), input.is_just_released(SPACE));
+            input.end_fixed_tick();
+
+            seen
+        }).collect()
+    }
+
+    #[test]
+    fn press_is_seen_once_by_update_and_fixed_update() {
+        let mut input = InputState::new();
+        input.press(SPACE);
+
+        // Three fixed ticks in one frame: only the first sees the press, while update sees it all frame
+        input.begin_frame(0.0);
+        assert_eq!(fixed_ticks(&mut input, 3), vec![(true, false), (false, false), (false, false)]);
+        assert!(input.is_just_pressed(SPACE));
+        assert!(input.is_down(SPACE));
+
+        input.begin_frame(0.1);
+        assert!(!input.is_just_pressed(SPACE));
+        assert_eq!(fixed_ticks(&mut input, 2), vec![(false, false), (false, false)]);
+
+        input.release(SPACE);
+        input.begin_frame(0.2);
+        assert!(input.is_just_released(SPACE));
+        assert!(!input.is_down(SPACE));
+        assert_eq!(fixed_ticks(&mut input, 2), vec![(false, true), (false, false)]);
+    }
+
+    #[test]
+    fn frame_without_fixed_ticks_leaves_the_press_for_the_next_one() {
+        let mut input = InputState::new();
+        input.press(SPACE);
+
+        input.begin_frame(0.0);
+        assert_eq!(fixed_ticks(&mut input, 0), vec![]);
+        assert!(input.is_just_pressed(SPACE));
+
+        input.begin_frame(0.01);
+        assert!(!input.is_just_pressed(SPACE));
+        assert_eq!(fixed_ticks(&mut input, 1), vec![(true, false)]);
+    }
+
+    #[test]
+    fn tap_between_frames_is_not_lost() {
+        let mut input = InputState::new();
+        input.press(SPACE);
+        input.release(SPACE);
+
+        input.begin_frame(0.0);
+        assert!(input.is_just_pressed(SPACE));
+        assert!(input.is_just_released(SPACE));
+        assert!(!input.is_down(SPACE));
+        assert_eq!(fixed_ticks(&mut input, 1), vec![(true, true)]);
+    }
+
+    #[test]
+    fn held_duration_counts_from_the_first_frame() {
+        let mut input = InputState::new();
+        input.begin_frame(0.0);
+        assert_eq!(input.get_held_duration(SPACE), 0.0);
+
+        input.press(SPACE);
+        input.begin_frame(0.5);
+        assert_eq!(input.get_held_duration(SPACE), 0.0);
+
+        input.begin_frame(1.25);
+        assert_eq!(input.get_held_duration(SPACE), 0.75);
+
+        // Pressing again while held doesn't restart it
+        input.press(SPACE);
+        input.begin_frame(2.0);
+        assert_eq!(input.get_held_duration(SPACE), 1.5);
+
+        input.release(SPACE);
+        input.begin_frame(2.5);
+        assert_eq!(input.get_held_duration(SPACE), 0.0);
+    }
+
+    #[test]
+    fn buffered_press_expires() {
+        let mut input = InputState::new();
+        input.press(SPACE);
+        input.begin_frame(1.0);
+        input.release(SPACE);
+
+        input.begin_frame(1.25);
+        assert!(!input.consume_buffered(&[SPACE], 0.125));
+        assert!(input.consume_buffered(&[SPACE], 0.25));
+
+        // Consumed, so it doesn't trigger again
+        assert!(!input.consume_buffered(&[SPACE], 0.25));
+    }
+
+    #[test]
+    fn consuming_a_buffered_action_consumes_all_its_bindings() {
+        let click = Binding::MouseButton(MouseButton::Button1);
+        let mut input = InputState::new();
+        input.press(SPACE);
+        input.press(click);
+        input.begin_frame(0.0);
+
+        assert!(input.consume_buffered(&[SPACE, click], 0.1));
+        assert!(!input.consume_buffered(&[click], 0.1));
+        assert!(!input.consume_buffered(&[Binding::Key(Key::Enter)], 0.1));
+    }
+
+    #[test]
+    fn key_repeat() {
+        let mut input = InputState::new();
+        input.press(SPACE);
+        input.begin_frame(0.0);
+        assert!(input.is_pressed_or_repeated(SPACE));
+
+        input.begin_frame(0.1);
+        assert!(!input.is_pressed_or_repeated(SPACE));
+
+        input.repeat(SPACE);
+        input.begin_frame(0.2);
+        assert!(input.is_pressed_or_repeated(SPACE));
+        assert!(!input.is_just_pressed(SPACE));
+
+        input.begin_fixed_tick();
+        assert!(input.is_pressed_or_repeated(SPACE));
+        input.end_fixed_tick();
+
+        // A repeat for a key that isn't held is ignored
+        input.release(SPACE);
+        input.repeat(SPACE);
+        input.begin_frame(0.3);
+        assert!(!input.is_pressed_or_repeated(SPACE));
+    }
+
+    #[test]
+    fn release_keys_leaves_other_buttons_held() {
+        let click = Binding::MouseButton(MouseButton::Button1);
+        let mut input = InputState::new();
+        input.press(SPACE);
+        input.press(click);
+        input.begin_frame(0.0);
+
+        input.release_keys();
+        input.begin_frame(0.1);
+
+        assert!(!input.is_down(SPACE));
+        assert!(input.is_just_released(SPACE));
+        assert!(input.is_down(click));
+    }
+}
@@ -0,0 +1,439 @@
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+use super::err::EngineError;
+
+/// How deeply arrays and objects may nest. The parser recurses, so without a limit a file of brackets overflows the stack.
+const MAX_DEPTH: usize = 128;
+
+/// A parsed JSON document. Objects keep their keys in file order.
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>)
+}
+
+impl JsonValue {
+    pub fn parse(text: &str) -> Result<JsonValue, EngineError> {
+        let mut parser = Parser { text, chars: text.char_indices().peekable(), depth: 0 };
+
+        let value = parser.parse_value();
+        if value.is_err() {
+            return Err(value.err().unwrap());
+        }
+
+        parser.skip_whitespace();
+        if parser.chars.peek().is_some() {
+            return Err(parser.error("Trailing characters after JSON value"));
+        }
+
+        value
+    }
+
+    /// Looks up `key` if this is an object.
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(n) => Some(*n),
+            _ => None
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(b) => Some(*b),
+            _ => None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[JsonValue]> {
+        match self {
+            JsonValue::Array(a) => Some(a),
+            _ => None
+        }
+    }
+
+    pub fn as_object(&self) -> Option<&[(String, JsonValue)]> {
+        match self {
+            JsonValue::Object(o) => Some(o),
+            _ => None
+        }
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    chars: Peekable<CharIndices<'a>>,
+    /// Arrays and objects currently open
+    depth: usize
+}
+
+impl<'a> Parser<'a> {
+    fn error(&mut self, message: &str) -> EngineError {
+        let offset = self.chars.peek().map(|(i, _)| *i).unwrap_or(self.text.len());
+        let line = self.text[..offset].matches('\n').count() + 1;
+        let column = offset - self.text[..offset].rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+
+        format!("JSON error at line {} column {}: {}", line, column, message).into()
+    }
+
+    fn skip_whitespace(&mut self) {
+        // Only the four that RFC 8259 allows, not every Unicode space
+        while let Some((_, c)) = self.chars.peek() {
+            if !matches!(c, ' ' | '\t' | '\n' | '\r') {
+                break;
+            }
+
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), EngineError> {
+        match self.chars.peek() {
+            Some((_, c)) if *c == expected => {
+                self.chars.next();
+                Ok(())
+            },
+            _ => Err(self.error(&format!("Expected '{}'", expected)))
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, EngineError> {
+        for expected in word.chars() {
+            let result = self.expect(expected);
+            if result.is_err() {
+                return Err(result.err().unwrap());
+            }
+        }
+
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, EngineError> {
+        self.skip_whitespace();
+
+        match self.chars.peek().map(|(_, c)| *c) {
+            Some('{' | '[') if self.depth >= MAX_DEPTH => Err(self.error(&format!("Nested deeper than {} levels", MAX_DEPTH))),
+            Some('{') => self.nested(Parser::parse_object),
+            Some('[') => self.nested(Parser::parse_array),
+            Some('"') => self.parse_string().map(JsonValue::String),
+            Some('t') => self.expect_word("true", JsonValue::Bool(true)),
+            Some('f') => self.expect_word("false", JsonValue::Bool(false)),
+            Some('n') => self.expect_word("null", JsonValue::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+            Some(_) => Err(self.error("Unexpected character")),
+            None => Err(self.error("Unexpected end of input"))
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Parser<'a>) -> Result<JsonValue, EngineError>) -> Result<JsonValue, EngineError> {
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+
+        value
+    }
+
+    fn parse_object(&mut self) -> Result<JsonValue, EngineError> {
+        self.chars.next();
+        let mut fields = Vec::new();
+
+        self.skip_whitespace();
+        if let Some((_, '}')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(JsonValue::Object(fields));
+        }
+
+        loop {
+            self.skip_whitespace();
+            if !matches!(self.chars.peek(), Some((_, '"'))) {
+                return Err(self.error("Expected object key"));
+            }
+
+            let key = self.parse_string();
+            if key.is_err() {
+                return Err(key.err().unwrap());
+            }
+
+            self.skip_whitespace();
+            let result = self.expect(':');
+            if result.is_err() {
+                return Err(result.err().unwrap());
+            }
+
+            let value = self.parse_value();
+            if value.is_err() {
+                return Err(value.err().unwrap());
+            }
+
+            fields.push((key.unwrap(), value.unwrap()));
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(JsonValue::Object(fields)),
+                _ => return Err(self.error("Expected ',' or '}'"))
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonValue, EngineError> {
+        self.chars.next();
+        let mut items = Vec::new();
+
+        self.skip_whitespace();
+        if let Some((_, ']')) = self.chars.peek() {
+            self.chars.next();
+            return Ok(JsonValue::Array(items));
+        }
+
+        loop {
+            let value = self.parse_value();
+            if value.is_err() {
+                return Err(value.err().unwrap());
+            }
+
+            items.push(value.unwrap());
+
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(JsonValue::Array(items)),
+                _ => return Err(self.error("Expected ',' or ']'"))
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, EngineError> {
+        let mut code = 0;
+
+        for _ in 0..4 {
+            match self.chars.next().and_then(|(_, c)| c.to_digit(16)) {
+                Some(d) => code = code * 16 + d,
+                None => return Err(self.error("Invalid \\u escape"))
+            }
+        }
+
+        Ok(code)
+    }
+
+    fn parse_string(&mut self) -> Result<String, EngineError> {
+        self.chars.next();
+        let mut out = String::new();
+
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(out),
+                Some((_, '\\')) => {
+                    let c = match self.chars.next() {
+                        Some((_, '"')) => '"',
+                        Some((_, '\\')) => '\\',
+                        Some((_, '/')) => '/',
+                        Some((_, 'b')) => '\u{8}',
+                        Some((_, 'f')) => '\u{c}',
+                        Some((_, 'n')) => '\n',
+                        Some((_, 'r')) => '\r',
+                        Some((_, 't')) => '\t',
+                        Some((_, 'u')) => {
+                            let code = self.parse_hex4();
+                            if code.is_err() {
+                                return Err(code.err().unwrap());
+                            }
+
+                            let mut code = code.unwrap();
+
+                            // Characters outside the BMP come as a surrogate pair
+                            if (0xD800..0xDC00).contains(&code) {
+                                let result = self.expect('\\').and_then(|_| self.expect('u'));
+                                if result.is_err() {
+                                    return Err(result.err().unwrap());
+                                }
+
+                                let low = self.parse_hex4();
+                                if low.is_err() {
+                                    return Err(low.err().unwrap());
+                                }
+
+                                let low = low.unwrap();
+                                if !(0xDC00..0xE000).contains(&low) {
+                                    return Err(self.error("High surrogate not followed by a low surrogate"));
+                                }
+
+                                code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                            }
+
+                            match char::from_u32(code) {
+                                Some(c) => c,
+                                None => return Err(self.error("Invalid unicode escape"))
+                            }
+                        },
+                        _ => return Err(self.error("Invalid escape sequence"))
+                    };
+
+                    out.push(c);
+                },
+                Some((_, c)) => out.push(c),
+                None => return Err(self.error("Unterminated string"))
+            }
+        }
+    }
+
+    /// Consumes a run of ASCII digits and returns how many there were.
+    fn skip_digits(&mut self) -> usize {
+        let mut count = 0;
+
+        while self.chars.next_if(|(_, c)| c.is_ascii_digit()).is_some() {
+            count += 1;
+        }
+
+        count
+    }
+
+    /// `-? (0 | [1-9][0-9]*) (.[0-9]+)? ([eE][+-]?[0-9]+)?`, as in RFC 8259
+    fn parse_number(&mut self) -> Result<JsonValue, EngineError> {
+        let start = self.chars.peek().map(|(i, _)| *i).unwrap();
+
+        self.chars.next_if(|(_, c)| *c == '-');
+
+        if self.chars.next_if(|(_, c)| *c == '0').is_some() {
+            if self.chars.peek().is_some_and(|(_, c)| c.is_ascii_digit()) {
+                return Err(self.error("Numbers can't have leading zeros"));
+            }
+        } else if self.skip_digits() == 0 {
+            return Err(self.error("Expected a digit"));
+        }
+
+        if self.chars.next_if(|(_, c)| *c == '.').is_some() && self.skip_digits() == 0 {
+            return Err(self.error("Expected a digit after '.'"));
+        }
+
+        if self.chars.next_if(|(_, c)| matches!(c, 'e' | 'E')).is_some() {
+            self.chars.next_if(|(_, c)| matches!(c, '+' | '-'));
+
+            if self.skip_digits() == 0 {
+                return Err(self.error("Expected a digit in the exponent"));
+            }
+        }
+
+        let end = self.chars.peek().map(|(i, _)| *i).unwrap_or(self.text.len());
+
+        match self.text[start..end].parse() {
+            Ok(n) => Ok(JsonValue::Number(n)),
+            Err(_) => Err(self.error(&format!("Invalid number \"{}\"", &self.text[start..end])))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_err(text: &str) -> String {
+        JsonValue::parse(text).err().unwrap().get_error_message().to_owned()
+    }
+
+    #[test]
+    fn values() {
+        let value = JsonValue::parse(r#" { "b": [true, false, null], "a": {"n": -1.5}, "s": "x", "e": [], "o": {} } "#).unwrap();
+
+        // Keys stay in file order
+        let keys: Vec<&str> = value.as_object().unwrap().iter().map(|(k, _)| k.as_str()).collect();
+        assert_eq!(keys, ["b", "a", "s", "e", "o"]);
+
+        assert_eq!(value.get("b").unwrap().as_array().unwrap(), [JsonValue::Bool(true), JsonValue::Bool(false), JsonValue::Null]);
+        assert_eq!(value.get("a").unwrap().get("n").unwrap().as_f64(), Some(-1.5));
+        assert_eq!(value.get("s").unwrap().as_str(), Some("x"));
+        assert_eq!(value.get("e").unwrap().as_array().unwrap().len(), 0);
+        assert_eq!(value.get("o").unwrap().as_object().unwrap().len(), 0);
+        assert_eq!(value.get("missing"), None);
+    }
+
+    #[test]
+    fn numbers() {
+        for (text, n) in [("0", 0.0), ("-0", 0.0), ("42", 42.0), ("-12.25", -12.25), ("1e3", 1000.0), ("2.5E-2", 0.025), ("1e+2", 100.0)] {
+            assert_eq!(JsonValue::parse(text).unwrap(), JsonValue::Number(n), "{}", text);
+        }
+
+        for text in ["-", "1e", "--1", "1.2.3", "+1", ".5", "01", "-01", "00", "-.5", "1.", "1.e3", "1e+", "-a", "0x10", "1_000"] {
+            assert!(JsonValue::parse(text).is_err(), "{} should not parse", text);
+        }
+
+        assert_eq!(parse_err("[01]"), "JSON error at line 1 column 3: Numbers can't have leading zeros");
+        assert_eq!(parse_err("1."), "JSON error at line 1 column 3: Expected a digit after '.'");
+    }
+
+    #[test]
+    fn whitespace() {
+        assert_eq!(JsonValue::parse(" \t\r\n[ 1 ,\t2\r\n]\n").unwrap().as_array().unwrap().len(), 2);
+
+        // Other Unicode spaces are not JSON whitespace
+        for text in ["\u{a0}1", "[1,\u{2003}2]", "1\u{3000}", "\u{b}1", "\u{c}1", "\u{feff}1"] {
+            assert!(JsonValue::parse(text).is_err(), "{:?} should not parse", text);
+        }
+    }
+
+    #[test]
+    fn escapes() {
+        let value = JsonValue::parse(r#""\"\\\/\b\f\n\r\t Aé€ 😀""#).unwrap();
+        assert_eq!(value.as_str(), Some("\"\\/\u{8}\u{c}\n\r\t A\u{e9}\u{20ac} \u{1f600}"));
+
+        // Unescaped non-ASCII passes straight through
+        assert_eq!(JsonValue::parse("\"h\u{e9}llo \u{1f600}\"").unwrap().as_str(), Some("h\u{e9}llo \u{1f600}"));
+    }
+
+    #[test]
+    fn bad_escapes() {
+        assert!(parse_err(r#""\x""#).contains("Invalid escape sequence"));
+        assert!(parse_err(r#""\u12G4""#).contains("Invalid \\u escape"));
+
+        // Surrogates have to come as a high then low pair
+        assert!(parse_err(r#""\ud83d""#).contains("Expected '\\'"));
+        assert!(parse_err(r#""\ud83dA""#).contains("Expected '\\'"));
+        assert!(parse_err(r#""\ud83d\u0041""#).contains("low surrogate"));
+        assert!(parse_err(r#""\ud83d\ud83d""#).contains("low surrogate"));
+        assert!(parse_err(r#""\ude00""#).contains("Invalid unicode escape"));
+    }
+
+    #[test]
+    fn nesting() {
+        let deep = format!("{}1{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+        let mut value = &JsonValue::parse(&deep).unwrap();
+        for _ in 0..MAX_DEPTH {
+            value = &value.as_array().unwrap()[0];
+        }
+        assert_eq!(*value, JsonValue::Number(1.0));
+
+        let too_deep = format!("{}1{}", "[".repeat(MAX_DEPTH + 1), "]".repeat(MAX_DEPTH + 1));
+        assert!(parse_err(&too_deep).contains("Nested deeper"));
+
+        // Would overflow the stack without the limit
+        assert!(parse_err(&"{\"a\":[".repeat(100_000)).contains("Nested deeper"));
+    }
+
+    #[test]
+    fn error_positions() {
+        assert_eq!(parse_err("{\n  \"a\": tru\n}"), "JSON error at line 2 column 11: Expected 'e'");
+        assert_eq!(parse_err("[1, 2"), "JSON error at line 1 column 6: Expected ',' or ']'");
+        assert_eq!(parse_err("{\"a\" 1}"), "JSON error at line 1 column 6: Expected ':'");
+        assert_eq!(parse_err("[1] x"), "JSON error at line 1 column 5: Trailing characters after JSON value");
+        assert_eq!(parse_err("\"abc"), "JSON error at line 1 column 5: Unterminated string");
+        assert_eq!(parse_err(""), "JSON error at line 1 column 1: Unexpected end of input");
+    }
+}
@@ -1,5 +1,7 @@
 use std::ops::{Mul, Add, Sub};
 
+use super::Vector3;
+
 
 /// Represents a 4x4 matrix. Indexed with standard math notation (first index is 1, not 0).
 #[derive(Debug, Clone, Copy)]
@@ -27,6 +29,65 @@ impl Mat4x4 {
                            0.0, 0.0, 0.0, 1.0 ]}
     }
 
+    /// Returns a translation matrix. Object transforms use column vectors, so the offset goes in the fourth column.
+    pub fn translation(v: Vector3) -> Mat4x4 {
+        Mat4x4 { values: [ 1.0, 0.0, 0.0, v.x,
+                           0.0, 1.0, 0.0, v.y,
+                           0.0, 0.0, 1.0, v.z,
+                           0.0, 0.0, 0.0, 1.0 ]}
+    }
+
+    /// Returns a rotation matrix from pitch (x), yaw (y) and roll (z) in radians, applied in the same order as the camera.
+    pub fn rotation(rot: Vector3) -> Mat4x4 {
+        let p_sin = f32::sin(rot.x);
+        let p_cos = f32::cos(rot.x);
+
+        let y_sin = f32::sin(rot.y);
+        let y_cos = f32::cos(rot.y);
+
+        let r_sin = f32::sin(rot.z);
+        let r_cos = f32::cos(rot.z);
+
+        let p_matrix = Mat4x4 { values: [1.0,   0.0,    0.0, 0.0,
+                                         0.0, p_cos, -p_sin, 0.0,
+                                         0.0, p_sin,  p_cos, 0.0,
+                                         0.0,   0.0,    0.0, 1.0]};
+
+        let y_matrix = Mat4x4 { values: [ y_cos, 0.0, y_sin, 0.0,
+                                            0.0, 1.0,   0.0, 0.0,
+                                         -y_sin, 0.0, y_cos, 0.0,
+                                            0.0, 0.0,   0.0, 1.0 ]};
+
+        let r_matrix = Mat4x4 { values: [r_cos, -r_sin, 0.0, 0.0,
+                                         r_sin,  r_cos, 0.0, 0.0,
+                                           0.0,    0.0, 1.0, 0.0,
+                                           0.0,    0.0, 0.0, 1.0 ]};
+
+        p_matrix * y_matrix * r_matrix
+    }
+
+    /// Returns a scale matrix.
+    pub fn scale(v: Vector3) -> Mat4x4 {
+        Mat4x4 { values: [ v.x, 0.0, 0.0, 0.0,
+                           0.0, v.y, 0.0, 0.0,
+                           0.0, 0.0, v.z, 0.0,
+                           0.0, 0.0, 0.0, 1.0 ]}
+    }
+
+    /// Multiplies the point (x, y, z, 1) as a column vector by this matrix.
+    pub fn transform_point(&self, p: Vector3) -> Vector3 {
+        Vector3 { x: self.get(1, 1) * p.x + self.get(1, 2) * p.y + self.get(1, 3) * p.z + self.get(1, 4),
+                  y: self.get(2, 1) * p.x + self.get(2, 2) * p.y + self.get(2, 3) * p.z + self.get(2, 4),
+                  z: self.get(3, 1) * p.x + self.get(3, 2) * p.y + self.get(3, 3) * p.z + self.get(3, 4) }
+    }
+
+    /// Multiplies the direction (x, y, z, 0) as a column vector by this matrix. Translation is ignored.
+    pub fn transform_vector(&self, v: Vector3) -> Vector3 {
+        Vector3 { x: self.get(1, 1) * v.x + self.get(1, 2) * v.y + self.get(1, 3) * v.z,
+                  y: self.get(2, 1) * v.x + self.get(2, 2) * v.y + self.get(2, 3) * v.z,
+                  z: self.get(3, 1) * v.x + self.get(3, 2) * v.y + self.get(3, 3) * v.z }
+    }
+
     /// Get element a<sub>ij</sub>. Indexed with standard math notation (first index is 1, not 0).
     pub fn get(&self, i: usize, j: usize) -> f32 {
         if i < 1 || i > 4 || j < 1 || j > 4 {
@@ -182,8 +243,8 @@ impl Mat4x4 {
 
         det = 1.0 / det;
 
-        for i in 0..16 {
-            inv[i] *= det;
+        for v in inv.iter_mut() {
+            *v *= det;
         }
 
         Some(Mat4x4 { values: inv })
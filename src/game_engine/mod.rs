@@ -6,14 +6,25 @@ mod err;
 mod quadtree;
 mod polygon;
 mod matrix;
+pub mod clock;
+mod color;
+mod json;
+pub mod animation;
+pub mod input;
 
 use std::{cell::RefCell, rc::Rc};
 
 use game_object::*;
-use glfw::{Key, Action};
+use glfw::{Key, Action, GamepadAxis, GamepadButton, Modifiers};
 pub use vectors::*;
 pub use n_array::NArray;
 pub use polygon::Polygon;
+pub use color::Color;
+pub use graphics::window::{WindowConfig, WindowMode, ScalingPolicy};
+use clock::{Clock, GlfwClock, SystemClock};
+use input::{InputMap, Binding, InputState};
+use input::replay::{Replay, ReplayRecorder, ReplayPlayer};
+use input::gamepad::{Gamepad, Gamepads, GlfwGamepadSource, MockGamepadSource};
 
 use graphics::*;
 
@@ -27,10 +38,32 @@ const VERTICES: [TerrainVertex; 3] = [
 
 pub struct Engine {
     running: bool,
+    clock: Box<dyn Clock>,
     fixed_tick_duration: f32,
+    fixed_tick_accumulator: f32,
+    fixed_tick_count: u64,
+    max_fixed_steps: u32,
+    interpolation_alpha: f32,
+    time_scale: f32,
+    paused: bool,
+    queued_steps: u32,
+    game_time: f32,
+    unscaled_time: f32,
     gfx: Graphics,
     root_object: Rc<RefCell<GameObject>>,
-    keys: [bool; 350]
+    input: InputState,
+    /// Seconds a buffered action press stays usable for
+    input_buffer_window: f32,
+    /// Screen coordinates, from the top left of the window
+    cursor_pos: Vector2,
+    scroll_delta: Vector2,
+    text_input_active: bool,
+    text_input: String,
+    text_input_keys: Vec<(Key, Modifiers)>,
+    input_map: InputMap,
+    gamepads: Gamepads,
+    recorder: Option<ReplayRecorder>,
+    replay_player: Option<ReplayPlayer>
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -39,18 +72,44 @@ pub struct Sprite {
     pub x: f32,
     pub y: f32,
     pub w: f32,
-    pub h: f32
+    pub h: f32,
+    /// Rotation in radians, counter-clockwise around the sprite's center.
+    pub rot: f32,
+    pub flip_x: bool,
+    pub flip_y: bool,
+    /// Multiplied with the texture color.
+    pub tint: Color,
+    /// Multiplied with the tint's alpha.
+    pub opacity: f32,
+    /// Which of the loaded atlases `sprite_id` refers to.
+    pub atlas: usize,
+    /// Sorting layer value. Higher layers draw on top. See `Graphics::add_sorting_layer`.
+    pub layer: i32,
+    /// Draw order within the layer. Higher draws on top.
+    pub order_in_layer: i32,
+    /// Lets the sprite use the depth buffer when it is enabled. Pixels under half alpha are cut out instead of blended.
+    pub opaque: bool
+}
+
+impl Default for Sprite {
+    fn default() -> Self {
+        Sprite { sprite_id: 0, x: 0.0, y: 0.0, w: 1.0, h: 1.0, rot: 0.0, flip_x: false, flip_y: false, tint: Color::WHITE, opacity: 1.0, atlas: 0, layer: 0, order_in_layer: 0, opaque: false }
+    }
 }
 
 impl Engine {
     pub fn init_engine() -> Result<Engine, EngineError> {
+        Engine::init_with_config(&WindowConfig::default())
+    }
+
+    pub fn init_with_config(config: &WindowConfig) -> Result<Engine, EngineError> {
         let result = Graphics::init_gl();
         if result.is_err() {
             return Err(result.err().unwrap());
         }
 
-        let mut gfx = {
-            let result = Graphics::create_window();
+        let gfx = {
+            let result = Graphics::create_window(config);
 
             if result.is_err() {
                 return Err(result.err().unwrap())
@@ -58,10 +117,53 @@ impl Engine {
 
             result.unwrap()
         };
-        
+
+        let mut engine = Engine::with_graphics(gfx);
+        engine.set_clock(Box::new(GlfwClock));
+        engine.gamepads.set_source(Box::new(GlfwGamepadSource));
+
+        Ok(engine)
+    }
+
+    /// Creates an engine that renders to memory instead of a window. Doesn't need a display or a GPU.
+    pub fn init_headless() -> Engine {
+        Engine::with_graphics(Graphics::create_headless())
+    }
+
+    pub fn init_with_backend(backend: Box<dyn RenderBackend>) -> Engine {
+        Engine::with_graphics(Graphics::with_backend(backend))
+    }
+
+    fn with_graphics(mut gfx: Graphics) -> Engine {
         gfx.buffer_terrain_verticies(&VERTICES);
 
-        Ok(Engine { running: false, fixed_tick_duration: 1.0 / 60.0, gfx: gfx, root_object: GameObject::create_empty("root".to_owned(), None), keys: [false; 350] })
+        Engine {
+            running: false,
+            clock: Box::new(SystemClock::new()),
+            fixed_tick_duration: 1.0 / 60.0,
+            fixed_tick_accumulator: 0.0,
+            fixed_tick_count: 0,
+            max_fixed_steps: 8,
+            interpolation_alpha: 0.0,
+            time_scale: 1.0,
+            paused: false,
+            queued_steps: 0,
+            game_time: 0.0,
+            unscaled_time: 0.0,
+            gfx,
+            root_object: GameObject::create_empty("root".to_owned(), None),
+            input: InputState::new(),
+            input_buffer_window: 0.1,
+            cursor_pos: Vector2::ZERO,
+            scroll_delta: Vector2::ZERO,
+            text_input_active: false,
+            text_input: String::new(),
+            text_input_keys: Vec::new(),
+            input_map: InputMap::default_bindings(),
+            gamepads: Gamepads::new(Box::new(MockGamepadSource::new())),
+            recorder: None,
+            replay_player: None
+        }
     }
 
     pub fn start_game_loop(&mut self) -> Result<(), EngineError> {
@@ -71,64 +173,205 @@ impl Engine {
 
         self.running = true;
 
-        let mut last_tick = Graphics::get_glfw_time();
-        let mut last_fixed_tick;
+        let mut last_tick = self.clock.get_time();
 
-        let mut fixed_tick_overflow = 0.0;
-
-        last_fixed_tick = last_tick;
-        
-        let mut should_close = false;
-
-        self.init();
         // Loop until the user closes the window
         while self.gfx.window_alive() {
-            // Poll for and process events
-            for (_, event) in self.gfx.get_window_events() {
-                match event {
-                    glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
-                        should_close = true;
-                    },
-                    glfw::WindowEvent::Key(key, _, Action::Press, _)  => {
-                        self.keys[key as usize] = true;
-                    },
-                    glfw::WindowEvent::Key(key, _, Action::Release, _)  => {
-                        self.keys[key as usize] = false;
-                    }
-                    _ => {},
-                }
+            let current_time = self.clock.get_time();
+
+            self.step(current_time - last_tick);
+            last_tick = current_time;
+
+            // Swap front and back buffers
+            self.gfx.swap_window_buffers();
+        }
+
+        self.running = false;
+
+        Ok(())
+    }
+
+    /// Runs a single frame that advances the simulation by exactly `delta_time` seconds of real time, without
+    /// consulting the clock. The time scale and pause state still apply.
+    pub fn step(&mut self, unscaled_delta_time: f32) {
+        self.sync_lifecycle();
+
+        let mut unscaled_delta_time = unscaled_delta_time;
+
+        // Poll for events, or take them and the frame time from the replay being played
+        let (events, pads) = match self.replay_player.as_mut().and_then(|p| p.next_frame(self.fixed_tick_count)) {
+            Some(frame) => {
+                unscaled_delta_time = frame.delta_time;
+
+                (frame.events, frame.gamepads.unwrap_or_default())
+            },
+            None => (self.gfx.get_window_events(), self.gamepads.poll_source())
+        };
+
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(unscaled_delta_time, self.fixed_tick_count, &events, &pads);
+        }
+
+        self.process_events(events, pads);
+
+        if !self.gfx.window_alive() {
+            return;
+        }
+
+        self.unscaled_time += unscaled_delta_time;
+        self.input.begin_frame(self.unscaled_time);
+
+        // Game time that passed this frame, for render
+        let mut frame_delta_time = 0.0;
+
+        if self.paused {
+            // Only tick while paused if someone asked for a single step
+            if self.queued_steps > 0 {
+                self.queued_steps -= 1;
+                self.game_time += self.fixed_tick_duration;
+                frame_delta_time = self.fixed_tick_duration;
+
+                self.fixed_game_tick(self.fixed_tick_duration, unscaled_delta_time);
+                self.game_tick(self.fixed_tick_duration, unscaled_delta_time);
             }
-            
-            if should_close {
-                self.gfx.close_window();
-                break;
+        } else {
+            let delta_time = unscaled_delta_time * self.time_scale;
+            self.game_time += delta_time;
+            frame_delta_time = delta_time;
+
+            // Fixed ticks. Run as many as the elapsed time calls for, but give up after max_fixed_steps so a slow
+            // frame doesn't snowball into an even slower one.
+            self.fixed_tick_accumulator += delta_time;
+
+            let mut steps = 0;
+            while self.fixed_tick_accumulator >= self.fixed_tick_duration && steps < self.max_fixed_steps {
+                self.fixed_game_tick(self.fixed_tick_duration, unscaled_delta_time);
+                self.fixed_tick_accumulator -= self.fixed_tick_duration;
+                steps += 1;
             }
 
+            if self.fixed_tick_accumulator >= self.fixed_tick_duration {
+                self.fixed_tick_accumulator %= self.fixed_tick_duration;
+            }
+
+            self.interpolation_alpha = self.fixed_tick_accumulator / self.fixed_tick_duration;
+
             // Game tick
-            let current_time = Graphics::get_glfw_time();
+            self.game_tick(delta_time, unscaled_delta_time);
+        }
 
-            self.game_tick(current_time - last_tick);
-            last_tick = current_time;
+        self.process_destroyed();
 
-            let fixed_diff = current_time - last_fixed_tick - self.fixed_tick_duration;
+        // Render
+        self.render_tick(frame_delta_time, unscaled_delta_time);
+        self.gfx.render();
+    }
 
-            // Add overflow to adjust for errors in timing
-            if fixed_diff + fixed_tick_overflow >= 0.0 {
-                fixed_tick_overflow = f32::max(0.0, fixed_diff * 2.0);
-                self.fixed_game_tick(current_time - last_fixed_tick);
-                last_fixed_tick = current_time;
-            }
+    /// Starts recording input from the next frame on, replacing any recording in progress.
+    pub fn start_recording(&mut self) {
+        self.recorder = Some(ReplayRecorder::new(self.fixed_tick_duration));
+    }
 
-            // Render
-            self.gfx.render();
+    /// Stops recording and returns what was recorded, if anything was being recorded.
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        self.recorder.take().map(ReplayRecorder::finish)
+    }
 
-            // Swap front and back buffers
-            self.gfx.swap_window_buffers();
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Feeds `replay` in place of the window's input and the clock, one recorded frame per `step`, and goes back to live
+    /// input once it runs out. The engine has to be set up the same way it was when recording started, usually by
+    /// building the same scene in a fresh engine. Headless, run it with `while engine.is_replaying() { engine.step(0.0); }`.
+    pub fn play_replay(&mut self, replay: Replay) {
+        let player = ReplayPlayer::new(replay);
+        self.fixed_tick_duration = player.get_fixed_tick_duration();
+        self.replay_player = Some(player);
+    }
+
+    pub fn stop_replay(&mut self) {
+        self.replay_player = None;
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay_player.as_ref().map(|p| !p.is_finished()).unwrap_or(false)
+    }
+
+    /// Index of the first frame of the current or last replay where the engine had run a different number of fixed ticks
+    /// than when it was recorded. Means the replay started from a different state and probably won't reproduce the run.
+    pub fn get_replay_desync(&self) -> Option<usize> {
+        self.replay_player.as_ref().and_then(ReplayPlayer::get_desync)
+    }
+
+    /// Number of fixed ticks run since the engine was created.
+    pub fn get_fixed_tick_count(&self) -> u64 {
+        self.fixed_tick_count
+    }
+
+    /// Runs a single frame of exactly one fixed tick of real time.
+    pub fn step_fixed(&mut self) {
+        self.step(self.fixed_tick_duration);
+    }
+
+    /// Scales how fast game time passes relative to real time. 1.0 is normal speed, 0.5 is half speed, etc.
+    pub fn set_time_scale(&mut self, time_scale: f32) {
+        self.time_scale = f32::max(0.0, time_scale);
+    }
+
+    pub fn get_time_scale(&self) -> f32 {
+        self.time_scale
+    }
+
+    /// Stops `update` and `fixed_update` from running. Events are still polled and frames are still rendered.
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.queued_steps = 0;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// While paused, runs one fixed tick (followed by one update of the same length) on the next frame.
+    pub fn queue_single_step(&mut self) {
+        if self.paused {
+            self.queued_steps += 1;
         }
+    }
 
-        self.running = false;
+    /// Seconds of game time that have passed, affected by time scale and pausing.
+    pub fn get_game_time(&self) -> f32 {
+        self.game_time
+    }
 
-        Ok(())
+    /// Seconds of real time that have passed in frames the engine has run, ignoring time scale and pausing.
+    pub fn get_unscaled_time(&self) -> f32 {
+        self.unscaled_time
+    }
+
+    /// Caps how many fixed ticks a single frame may run to catch up. Any time beyond that is dropped.
+    pub fn set_max_fixed_steps(&mut self, steps: u32) {
+        self.max_fixed_steps = steps;
+    }
+
+    /// How far the current frame is between the last fixed tick and the next one, from 0.0 to 1.0.
+    /// Use it to blend between the previous and current fixed-step state when rendering.
+    pub fn get_interpolation_alpha(&self) -> f32 {
+        self.interpolation_alpha
+    }
+
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    /// Current time according to the engine's clock.
+    pub fn get_time(&self) -> f32 {
+        self.clock.get_time()
     }
 
     pub fn set_fixed_tick_rate(&mut self, tickrate:  f32) {
@@ -148,58 +391,1135 @@ impl Engine {
     }
 
     pub fn get_key(&self, key: glfw::Key) -> bool {
-        self.keys[key as usize]
+        self.input.is_down(Binding::Key(key))
+    }
+
+    pub fn get_mouse_button(&self, button: glfw::MouseButton) -> bool {
+        self.input.is_down(Binding::MouseButton(button))
     }
 
-    fn init(&mut self) {
-        let comps = self.root_object.borrow().get_all_components();
-        for comp in &comps {
-            comp.borrow_mut().init(self, Rc::clone(&self.root_object));
+    /// Whether `binding` went down since the last frame. Inside `fixed_update`, since the last fixed tick instead.
+    /// Gamepad axes count as down past halfway.
+    pub fn is_just_pressed(&self, binding: Binding) -> bool {
+        self.input.is_just_pressed(binding)
+    }
+
+    /// Whether `binding` went up since the last frame. Inside `fixed_update`, since the last fixed tick instead.
+    pub fn is_just_released(&self, binding: Binding) -> bool {
+        self.input.is_just_released(binding)
+    }
+
+    /// Like `is_just_pressed`, but also true each time the OS repeats a held key.
+    pub fn is_pressed_or_repeated(&self, binding: Binding) -> bool {
+        self.input.is_pressed_or_repeated(binding)
+    }
+
+    /// Seconds of unscaled time `binding` has been held for, 0 if it isn't held.
+    pub fn get_held_duration(&self, binding: Binding) -> f32 {
+        self.input.get_held_duration(binding)
+    }
+
+    /// Cursor position in screen coordinates, from the top left of the window.
+    pub fn get_cursor_pos(&self) -> Vector2 {
+        self.cursor_pos
+    }
+
+    /// Point in the world under the cursor.
+    pub fn get_cursor_world_pos(&self) -> Vector2 {
+        self.gfx.screen_to_world(self.cursor_pos)
+    }
+
+    /// How far the scroll wheel moved this frame. Positive y is scrolling up.
+    pub fn get_scroll_delta(&self) -> Vector2 {
+        self.scroll_delta
+    }
+
+    /// Sends the keyboard to `get_text_input` and `get_text_input_keys` instead of the game, so typing a name doesn't
+    /// also move the player. Held keys are released, and keys pressed while typing don't register as key presses.
+    pub fn start_text_input(&mut self) {
+        if !self.text_input_active {
+            self.text_input_active = true;
+            self.input.release_keys();
         }
+    }
 
-        let stuff = self.root_object.borrow().get_all_children();
-        for obj in stuff {
-            obj.borrow_mut().init(self);
-            let comps = obj.borrow().get_all_components();
-            for comp in &comps {
-                comp.borrow_mut().init(self, Rc::clone(&obj));
+    pub fn stop_text_input(&mut self) {
+        self.text_input_active = false;
+    }
+
+    pub fn is_text_input_active(&self) -> bool {
+        self.text_input_active
+    }
+
+    /// Text typed this frame while text input is active. It comes from the OS after keyboard layouts, dead keys and IME
+    /// composition are applied, so only finished characters show up.
+    pub fn get_text_input(&self) -> &str {
+        &self.text_input
+    }
+
+    /// Keys pressed or repeated this frame while text input is active, for editing keys like Backspace, Enter or Ctrl+V.
+    pub fn get_text_input_keys(&self) -> &[(Key, Modifiers)] {
+        &self.text_input_keys
+    }
+
+    pub fn get_clipboard(&self) -> Option<String> {
+        self.gfx.get_clipboard()
+    }
+
+    pub fn set_clipboard(&mut self, text: &str) {
+        self.gfx.set_clipboard(text);
+    }
+
+    pub fn get_input_map(&self) -> &InputMap {
+        &self.input_map
+    }
+
+    pub fn get_input_map_mut(&mut self) -> &mut InputMap {
+        &mut self.input_map
+    }
+
+    pub fn set_input_map(&mut self, input_map: InputMap) {
+        self.input_map = input_map;
+    }
+
+    /// Gamepad state. Headless engines start with a `MockGamepadSource` with nothing connected.
+    pub fn get_gamepads(&self) -> &Gamepads {
+        &self.gamepads
+    }
+
+    pub fn get_gamepads_mut(&mut self) -> &mut Gamepads {
+        &mut self.gamepads
+    }
+
+    /// How far down `binding` is, from 0 to 1.
+    fn get_binding_value(&self, binding: Binding) -> f32 {
+        match binding {
+            Binding::Key(key) => self.get_key(key) as i32 as f32,
+            Binding::MouseButton(button) => self.get_mouse_button(button) as i32 as f32,
+            Binding::GamepadButton(button) => self.gamepads.any_button(button) as i32 as f32,
+            Binding::GamepadAxis(axis, positive) => {
+                let value = self.gamepads.any_axis(axis);
+
+                if positive { value.max(0.0) } else { (-value).max(0.0) }
             }
         }
     }
 
-    fn game_tick(&mut self, delta_time: f32) {
-        self.root_object.clone().borrow_mut().fixed_update(delta_time, self);
-        let comps = self.root_object.borrow().get_all_components();
-        for comp in &comps {
-            let root_rc = Rc::clone(&self.root_object);
-            comp.borrow_mut().update(TickInfo { delta_time, engine: self }, root_rc);
+    /// Whether any input bound to `action` is held. Panics if the input map has no such action.
+    pub fn get_action(&self, action: &str) -> bool {
+        self.try_get_action(action).unwrap_or_else(|| panic!("Input action \"{}\" does not exist!", action))
+    }
+
+    /// Same as `get_action`, but `None` if the input map has no such action.
+    pub fn try_get_action(&self, action: &str) -> Option<bool> {
+        self.input_map.is_action_down(action, |b| self.get_binding_value(b))
+    }
+
+    fn get_action_bindings(&self, action: &str) -> &[Binding] {
+        self.input_map.get_action_bindings(action).unwrap_or_else(|| panic!("Input action \"{}\" does not exist!", action))
+    }
+
+    /// Whether an input bound to `action` was pressed since the last frame, or the last fixed tick inside `fixed_update`.
+    pub fn is_action_just_pressed(&self, action: &str) -> bool {
+        self.get_action_bindings(action).iter().any(|b| self.input.is_just_pressed(*b))
+    }
+
+    /// Whether `action` stopped being held since the last frame, or the last fixed tick inside `fixed_update`.
+    pub fn is_action_just_released(&self, action: &str) -> bool {
+        let bindings = self.get_action_bindings(action);
+
+        bindings.iter().any(|b| self.input.is_just_released(*b)) && !bindings.iter().any(|b| self.input.is_down(*b))
+    }
+
+    /// Seconds the longest held input bound to `action` has been held for.
+    pub fn get_action_held_duration(&self, action: &str) -> f32 {
+        self.get_action_bindings(action).iter().map(|b| self.input.get_held_duration(*b)).fold(0.0, f32::max)
+    }
+
+    /// How long `consume_buffered_action` remembers a press for. Defaults to 0.1 seconds.
+    pub fn set_input_buffer_window(&mut self, seconds: f32) {
+        self.input_buffer_window = f32::max(0.0, seconds);
+    }
+
+    pub fn get_input_buffer_window(&self) -> f32 {
+        self.input_buffer_window
+    }
+
+    /// True once for each press of `action` made within the input buffer window, so a jump pressed just before landing
+    /// still happens when the character lands. Panics if the input map has no such action.
+    pub fn consume_buffered_action(&mut self, action: &str) -> bool {
+        self.try_consume_buffered_action(action).unwrap_or_else(|| panic!("Input action \"{}\" does not exist!", action))
+    }
+
+    /// Same as `consume_buffered_action`, but `None` if the input map has no such action.
+    pub fn try_consume_buffered_action(&mut self, action: &str) -> Option<bool> {
+        let bindings = self.input_map.get_action_bindings(action)?.to_vec();
+
+        Some(self.input.consume_buffered(&bindings, self.input_buffer_window))
+    }
+
+    /// Value of `axis` from -1 to 1. Panics if the input map has no such axis.
+    pub fn get_axis(&self, axis: &str) -> f32 {
+        self.try_get_axis(axis).unwrap_or_else(|| panic!("Input axis \"{}\" does not exist!", axis))
+    }
+
+    /// Same as `get_axis`, but `None` if the input map has no such axis.
+    pub fn try_get_axis(&self, axis: &str) -> Option<f32> {
+        self.input_map.get_axis_value(axis, |b| self.get_binding_value(b))
+    }
+
+    fn process_events(&mut self, events: Vec<(f64, glfw::WindowEvent)>, pads: Vec<(usize, Gamepad)>) {
+        self.scroll_delta = Vector2::ZERO;
+        self.text_input.clear();
+        self.text_input_keys.clear();
+        self.gamepads.update(pads);
+
+        // Gamepads are polled, so find their presses and releases by comparing with the last frame
+        for button in (0..15).filter_map(GamepadButton::from_i32) {
+            self.input.set_down(Binding::GamepadButton(button), self.gamepads.any_button(button));
         }
 
-        let stuff = self.root_object.borrow().get_all_children();
-        for obj in stuff {
+        for axis in (0..6).filter_map(GamepadAxis::from_i32) {
+            for positive in [false, true] {
+                let binding = Binding::GamepadAxis(axis, positive);
+                self.input.set_down(binding, self.get_binding_value(binding) >= 0.5);
+            }
+        }
+
+        for (_, event) in events {
+            match event {
+                // Releases still go to the game, so keys held down before typing started come back up
+                glfw::WindowEvent::Key(key, _, Action::Press | Action::Repeat, mods) if self.text_input_active => {
+                    self.text_input_keys.push((key, mods));
+                },
+                glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
+                    self.gfx.close_window();
+                },
+                glfw::WindowEvent::Key(key, _, Action::Press, _)  => {
+                    self.input.press(Binding::Key(key));
+                },
+                glfw::WindowEvent::Key(key, _, Action::Release, _)  => {
+                    self.input.release(Binding::Key(key));
+                },
+                glfw::WindowEvent::Key(key, _, Action::Repeat, _)  => {
+                    self.input.repeat(Binding::Key(key));
+                },
+                glfw::WindowEvent::Char(c) if self.text_input_active => {
+                    self.text_input.push(c);
+                },
+                glfw::WindowEvent::MouseButton(button, Action::Press, _) => {
+                    self.input.press(Binding::MouseButton(button));
+                },
+                glfw::WindowEvent::MouseButton(button, Action::Release, _) => {
+                    self.input.release(Binding::MouseButton(button));
+                },
+                glfw::WindowEvent::CursorPos(x, y) => {
+                    self.cursor_pos = Vector2::new(x as f32, y as f32);
+                },
+                glfw::WindowEvent::Scroll(x, y) => {
+                    self.scroll_delta += Vector2::new(x as f32, y as f32);
+                }
+                _ => {},
+            }
+        }
+    }
+
+    /// The root and every object under it, parents before children, with whether each one is active in the hierarchy.
+    fn get_objects(&self) -> Vec<(Rc<RefCell<GameObject>>, bool)> {
+        let root_active = self.root_object.borrow().is_active_self();
+        let mut v = vec![(Rc::clone(&self.root_object), root_active)];
+
+        // BFS, so each parent's state is known before its children
+        let mut i = 0;
+        while i < v.len() {
+            let (obj, active) = v[i].clone();
+
+            for child in obj.borrow().get_children() {
+                let child_active = active && child.borrow().is_active_self();
+                v.push((child, child_active));
+            }
+
+            i += 1;
+        }
+
+        v
+    }
+
+    /// Objects that tick this frame. Skips inactive subtrees without looking inside them.
+    fn get_active_objects(&self) -> Vec<Rc<RefCell<GameObject>>> {
+        let mut v = Vec::new();
+
+        if self.root_object.borrow().is_active_self() {
+            v.push(Rc::clone(&self.root_object));
+        }
+
+        let mut i = 0;
+        while i < v.len() {
+            let children = v[i].borrow().get_children();
+            v.extend(children.into_iter().filter(|c| c.borrow().is_active_self()));
+
+            i += 1;
+        }
+
+        v
+    }
+
+    /// Catches up on lifecycle hooks: `awake` for everything added since the last call, then `on_enable`/`on_disable` for
+    /// components whose enabled state changed, then `start` for newly enabled ones. Runs until nothing is left to do, so
+    /// objects and components created by these hooks or at any point during a tick are ready before their first update.
+    /// Only walks the tree if something was added, enabled, disabled or moved since the last call.
+    fn sync_lifecycle(&mut self) {
+        if !self.root_object.borrow().take_lifecycle_dirty() {
+            return;
+        }
+
+        loop {
+            // Hooks that add or toggle things set the flag again, and the next pass picks those up
+            self.root_object.borrow().take_lifecycle_dirty();
+
+            let stuff = self.get_objects();
+            let mut changed = false;
+
+            for (obj, _) in &stuff {
+                if !obj.borrow().needs_awake() {
+                    continue;
+                }
+
+                changed = true;
+
+                let comps = obj.borrow_mut().take_pending_awake();
+                obj.borrow_mut().init(self);
+
+                for comp in comps {
+                    comp.borrow_mut().awake(self, Rc::clone(obj));
+                }
+            }
+
+            for (obj, active) in &stuff {
+                let changes = obj.borrow_mut().take_enable_changes(*active);
+
+                for (comp, enabled) in changes {
+                    changed = true;
+
+                    if enabled {
+                        comp.borrow_mut().on_enable(self, Rc::clone(obj));
+                    } else {
+                        comp.borrow_mut().on_disable(self, Rc::clone(obj));
+                    }
+                }
+            }
+
+            for (obj, _) in &stuff {
+                let comps = obj.borrow_mut().take_pending_start();
+
+                for comp in comps {
+                    changed = true;
+                    comp.borrow_mut().start(self, Rc::clone(obj));
+                }
+            }
+
+            if !changed {
+                return;
+            }
+        }
+    }
+
+    /// Carries out `GameObject::destroy` and `GameObject::remove_component` calls made during the frame.
+    fn process_destroyed(&mut self) {
+        // Nothing was destroyed or removed this frame, so there is no need to walk the tree
+        if !self.root_object.borrow().take_destroy_pending() {
+            return;
+        }
+
+        let stuff = self.get_objects();
+        for (obj, _) in stuff {
+            // Already gone if a parent was destroyed
+            if obj.borrow().is_destroyed() {
+                continue;
+            }
+
+            if obj.borrow().is_pending_destroy() && !Rc::ptr_eq(&obj, &self.root_object) {
+                self.destroy_object(&obj);
+                continue;
+            }
+
+            let removed = obj.borrow_mut().take_removed_components();
+            self.call_destroy_hooks(&obj, removed);
+        }
+    }
+
+    fn destroy_object(&mut self, obj: &Rc<RefCell<GameObject>>) {
+        let mut subtree = vec![Rc::clone(obj)];
+        subtree.extend(obj.borrow().get_all_children());
+
+        // Every hook runs before anything is torn down, so they can still look at the rest of the subtree
+        for o in &subtree {
+            let entries = o.borrow().get_component_entries();
+            self.call_destroy_hooks(o, entries);
+        }
+
+        for o in &subtree {
+            GameObject::tear_down(o);
+        }
+    }
+
+    /// `on_disable` for components that are enabled, then `on_destroy` for any that woke up.
+    fn call_destroy_hooks(&mut self, obj: &Rc<RefCell<GameObject>>, entries: Vec<ComponentEntry>) {
+        for entry in entries {
+            if entry.reported_enabled {
+                entry.component.borrow_mut().on_disable(self, Rc::clone(obj));
+            }
+
+            if entry.awake {
+                entry.component.borrow_mut().on_destroy(self, Rc::clone(obj));
+            }
+        }
+    }
+
+    fn game_tick(&mut self, delta_time: f32, unscaled_delta_time: f32) {
+        self.sync_lifecycle();
+
+        let stuff = self.get_active_objects();
+        for obj in &stuff {
+            // Could have been deactivated earlier in the tick
+            if !obj.borrow().is_active_in_hierarchy() {
+                continue;
+            }
+
             obj.borrow_mut().update(delta_time, self);
-            let comps = obj.borrow().get_all_components();
+            let comps = obj.borrow().get_tickable_components();
             for comp in comps {
-                comp.borrow_mut().update(TickInfo { delta_time, engine: self }, Rc::clone(&obj));
+                comp.borrow_mut().update(TickInfo { delta_time, unscaled_delta_time, engine: self }, Rc::clone(obj));
             }
         }
-    }
 
-    fn fixed_game_tick(&mut self, delta_time: f32) {
-        self.root_object.clone().borrow_mut().fixed_update(delta_time, self);
-        let comps = self.root_object.borrow().get_all_components();
-        for comp in &comps {
-            let root_rc = Rc::clone(&self.root_object);
-            comp.borrow_mut().fixed_update(TickInfo { delta_time, engine: self }, root_rc);
+        for obj in &stuff {
+            if !obj.borrow().is_active_in_hierarchy() {
+                continue;
+            }
+
+            let comps = obj.borrow().get_tickable_components();
+            for comp in comps {
+                comp.borrow_mut().late_update(TickInfo { delta_time, unscaled_delta_time, engine: self }, Rc::clone(obj));
+            }
         }
+    }
 
-        let stuff = self.root_object.borrow().get_all_children();
+    fn fixed_game_tick(&mut self, delta_time: f32, unscaled_delta_time: f32) {
+        self.input.begin_fixed_tick();
+        self.sync_lifecycle();
+
+        let stuff = self.get_active_objects();
         for obj in stuff {
+            if !obj.borrow().is_active_in_hierarchy() {
+                continue;
+            }
+
             obj.borrow_mut().fixed_update(delta_time, self);
-            let comps = obj.borrow().get_all_components();
+            let comps = obj.borrow().get_tickable_components();
             for comp in comps {
-                comp.borrow_mut().fixed_update(TickInfo { delta_time, engine: self }, Rc::clone(&obj));
+                comp.borrow_mut().fixed_update(TickInfo { delta_time, unscaled_delta_time, engine: self }, Rc::clone(&obj));
             }
         }
+
+        self.input.end_fixed_tick();
+        self.fixed_tick_count += 1;
+    }
+
+    fn render_tick(&mut self, delta_time: f32, unscaled_delta_time: f32) {
+        self.sync_lifecycle();
+
+        let stuff = self.get_active_objects();
+        for obj in stuff {
+            if !obj.borrow().is_active_in_hierarchy() {
+                continue;
+            }
+
+            obj.borrow_mut().render(delta_time, self);
+            let comps = obj.borrow().get_tickable_components();
+            for comp in comps {
+                comp.borrow_mut().render(TickInfo { delta_time, unscaled_delta_time, engine: self }, Rc::clone(&obj));
+            }
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::ManualClock;
+    use components::{Collider, Component, WASDy};
+    use graphics::headless::HeadlessBackend;
+
+    /// Ground with its top at y = -0.5, and a 0.5x1 player with `WASDy` whose center starts at `y`.
+    pub(super) fn spawn_platformer(engine: &Engine, y: f32) -> Rc<RefCell<GameObject>> {
+        let root = engine.get_root_object();
+
+        let ground = GameObject::create_empty("ground".to_owned(), Some(root.clone()));
+        ground.borrow_mut().set_pos(Vector3::new(0.0, -0.75, 0.0));
+        ground.borrow_mut().add_component(Collider::new(Polygon::new((0.0, 0.0).into(), vec![(-1.0, 0.25).into(), (1.0, 0.25).into(), (1.0, -0.25).into(), (-1.0, -0.25).into()])));
+
+        let guy = GameObject::create_empty("guy".to_owned(), Some(root));
+        guy.borrow_mut().set_pos(Vector3::new(0.0, y, 0.0));
+        guy.borrow_mut().add_component(WASDy { speed: 1.0, velocity: 0.0, acc: -5.0 });
+        guy.borrow_mut().add_component(Collider::new(Polygon::new((0.0, 0.0).into(), vec![(-0.25, 0.5).into(), (0.25, 0.5).into(), (0.25, -0.5).into(), (-0.25, -0.5).into()])));
+
+        guy
+    }
+
+    pub(super) fn push_key(engine: &mut Engine, key: Key, action: Action) {
+        let backend = engine.get_gfx_mut().get_backend_mut::<HeadlessBackend>().unwrap();
+        backend.push_event(glfw::WindowEvent::Key(key, 0, action, Modifiers::empty()));
+    }
+
+    #[test]
+    fn wasdy_falls_and_lands_on_ground() {
+        let mut engine = Engine::init_headless();
+        let guy = spawn_platformer(&engine, 1.0);
+
+        for _ in 0..120 {
+            engine.step_fixed();
+        }
+
+        let guy = guy.borrow();
+        assert!(guy.is_grounded());
+        assert!(guy.get_world_pos().y.abs() < 1e-3, "landed at {}", guy.get_world_pos());
+        assert_eq!(engine.get_fixed_tick_count(), 120);
+    }
+
+    #[test]
+    fn wasdy_jump_arc_lands_on_ground() {
+        let mut engine = Engine::init_headless();
+        let guy = spawn_platformer(&engine, 0.0);
+
+        for _ in 0..10 {
+            engine.step_fixed();
+        }
+        assert!(guy.borrow().is_grounded());
+
+        push_key(&mut engine, Key::Space, Action::Press);
+        engine.step_fixed();
+        push_key(&mut engine, Key::Space, Action::Release);
+
+        let mut peak: f32 = 0.0;
+        let mut airborne_ticks = 0;
+        while !guy.borrow().is_grounded() {
+            peak = peak.max(guy.borrow().get_world_pos().y);
+            airborne_ticks += 1;
+            assert!(airborne_ticks < 120, "never landed");
+
+            engine.step_fixed();
+        }
+
+        // A take-off speed of 2.5 under a gravity of 5 peaks at 0.625 and takes a second
+        assert!((peak - 0.625).abs() < 0.05, "peaked at {}", peak);
+        assert!((airborne_ticks as f32 / 60.0 - 1.0).abs() < 0.05, "airborne for {} ticks", airborne_ticks);
+        assert!(guy.borrow().get_world_pos().y.abs() < 1e-3);
+    }
+
+    /// Moves a `ManualClock` forward every frame, like real time passing between frames.
+    struct AdvanceClock(ManualClock, f32);
+
+    impl Component for AdvanceClock {
+        fn update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) {
+            self.0.advance(self.1);
+        }
+    }
+
+    #[test]
+    fn game_loop_reads_the_engine_clock() {
+        let mut engine = Engine::init_with_backend(Box::new(HeadlessBackend::with_frame_limit(33)));
+        let clock = ManualClock::new();
+        engine.set_clock(Box::new(clock.clone()));
+        engine.set_fixed_tick_rate(64.0);
+        engine.get_root_object().borrow_mut().add_component(AdvanceClock(clock.clone(), 1.0 / 64.0));
+
+        engine.start_game_loop().unwrap();
+
+        // The first frame has nothing to measure against, every one after it is 1/64 of a second
+        assert_eq!(engine.get_time(), 33.0 / 64.0);
+        assert_eq!(engine.get_unscaled_time(), 32.0 / 64.0);
+        assert_eq!(engine.get_fixed_tick_count(), 32);
+    }
+
+    /// Counts fixed ticks and updates, and remembers the delta times it was given.
+    #[derive(Default)]
+    struct TickCounter {
+        updates: u32,
+        fixed_updates: u32,
+        last_delta_time: f32,
+        last_unscaled_delta_time: f32
+    }
+
+    impl Component for TickCounter {
+        fn update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) {
+            self.updates += 1;
+            self.last_delta_time = _info.delta_time;
+            self.last_unscaled_delta_time = _info.unscaled_delta_time;
+        }
+
+        fn fixed_update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) {
+            self.fixed_updates += 1;
+        }
+    }
+
+    fn counting_engine() -> (Engine, components::CompRc<TickCounter>) {
+        let mut engine = Engine::init_headless();
+        engine.set_fixed_tick_rate(64.0);
+
+        let root = engine.get_root_object();
+        root.borrow_mut().add_component(TickCounter::default());
+        let counter = root.borrow().get_component::<TickCounter>().unwrap();
+
+        (engine, counter)
+    }
+
+    #[test]
+    fn accumulator_runs_as_many_fixed_ticks_as_time_calls_for() {
+        let (mut engine, counter) = counting_engine();
+
+        // 2.5 ticks' worth, leaving half a tick over
+        engine.step(2.5 / 64.0);
+        assert_eq!(counter.borrow().fixed_updates, 2);
+        assert_eq!(engine.get_interpolation_alpha(), 0.5);
+
+        // The leftover half plus this half makes one more
+        engine.step(0.5 / 64.0);
+        assert_eq!(counter.borrow().fixed_updates, 3);
+        assert_eq!(engine.get_interpolation_alpha(), 0.0);
+        assert_eq!(counter.borrow().updates, 2);
+    }
+
+    #[test]
+    fn max_fixed_steps_drops_the_rest() {
+        let (mut engine, counter) = counting_engine();
+        engine.set_max_fixed_steps(4);
+
+        // A 10.25 tick hitch only runs 4, and the whole ticks past that are thrown away
+        engine.step(10.25 / 64.0);
+        assert_eq!(counter.borrow().fixed_updates, 4);
+        assert_eq!(engine.get_interpolation_alpha(), 0.25);
+
+        engine.step(1.0 / 64.0);
+        assert_eq!(counter.borrow().fixed_updates, 5);
     }
-}
\ No newline at end of file
+
+    /// Moves its owner one unit right every fixed tick.
+    struct Drift;
+
+    impl Component for Drift {
+        fn fixed_update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) {
+            let pos = _owner.borrow().get_pos();
+            _owner.borrow_mut().set_pos(pos + Vector3::new(1.0, 0.0, 0.0));
+        }
+    }
+
+    fn pushed_sprite_pos(engine: &Engine) -> (f32, f32) {
+        let frame = engine.get_gfx().get_backend::<HeadlessBackend>().unwrap().get_last_frame().unwrap();
+        let (_, sprite) = frame.visible_sprites()[0];
+
+        (sprite.x, sprite.y)
+    }
+
+    #[test]
+    fn sprites_blend_between_fixed_ticks() {
+        let mut engine = Engine::init_headless();
+        engine.set_fixed_tick_rate(64.0);
+
+        // The sprite sits on a child, so only the parent's movement can make it blend
+        let parent = GameObject::create_empty("parent".to_owned(), Some(engine.get_root_object()));
+        parent.borrow_mut().set_pos(Vector3::new(10.0, 0.0, 0.0));
+        parent.borrow_mut().add_component(Drift);
+
+        let child = GameObject::create_empty("child".to_owned(), Some(parent.clone()));
+        child.borrow_mut().set_pos(Vector3::new(0.0, 1.0, 0.0));
+        child.borrow_mut().add_component(components::SpriteComponent::new(1));
+
+        // Alpha 0 before any tick, where it was placed rather than sliding in from the origin
+        engine.step(0.0);
+        assert_eq!(engine.get_interpolation_alpha(), 0.0);
+        assert_eq!(pushed_sprite_pos(&engine), (10.0, 1.0));
+
+        // One tick moved the parent from 10 to 11, and half a tick is left over
+        engine.step(1.5 / 64.0);
+        assert_eq!(engine.get_interpolation_alpha(), 0.5);
+        assert_eq!(pushed_sprite_pos(&engine), (10.5, 1.0));
+
+        // Alpha 1 is the current position. The accumulator never gets there, so ask directly.
+        let end = child.borrow().get_interpolated_world_pos(1.0);
+        assert_eq!((end.x, end.y), (11.0, 1.0));
+
+        // Topping the accumulator up to a whole tick lands exactly on the next tick, at alpha 0
+        engine.step(0.5 / 64.0);
+        assert_eq!(engine.get_interpolation_alpha(), 0.0);
+        assert_eq!(pushed_sprite_pos(&engine), (11.0, 1.0));
+    }
+
+    #[test]
+    fn time_scale_slows_game_time_only() {
+        let (mut engine, counter) = counting_engine();
+        engine.set_time_scale(0.5);
+
+        for _ in 0..4 {
+            engine.step(1.0 / 64.0);
+        }
+
+        assert_eq!(counter.borrow().fixed_updates, 2);
+        assert_eq!(counter.borrow().last_delta_time, 0.5 / 64.0);
+        assert_eq!(counter.borrow().last_unscaled_delta_time, 1.0 / 64.0);
+        assert_eq!(engine.get_game_time(), 2.0 / 64.0);
+        assert_eq!(engine.get_unscaled_time(), 4.0 / 64.0);
+
+        engine.set_time_scale(-1.0);
+        assert_eq!(engine.get_time_scale(), 0.0);
+    }
+
+    #[test]
+    fn pause_and_single_step() {
+        let (mut engine, counter) = counting_engine();
+        engine.step(1.0 / 64.0);
+
+        engine.pause();
+        for _ in 0..3 {
+            engine.step(1.0 / 64.0);
+        }
+
+        assert_eq!(counter.borrow().fixed_updates, 1);
+        assert_eq!(counter.borrow().updates, 1);
+        assert_eq!(engine.get_game_time(), 1.0 / 64.0);
+        assert_eq!(engine.get_unscaled_time(), 4.0 / 64.0);
+
+        // One fixed tick and one update of the same length, on the next frame only
+        engine.queue_single_step();
+        engine.step(1.0 / 64.0);
+        engine.step(1.0 / 64.0);
+
+        assert_eq!(counter.borrow().fixed_updates, 2);
+        assert_eq!(counter.borrow().updates, 2);
+        assert_eq!(counter.borrow().last_delta_time, 1.0 / 64.0);
+        assert_eq!(engine.get_game_time(), 2.0 / 64.0);
+
+        // Steps queued while paused don't carry over past resuming
+        engine.queue_single_step();
+        engine.resume();
+        engine.step(1.0 / 64.0);
+        assert_eq!(counter.borrow().fixed_updates, 3);
+
+        // Only queued while paused
+        engine.queue_single_step();
+        engine.pause();
+        engine.step(1.0 / 64.0);
+        assert_eq!(counter.borrow().fixed_updates, 3);
+    }
+
+    fn push_event(engine: &mut Engine, event: glfw::WindowEvent) {
+        engine.get_gfx_mut().get_backend_mut::<HeadlessBackend>().unwrap().push_event(event);
+    }
+
+    #[test]
+    fn text_input_takes_the_keyboard() {
+        let mut engine = Engine::init_headless();
+        push_key(&mut engine, Key::D, Action::Press);
+        engine.step_fixed();
+        assert!(engine.get_key(Key::D));
+
+        // Keys held before typing started are released, and typed ones only show up as text
+        engine.start_text_input();
+        push_key(&mut engine, Key::H, Action::Press);
+        push_event(&mut engine, glfw::WindowEvent::Char('h'));
+        push_key(&mut engine, Key::H, Action::Release);
+        push_event(&mut engine, glfw::WindowEvent::Key(Key::Backspace, 0, Action::Repeat, Modifiers::Control));
+        push_event(&mut engine, glfw::WindowEvent::Char('é'));
+        push_key(&mut engine, Key::Escape, Action::Press);
+        engine.step_fixed();
+
+        assert_eq!(engine.get_text_input(), "hé");
+        assert_eq!(engine.get_text_input_keys(), &[(Key::H, Modifiers::empty()), (Key::Backspace, Modifiers::Control), (Key::Escape, Modifiers::empty())]);
+        assert!(!engine.get_key(Key::D));
+        assert!(!engine.get_key(Key::H));
+        assert!(!engine.is_just_pressed(Binding::Key(Key::H)));
+        assert!(engine.get_gfx().window_alive(), "Escape closed the window while typing");
+
+        // Cleared every frame
+        engine.step_fixed();
+        assert_eq!(engine.get_text_input(), "");
+        assert!(engine.get_text_input_keys().is_empty());
+
+        // Back to the game, and characters are ignored again
+        engine.stop_text_input();
+        push_key(&mut engine, Key::H, Action::Press);
+        push_event(&mut engine, glfw::WindowEvent::Char('h'));
+        engine.step_fixed();
+
+        assert!(engine.get_key(Key::H));
+        assert_eq!(engine.get_text_input(), "");
+    }
+
+    #[test]
+    fn clipboard_round_trip() {
+        let mut engine = Engine::init_headless();
+        assert_eq!(engine.get_clipboard(), None);
+
+        engine.set_clipboard("player one");
+        assert_eq!(engine.get_clipboard(), Some("player one".to_owned()));
+    }
+
+    #[test]
+    fn replay_reproduces_a_recorded_run() {
+        let mut engine = Engine::init_headless();
+        let guy = spawn_platformer(&engine, 0.0);
+        engine.start_recording();
+
+        // Uneven frame times, so the replay also has to reproduce the accumulator
+        let mut recorded = Vec::new();
+        for frame in 0..90 {
+            match frame {
+                10 => push_key(&mut engine, Key::D, Action::Press),
+                12 => push_key(&mut engine, Key::Space, Action::Press),
+                14 => push_key(&mut engine, Key::Space, Action::Release),
+                50 => push_key(&mut engine, Key::D, Action::Release),
+                _ => {}
+            }
+
+            engine.step([1.0 / 60.0, 1.0 / 45.0, 1.0 / 144.0][frame % 3]);
+            recorded.push(guy.borrow().get_world_pos());
+        }
+
+        // Through the file format, like a replay loaded from disk
+        let replay = Replay::from_bytes(&engine.stop_recording().unwrap().to_bytes()).unwrap();
+        assert_eq!(replay.frames.len(), 90);
+        assert!(recorded[40].x > 0.1 && recorded[20].y > 0.1, "the recording never moved");
+
+        let mut engine = Engine::init_headless();
+        let guy = spawn_platformer(&engine, 0.0);
+        engine.play_replay(replay);
+
+        let mut replayed = Vec::new();
+        while engine.is_replaying() {
+            engine.step(0.0);
+            replayed.push(guy.borrow().get_world_pos());
+        }
+
+        assert_eq!(engine.get_replay_desync(), None);
+        assert_eq!(replayed.len(), recorded.len());
+        for (frame, (a, b)) in recorded.iter().zip(replayed.iter()).enumerate() {
+            assert!(a.x == b.x && a.y == b.y, "frame {}: recorded {}, replayed {}", frame, a, b);
+        }
+    }
+
+    type Log = Rc<RefCell<Vec<String>>>;
+
+    /// Writes every hook it gets to a shared log as "<name>.<hook>".
+    struct LifecycleLog(&'static str, Log);
+
+    impl LifecycleLog {
+        fn log(&self, hook: &str) {
+            self.1.borrow_mut().push(format!("{}.{}", self.0, hook));
+        }
+    }
+
+    impl Component for LifecycleLog {
+        fn awake(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) { self.log("awake"); }
+        fn start(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) { self.log("start"); }
+        fn update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) { self.log("update"); }
+        fn late_update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) { self.log("late_update"); }
+        fn fixed_update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) { self.log("fixed_update"); }
+        fn render(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) { self.log("render"); }
+        fn on_enable(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) { self.log("on_enable"); }
+        fn on_disable(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) { self.log("on_disable"); }
+        fn on_destroy(&mut self, _engine: &mut Engine, _owner: Rc<RefCell<GameObject>>) { self.log("on_destroy"); }
+    }
+
+    type UpdateFn = Box<dyn FnMut(u32, &mut Engine, &Rc<RefCell<GameObject>>)>;
+
+    /// Runs a closure in `update`, with the frame number starting at 0.
+    struct OnUpdate(u32, UpdateFn);
+
+    impl Component for OnUpdate {
+        fn update(&mut self, _info: TickInfo, _owner: Rc<RefCell<GameObject>>) {
+            (self.1)(self.0, _info.engine, &_owner);
+            self.0 += 1;
+        }
+    }
+
+    fn on_update(f: impl FnMut(u32, &mut Engine, &Rc<RefCell<GameObject>>) + 'static) -> OnUpdate {
+        OnUpdate(0, Box::new(f))
+    }
+
+    /// The entries of `log` for `name`, without the object name.
+    fn hooks_of(log: &Log, name: &str) -> Vec<String> {
+        let prefix = format!("{}.", name);
+
+        log.borrow().iter().filter_map(|e| e.strip_prefix(&prefix)).map(str::to_owned).collect()
+    }
+
+    fn count(log: &Log, entry: &str) -> usize {
+        log.borrow().iter().filter(|e| *e == entry).count()
+    }
+
+    #[test]
+    fn destroy_during_update_finishes_the_frame() {
+        let mut engine = Engine::init_headless();
+        let log = Log::default();
+        let root = engine.get_root_object();
+
+        let target = GameObject::create_empty("target".to_owned(), Some(root.clone()));
+        target.borrow_mut().add_component(LifecycleLog("target", log.clone()));
+        target.borrow_mut().add_component(components::SpriteComponent::new(1));
+
+        let child = GameObject::create_empty("child".to_owned(), Some(target.clone()));
+        child.borrow_mut().add_component(LifecycleLog("child", log.clone()));
+
+        // Added to the root first, so it updates before the target
+        let killer = target.clone();
+        root.borrow_mut().add_component(on_update(move |frame, _, _| {
+            if frame == 1 {
+                killer.borrow_mut().destroy();
+                killer.borrow_mut().destroy();
+            }
+        }));
+
+        engine.step_fixed();
+        assert_eq!(engine.get_gfx().get_sprite_count(), 1);
+        log.borrow_mut().clear();
+
+        engine.step_fixed();
+
+        // Still ticks for the rest of the frame, then both objects get torn down together
+        assert_eq!(hooks_of(&log, "target"), vec!["fixed_update", "update", "late_update", "on_disable", "on_destroy"]);
+        assert_eq!(hooks_of(&log, "child"), vec!["fixed_update", "update", "late_update", "on_disable", "on_destroy"]);
+        assert!(target.borrow().is_destroyed() && child.borrow().is_destroyed());
+        assert!(root.borrow().get_children().is_empty());
+        assert_eq!(engine.get_gfx().get_sprite_count(), 0);
+
+        log.borrow_mut().clear();
+        for _ in 0..3 {
+            engine.step_fixed();
+        }
+        assert!(log.borrow().is_empty(), "{:?}", log.borrow());
+    }
+
+    #[test]
+    fn on_destroy_runs_once_per_component() {
+        let mut engine = Engine::init_headless();
+        let log = Log::default();
+
+        let obj = GameObject::create_empty("obj".to_owned(), Some(engine.get_root_object()));
+        obj.borrow_mut().add_component(LifecycleLog("a", log.clone()));
+        obj.borrow_mut().add_component(LifecycleLog("b", log.clone()));
+        engine.step_fixed();
+
+        // Removing a component and destroying its object in the same frame only ends it once
+        obj.borrow_mut().remove_component::<LifecycleLog>();
+        obj.borrow_mut().destroy();
+        engine.step_fixed();
+        engine.step_fixed();
+
+        assert_eq!(count(&log, "a.on_destroy"), 1);
+        assert_eq!(count(&log, "b.on_destroy"), 1);
+        assert_eq!(count(&log, "a.on_disable"), 1);
+    }
+
+    #[test]
+    fn removed_component_releases_its_sprite() {
+        let mut engine = Engine::init_headless();
+        let log = Log::default();
+
+        let obj = GameObject::create_empty("obj".to_owned(), Some(engine.get_root_object()));
+        obj.borrow_mut().add_component(components::SpriteComponent::new(1));
+        obj.borrow_mut().add_component(LifecycleLog("obj", log.clone()));
+        engine.step_fixed();
+        assert_eq!(engine.get_gfx().get_sprite_count(), 1);
+
+        assert!(obj.borrow_mut().remove_component::<components::SpriteComponent>());
+        assert!(!obj.borrow_mut().remove_component::<components::SpriteComponent>());
+        engine.step_fixed();
+
+        assert_eq!(engine.get_gfx().get_sprite_count(), 0);
+        assert!(obj.borrow().get_component::<components::SpriteComponent>().is_none());
+        assert!(!obj.borrow().is_destroyed());
+        assert_eq!(count(&log, "obj.on_destroy"), 0);
+    }
+
+    #[test]
+    fn objects_added_mid_frame_wake_before_their_first_update() {
+        let mut engine = Engine::init_headless();
+        let log = Log::default();
+        let root = engine.get_root_object();
+
+        let spawn_log = log.clone();
+        root.borrow_mut().add_component(on_update(move |frame, _, owner| {
+            match frame {
+                1 => {
+                    let obj = GameObject::create_empty("spawned".to_owned(), Some(owner.clone()));
+                    obj.borrow_mut().add_component(LifecycleLog("spawned", spawn_log.clone()));
+                },
+                2 => owner.borrow_mut().add_component(LifecycleLog("added", spawn_log.clone())),
+                _ => {}
+            }
+        }));
+
+        for _ in 0..4 {
+            engine.step_fixed();
+        }
+
+        // Woken up before the frame is rendered, then ticked from the next frame on
+        assert_eq!(hooks_of(&log, "spawned"), vec![
+            "awake", "on_enable", "start", "render",
+            "fixed_update", "update", "late_update", "render",
+            "fixed_update", "update", "late_update", "render"
+        ]);
+
+        // Also true for a component added to an object that is already ticking
+        assert_eq!(hooks_of(&log, "added"), vec![
+            "awake", "on_enable", "start", "render",
+            "fixed_update", "update", "late_update", "render"
+        ]);
+    }
+
+    #[test]
+    fn hooks_run_in_lifecycle_order() {
+        let mut engine = Engine::init_headless();
+        let log = Log::default();
+
+        let obj = GameObject::create_empty("obj".to_owned(), Some(engine.get_root_object()));
+        obj.borrow_mut().add_component(LifecycleLog("obj", log.clone()));
+
+        engine.step_fixed();
+        engine.step_fixed();
+        obj.borrow_mut().destroy();
+        engine.step_fixed();
+
+        assert_eq!(hooks_of(&log, "obj"), vec![
+            "awake", "on_enable", "start",
+            "fixed_update", "update", "late_update", "render",
+            "fixed_update", "update", "late_update", "render",
+            "fixed_update", "update", "late_update", "on_disable", "on_destroy"
+        ]);
+    }
+
+    #[test]
+    fn disabled_components_wake_but_do_not_start() {
+        let mut engine = Engine::init_headless();
+        let log = Log::default();
+
+        let obj = GameObject::create_empty("obj".to_owned(), Some(engine.get_root_object()));
+        obj.borrow_mut().add_component(LifecycleLog("obj", log.clone()));
+        let comp = obj.borrow().get_component::<LifecycleLog>().unwrap();
+        obj.borrow_mut().set_component_enabled(&comp, false);
+
+        engine.step_fixed();
+        assert_eq!(hooks_of(&log, "obj"), vec!["awake"]);
+
+        // start only once, even after being toggled again
+        obj.borrow_mut().set_component_enabled(&comp, true);
+        engine.step_fixed();
+        obj.borrow_mut().set_component_enabled(&comp, false);
+        engine.step_fixed();
+        obj.borrow_mut().set_component_enabled(&comp, true);
+        engine.step_fixed();
+
+        assert_eq!(hooks_of(&log, "obj"), vec![
+            "awake", "on_enable", "start", "fixed_update", "update", "late_update", "render",
+            "on_disable",
+            "on_enable", "fixed_update", "update", "late_update", "render"
+        ]);
+        assert!(obj.borrow().is_component_enabled(&comp));
+    }
+
+    #[test]
+    fn inactive_subtree_skips_every_tick() {
+        let mut engine = Engine::init_headless();
+        let log = Log::default();
+
+        let parent = GameObject::create_empty("parent".to_owned(), Some(engine.get_root_object()));
+        parent.borrow_mut().add_component(LifecycleLog("parent", log.clone()));
+        let child = GameObject::create_empty("child".to_owned(), Some(parent.clone()));
+        child.borrow_mut().add_component(LifecycleLog("child", log.clone()));
+        let grandchild = GameObject::create_empty("grandchild".to_owned(), Some(child.clone()));
+        grandchild.borrow_mut().add_component(LifecycleLog("grandchild", log.clone()));
+        grandchild.borrow_mut().add_component(components::SpriteComponent::new(1));
+
+        engine.step_fixed();
+        assert_eq!(engine.get_gfx().get_sprite_count(), 1);
+
+        // Deactivating the child takes the grandchild with it, but leaves the parent ticking
+        child.borrow_mut().set_active(false);
+        log.borrow_mut().clear();
+        for _ in 0..3 {
+            engine.step_fixed();
+        }
+
+        assert_eq!(hooks_of(&log, "child"), vec!["on_disable"]);
+        assert_eq!(hooks_of(&log, "grandchild"), vec!["on_disable"]);
+        assert_eq!(count(&log, "parent.update"), 3);
+        assert!(!grandchild.borrow().is_active_in_hierarchy());
+        assert_eq!(engine.get_gfx().get_sprite_count(), 0);
+
+        // The grandchild's own flag was never touched, so it comes back with the child
+        child.borrow_mut().set_active(true);
+        log.borrow_mut().clear();
+        engine.step_fixed();
+
+        assert_eq!(hooks_of(&log, "grandchild"), vec!["on_enable", "fixed_update", "update", "late_update", "render"]);
+        assert_eq!(engine.get_gfx().get_sprite_count(), 1);
+    }
+
+    #[test]
+    fn lifecycle_only_syncs_after_changes() {
+        let mut engine = Engine::init_headless();
+        let root = engine.get_root_object();
+        let child = GameObject::create_empty("child".to_owned(), Some(root.clone()));
+        let grandchild = GameObject::create_empty("grandchild".to_owned(), Some(child.clone()));
+
+        engine.step_fixed();
+        assert!(!root.borrow().take_lifecycle_dirty());
+
+        // Changes deep in the tree flag the root
+        grandchild.borrow_mut().set_active(false);
+        assert!(root.borrow().take_lifecycle_dirty());
+
+        // An object built outside the tree is flagged once it is attached
+        let loose = GameObject::create_empty("loose".to_owned(), None);
+        loose.borrow_mut().add_component(TickCounter::default());
+        assert!(!root.borrow().take_lifecycle_dirty());
+
+        GameObject::set_parent(loose.clone(), Some(grandchild.clone()));
+        assert!(root.borrow().take_lifecycle_dirty());
+    }
+
+    #[test]
+    fn destroyed_objects_are_only_looked_for_after_a_destroy() {
+        let mut engine = Engine::init_headless();
+        let root = engine.get_root_object();
+        let log = Log::default();
+
+        let obj = GameObject::create_empty("obj".to_owned(), Some(root.clone()));
+        obj.borrow_mut().add_component(LifecycleLog("obj", log.clone()));
+        let child = GameObject::create_empty("child".to_owned(), Some(obj.clone()));
+        child.borrow_mut().add_component(LifecycleLog("child", log.clone()));
+        engine.step_fixed();
+        assert!(!root.borrow().take_destroy_pending());
+
+        // Queued deep in the tree, flagged at the top and cleared by the end of the frame
+        child.borrow_mut().remove_component::<LifecycleLog>();
+        engine.step_fixed();
+        assert!(!root.borrow().take_destroy_pending());
+        assert_eq!(count(&log, "child.on_destroy"), 1);
+
+        // Destroyed outside the tree, then attached
+        let loose = GameObject::create_empty("loose".to_owned(), None);
+        loose.borrow_mut().add_component(LifecycleLog("loose", log.clone()));
+        loose.borrow_mut().destroy();
+        assert!(!root.borrow().take_destroy_pending());
+
+        GameObject::set_parent(loose.clone(), Some(obj.clone()));
+        engine.step_fixed();
+        assert!(loose.borrow().is_destroyed());
+
+        obj.borrow_mut().destroy();
+        engine.step_fixed();
+        assert!(obj.borrow().is_destroyed());
+        assert_eq!(count(&log, "obj.on_destroy"), 1);
+        assert!(!root.borrow().take_destroy_pending());
+    }
+}
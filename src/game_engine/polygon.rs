@@ -1,4 +1,4 @@
-use super::Vector2;
+use super::{Vector2, Vector3, matrix::Mat4x4};
 
 #[derive(Clone)]
 pub struct Polygon {
@@ -11,6 +11,17 @@ impl Polygon {
         Polygon { pos: center, verticies }
     }
 
+    /// Returns a copy with every vertex (offset by `pos`) run through `transform`. The result is centered on the origin.
+    pub fn transformed(&self, transform: &Mat4x4) -> Polygon {
+        let verticies = self.verticies.iter().map(|v| {
+            let p = transform.transform_point(Vector3::new(v.x + self.pos.x, v.y + self.pos.y, 0.0));
+
+            Vector2::new(p.x, p.y)
+        }).collect();
+
+        Polygon { pos: Vector2::ZERO, verticies }
+    }
+
     pub fn get_edges(&self) -> Vec<(Vector2, Vector2)> {
         let mut out = Vec::new();
 
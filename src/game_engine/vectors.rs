@@ -30,6 +30,10 @@ impl Vector2 {
         Vector2 { x: self.y, y: -self.x }
     }
 
+    pub fn lerp(&self, o: Vector2, t: f32) -> Vector2 {
+        Vector2 { x: self.x + (o.x - self.x) * t, y: self.y + (o.y - self.y) * t }
+    }
+
     pub const ZERO: Vector2 = Vector2 { x: 0.0, y: 0.0 };
     pub const UNIT: Vector2 = Vector2 { x: 1.0, y: 1.0 };
 }
@@ -149,6 +153,10 @@ impl Vector3 {
         Vector3 { x: self.x / mag, y: self.y / mag, z: self.z / mag }
     }
 
+    pub fn lerp(&self, o: Vector3, t: f32) -> Vector3 {
+        Vector3 { x: self.x + (o.x - self.x) * t, y: self.y + (o.y - self.y) * t, z: self.z + (o.z - self.z) * t }
+    }
+
     pub const ZERO: Vector3 = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
     pub const ONE: Vector3 = Vector3 { x: 1.0, y: 1.0, z: 1.0 };
 }
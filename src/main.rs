@@ -9,10 +9,15 @@ use game_engine::game_object::GameObject;
 
 fn main() {
     println!("Initializing Engine...");
-    let mut engine = Engine::init_engine().unwrap();
+    let config = WindowConfig { title: "Wob".to_owned(), mode: WindowMode::Windowed, resizable: true, ..WindowConfig::default() };
+    let mut engine = Engine::init_with_config(&config).unwrap();
     engine.set_fixed_tick_rate(60.0);
     println!("Engine Initialized.");
 
+    engine.get_gfx_mut().load_aseprite("testgame_spritesheet.json").unwrap();
+    engine.set_input_map(input::InputMap::load("input.cfg").unwrap());
+
+    engine.get_gfx_mut().set_scaling_policy(ScalingPolicy::Letterbox);
     let cam = engine.get_gfx_mut().get_camera_mut();
     cam.size = Vector2::new(0.8, 0.6) * 3.0;
 
@@ -22,7 +27,8 @@ fn main() {
 
     let ground = GameObject::create_empty("ground".to_owned(), Some(root.clone()));
     ground.borrow_mut().set_pos(Vector3{ x: 0.0, y: -0.75, z: 0.0 });
-    let mut ground_sprite = SpriteComponent::new(0, 1);
+    let mut ground_sprite = SpriteComponent::from_frame("ground");
+    ground_sprite.sorting_layer = Some("Background".to_owned());
     ground_sprite.sprite.w = 2.0;
     ground_sprite.sprite.h = 0.5;
     ground.borrow_mut().add_component(ground_sprite);
@@ -31,7 +37,8 @@ fn main() {
 
     let ledge = GameObject::create_empty("ground".to_owned(), Some(root.clone()));
     ledge.borrow_mut().set_pos(Vector3{ x: 0.5, y: -0.125, z: 0.0 });
-    let mut ground_sprite = SpriteComponent::new(2, 1);
+    let mut ground_sprite = SpriteComponent::from_frame("ground");
+    ground_sprite.sorting_layer = Some("Foreground".to_owned());
     ground_sprite.sprite.w = 0.5;
     ground_sprite.sprite.h = 0.75;
     ledge.borrow_mut().add_component(ground_sprite);
@@ -39,15 +46,40 @@ fn main() {
     ledge.borrow_mut().add_component(ground_collider);
 
     let guy = GameObject::create_empty("guy".to_owned(), Some(root.clone()));
-    let mut guy_sprite = SpriteComponent::new(1, 2);
+    let mut guy_sprite = SpriteComponent::from_frame("player_idle_0");
     guy_sprite.sprite.w = 0.5;
     guy_sprite.sprite.h = 1.0;
     guy.borrow_mut().add_component(guy_sprite);
+    let mut guy_animator = Animator::with_atlas_clips(engine.get_gfx());
+    guy_animator.play("player_idle").unwrap();
+    guy.borrow_mut().add_component(guy_animator);
     guy.borrow_mut().add_component(WASDy { speed: 1.0, velocity: 0.0, acc: -5.0 });
     let guy_collider = Collider::new(Polygon::new((0.0, 0.0).into(), vec![(-0.25, 0.5).into(), (0.25, 0.5).into(), (0.25, -0.5).into(), (-0.25, -0.5).into()]));
     guy.borrow_mut().add_component(guy_collider);
 
+    let mut camera_follow = CameraFollow::new(&guy);
+    camera_follow.dead_zone = Vector2::new(0.4, 0.3);
+    camera_follow.look_ahead = Vector2::new(0.3, 0.0);
+    camera_follow.bounds = Some((Vector2::new(-3.0, -1.5), Vector2::new(3.0, 3.0)));
+    root.borrow_mut().add_component(camera_follow);
+
+    // --record <file> saves the input of this run, --replay <file> plays one back
+    let args: Vec<String> = std::env::args().collect();
+    let arg = |name: &str| args.iter().position(|a| a == name).and_then(|i| args.get(i + 1)).cloned();
+
+    if let Some(path) = arg("--replay") {
+        engine.play_replay(input::replay::Replay::load(path).unwrap());
+    }
+
+    if arg("--record").is_some() {
+        engine.start_recording();
+    }
+
     println!("Starting Game Loop...");
     engine.start_game_loop().unwrap();
     println!("Game Loop Exited.");
+
+    if let Some(path) = arg("--record") {
+        engine.stop_recording().unwrap().save(path).unwrap();
+    }
 }